@@ -0,0 +1,592 @@
+//! This module contains an ABI decoder, the counterpart of the encoder in `clarity::abi`.
+//! Decoded values are returned as clarity `AbiToken`s: `bytesN` as `Bytes`, `bytes` as
+//! `UnboundedBytes`, arrays of either kind as `Dynamic` and tuples as `Struct`.
+use crate::jsonrpc::error::Web3Error;
+use clarity::abi::AbiToken as Token;
+use clarity::{Address, Uint256};
+use num256::Int256;
+use num_traits::ToPrimitive;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// A Solidity ABI type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamType {
+    Address,
+    Bool,
+    /// `uintN`, with the size in bits
+    Uint(usize),
+    /// `intN`, with the size in bits
+    Int(usize),
+    /// `bytesN`, with the size in bytes
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<ParamType>),
+    FixedArray(Box<ParamType>, usize),
+    Tuple(Vec<ParamType>),
+}
+
+impl ParamType {
+    /// Returns true if values of this type are encoded out of place, behind an offset
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(inner, _) => inner.is_dynamic(),
+            ParamType::Tuple(types) => types.iter().any(|t| t.is_dynamic()),
+            _ => false,
+        }
+    }
+
+    /// The number of bytes this type occupies in the head of an encoding
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            ParamType::FixedArray(inner, len) => inner.head_size() * len,
+            ParamType::Tuple(types) => types.iter().map(|t| t.head_size()).sum(),
+            _ => 32,
+        }
+    }
+}
+
+impl Display for ParamType {
+    /// Formats the canonical type name used in function and event signatures
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            ParamType::Address => write!(f, "address"),
+            ParamType::Bool => write!(f, "bool"),
+            ParamType::Uint(bits) => write!(f, "uint{bits}"),
+            ParamType::Int(bits) => write!(f, "int{bits}"),
+            ParamType::FixedBytes(len) => write!(f, "bytes{len}"),
+            ParamType::Bytes => write!(f, "bytes"),
+            ParamType::String => write!(f, "string"),
+            ParamType::Array(inner) => write!(f, "{inner}[]"),
+            ParamType::FixedArray(inner, len) => write!(f, "{inner}[{len}]"),
+            ParamType::Tuple(types) => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", types.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for ParamType {
+    type Err = Web3Error;
+
+    /// Parses a type name as it appears in signatures, such as `uint256`, `bytes32[2][]` or
+    /// `(address,uint24)[]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || Web3Error::BadInput(format!("Invalid ABI type {s}"));
+        if let Some(inner) = s.strip_suffix(']') {
+            let (inner, len) = inner.rsplit_once('[').ok_or_else(invalid)?;
+            let inner = Box::new(inner.parse()?);
+            return if len.is_empty() {
+                Ok(ParamType::Array(inner))
+            } else {
+                let len = len.parse().map_err(|_| invalid())?;
+                Ok(ParamType::FixedArray(inner, len))
+            };
+        }
+        if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+            return Ok(ParamType::Tuple(parse_types(inner)?));
+        }
+        let sized = |prefix: &str, default: usize| -> Option<usize> {
+            let size = s.strip_prefix(prefix)?;
+            if size.is_empty() {
+                Some(default)
+            } else {
+                size.parse().ok()
+            }
+        };
+        match s {
+            "address" => Ok(ParamType::Address),
+            "bool" => Ok(ParamType::Bool),
+            "bytes" => Ok(ParamType::Bytes),
+            "string" => Ok(ParamType::String),
+            _ => {
+                if let Some(bits) = sized("uint", 256) {
+                    check_bits(bits).map(ParamType::Uint).ok_or_else(invalid)
+                } else if let Some(bits) = sized("int", 256) {
+                    check_bits(bits).map(ParamType::Int).ok_or_else(invalid)
+                } else if let Some(len) = s.strip_prefix("bytes").and_then(|l| l.parse().ok()) {
+                    match len {
+                        1..=32 => Ok(ParamType::FixedBytes(len)),
+                        _ => Err(invalid()),
+                    }
+                } else {
+                    Err(invalid())
+                }
+            }
+        }
+    }
+}
+
+fn check_bits(bits: usize) -> Option<usize> {
+    if bits > 0 && bits <= 256 && bits % 8 == 0 {
+        Some(bits)
+    } else {
+        None
+    }
+}
+
+/// Parses a comma separated list of types such as `uint256,address[]`
+pub fn parse_types(types: &str) -> Result<Vec<ParamType>, Web3Error> {
+    split_list(types)?.into_iter().map(|t| t.parse()).collect()
+}
+
+/// Splits a comma separated list at the commas outside of parentheses
+pub(crate) fn split_list(list: &str) -> Result<Vec<&str>, Web3Error> {
+    if list.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                return Err(Web3Error::BadInput(format!("Unbalanced types {list}")))
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&list[start..]);
+    Ok(items)
+}
+
+/// Decodes the return data of a call given its return types as written in Solidity, either
+/// a list like `uint256,address[]` or a tuple like `(uint256,address[])`. Each top level type
+/// produces one token.
+///
+/// ```rust,ignore
+/// let amounts = decode_return("uint256[]", &result)?.remove(0).into_array()?;
+/// ```
+pub fn decode_return(types: &str, data: &[u8]) -> Result<Vec<Token>, Web3Error> {
+    let types = types.trim();
+    let types = match parse_types(types) {
+        // a single tuple is the whole list of return values
+        Ok(mut parsed) if parsed.len() == 1 && types.starts_with('(') && types.ends_with(')') => {
+            match parsed.remove(0) {
+                ParamType::Tuple(types) => types,
+                other => vec![other],
+            }
+        }
+        parsed => parsed?,
+    };
+    decode_tokens(&types, data)
+}
+
+/// Decodes the return data of a call returning a single value of type `param_type`
+pub fn decode_single(param_type: &str, data: &[u8]) -> Result<Token, Web3Error> {
+    let param_type: ParamType = param_type.parse()?;
+    Ok(decode_tokens(&[param_type], data)?.remove(0))
+}
+
+/// Conversions of decoded tokens into their values, these fail if the token is of
+/// another kind
+pub trait AbiTokenExt: Sized {
+    fn into_uint(self) -> Result<Uint256, Web3Error>;
+    /// clarity has no signed token, signed integers are decoded to `Token::Uint` holding their
+    /// two's complement word (the same way they are passed to `encode_tokens`) and converted here
+    fn into_int(self) -> Result<Int256, Web3Error>;
+    fn into_address(self) -> Result<Address, Web3Error>;
+    fn into_bool(self) -> Result<bool, Web3Error>;
+    fn into_string(self) -> Result<String, Web3Error>;
+    /// The contents of `bytes` or `bytesN`
+    fn into_bytes(self) -> Result<Vec<u8>, Web3Error>;
+    /// The elements of an array or the members of a tuple
+    fn into_array(self) -> Result<Vec<Token>, Web3Error>;
+}
+
+fn unexpected(expected: &str, token: Token) -> Web3Error {
+    Web3Error::AbiDecodingError(format!("Expected {expected}, got {token:?}"))
+}
+
+impl AbiTokenExt for Token {
+    fn into_uint(self) -> Result<Uint256, Web3Error> {
+        match self {
+            Token::Uint(value) => Ok(value),
+            token => Err(unexpected("uint", token)),
+        }
+    }
+
+    fn into_int(self) -> Result<Int256, Web3Error> {
+        match self {
+            Token::Uint(value) => Ok(Int256::from_be_bytes(&value.to_be_bytes())),
+            token => Err(unexpected("int", token)),
+        }
+    }
+
+    fn into_address(self) -> Result<Address, Web3Error> {
+        match self {
+            Token::Address(value) => Ok(value),
+            token => Err(unexpected("address", token)),
+        }
+    }
+
+    fn into_bool(self) -> Result<bool, Web3Error> {
+        match self {
+            Token::Bool(value) => Ok(value),
+            token => Err(unexpected("bool", token)),
+        }
+    }
+
+    fn into_string(self) -> Result<String, Web3Error> {
+        match self {
+            Token::String(value) => Ok(value),
+            token => Err(unexpected("string", token)),
+        }
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>, Web3Error> {
+        match self {
+            Token::Bytes(value) | Token::UnboundedBytes(value) => Ok(value),
+            token => Err(unexpected("bytes", token)),
+        }
+    }
+
+    fn into_array(self) -> Result<Vec<Token>, Web3Error> {
+        match self {
+            Token::Dynamic(values) | Token::Struct(values) => Ok(values),
+            token => Err(unexpected("array or tuple", token)),
+        }
+    }
+}
+
+/// Types that can be built from a decoded token, used to map event parameters to struct
+/// fields by `#[derive(ContractEvent)]`
+pub trait FromToken: Sized {
+    fn from_token(token: Token) -> Result<Self, Web3Error>;
+}
+
+impl FromToken for Token {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        Ok(token)
+    }
+}
+
+impl FromToken for Uint256 {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_uint()
+    }
+}
+
+impl FromToken for Int256 {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_int()
+    }
+}
+
+impl FromToken for Address {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_address()
+    }
+}
+
+impl FromToken for bool {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_bool()
+    }
+}
+
+impl FromToken for String {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_string()
+    }
+}
+
+impl FromToken for Vec<u8> {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_bytes()
+    }
+}
+
+impl FromToken for [u8; 32] {
+    /// A `bytes32` or the hash of an indexed reference type
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        let bytes = token.into_bytes()?;
+        bytes.as_slice().try_into().map_err(|_| {
+            Web3Error::AbiDecodingError(format!("Expected 32 bytes, got {}", bytes.len()))
+        })
+    }
+}
+
+impl FromToken for Vec<Token> {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token.into_array()
+    }
+}
+
+impl FromToken for Vec<Uint256> {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token
+            .into_array()?
+            .into_iter()
+            .map(|t| t.into_uint())
+            .collect()
+    }
+}
+
+impl FromToken for Vec<Address> {
+    fn from_token(token: Token) -> Result<Self, Web3Error> {
+        token
+            .into_array()?
+            .into_iter()
+            .map(|t| t.into_address())
+            .collect()
+    }
+}
+
+/// Decodes ABI encoded `data`, such as the return value of a call or the data of a log,
+/// as a sequence of values of `types`
+pub fn decode_tokens(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>, Web3Error> {
+    decode_sequence(types, data, 0)
+}
+
+/// Decodes a sequence of values whose head starts at `base`, offsets of dynamic values are
+/// relative to `base`
+fn decode_sequence(types: &[ParamType], data: &[u8], base: usize) -> Result<Vec<Token>, Web3Error> {
+    let mut head = base;
+    let mut tokens = Vec::with_capacity(types.len());
+    for param_type in types {
+        if param_type.is_dynamic() {
+            let offset = read_usize(data, head)?;
+            let position = base.checked_add(offset).ok_or_else(|| overflow(head))?;
+            tokens.push(decode_value(param_type, data, position)?);
+        } else {
+            tokens.push(decode_value(param_type, data, head)?);
+        }
+        head += param_type.head_size();
+    }
+    Ok(tokens)
+}
+
+fn decode_value(param_type: &ParamType, data: &[u8], position: usize) -> Result<Token, Web3Error> {
+    match param_type {
+        ParamType::Address => {
+            let word = read_word(data, position)?;
+            if word[..12].iter().any(|b| *b != 0) {
+                return Err(out_of_range(param_type, position));
+            }
+            Ok(Token::Address(Address::from_slice(&word[12..])?))
+        }
+        ParamType::Bool => match read_word(data, position)? {
+            word if word[..31].iter().all(|b| *b == 0) && word[31] <= 1 => {
+                Ok(Token::Bool(word[31] == 1))
+            }
+            _ => Err(out_of_range(param_type, position)),
+        },
+        ParamType::Uint(bits) => {
+            let word = read_word(data, position)?;
+            if word[..32 - bits / 8].iter().any(|b| *b != 0) {
+                return Err(out_of_range(param_type, position));
+            }
+            Ok(Token::Uint(Uint256::from_be_bytes(word)))
+        }
+        ParamType::Int(bits) => {
+            let word = read_word(data, position)?;
+            let negative = word[0] & 0x80 != 0;
+            let fill = if negative { 0xff } else { 0x00 };
+            let size = bits / 8;
+            // the bytes above the value must be the sign extension of its top bit
+            if word[..32 - size].iter().any(|b| *b != fill)
+                || (word[32 - size] & 0x80 != 0) != negative
+            {
+                return Err(out_of_range(param_type, position));
+            }
+            Ok(Token::Uint(Uint256::from_be_bytes(word)))
+        }
+        ParamType::FixedBytes(len) => {
+            let word = read_word(data, position)?;
+            if word[*len..].iter().any(|b| *b != 0) {
+                return Err(out_of_range(param_type, position));
+            }
+            Ok(Token::Bytes(word[..*len].to_vec()))
+        }
+        ParamType::Bytes => Ok(Token::UnboundedBytes(read_bytes(data, position)?.to_vec())),
+        ParamType::String => match String::from_utf8(read_bytes(data, position)?.to_vec()) {
+            Ok(s) => Ok(Token::String(s)),
+            Err(_) => Err(Web3Error::AbiDecodingError(format!(
+                "String at {position} is not valid utf8"
+            ))),
+        },
+        ParamType::Array(inner) => {
+            let len = read_usize(data, position)?;
+            let start = position + 32;
+            // every element takes at least one word, so a length larger than the remaining
+            // data is invalid and would otherwise allocate a huge vector
+            if len > (data.len() - start) / 32 {
+                return Err(out_of_range(param_type, position));
+            }
+            let types = vec![(**inner).clone(); len];
+            Ok(Token::Dynamic(decode_sequence(&types, data, start)?))
+        }
+        ParamType::FixedArray(inner, len) => {
+            if *len > data.len().saturating_sub(position) / 32 {
+                return Err(out_of_range(param_type, position));
+            }
+            let types = vec![(**inner).clone(); *len];
+            Ok(Token::Dynamic(decode_sequence(&types, data, position)?))
+        }
+        ParamType::Tuple(types) => Ok(Token::Struct(decode_sequence(types, data, position)?)),
+    }
+}
+
+fn read_word(data: &[u8], position: usize) -> Result<&[u8], Web3Error> {
+    position
+        .checked_add(32)
+        .and_then(|end| data.get(position..end))
+        .ok_or_else(|| {
+            Web3Error::AbiDecodingError(format!(
+                "Data of length {} ends before word at {position}",
+                data.len()
+            ))
+        })
+}
+
+/// Reads a word used as an offset or a length, it must point inside `data`
+fn read_usize(data: &[u8], position: usize) -> Result<usize, Web3Error> {
+    Uint256::from_be_bytes(read_word(data, position)?)
+        .to_usize()
+        .filter(|value| *value <= data.len())
+        .ok_or_else(|| overflow(position))
+}
+
+fn read_bytes(data: &[u8], position: usize) -> Result<&[u8], Web3Error> {
+    let len = read_usize(data, position)?;
+    let start = position + 32;
+    data.get(start..start + len).ok_or_else(|| {
+        Web3Error::AbiDecodingError(format!(
+            "Data of length {} ends before {len} bytes at {start}",
+            data.len()
+        ))
+    })
+}
+
+fn overflow(position: usize) -> Web3Error {
+    Web3Error::AbiDecodingError(format!(
+        "Offset or length at {position} points outside of the data"
+    ))
+}
+
+fn out_of_range(param_type: &ParamType, position: usize) -> Web3Error {
+    Web3Error::AbiDecodingError(format!("Invalid {param_type} value at {position}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clarity::abi::encode_tokens;
+    use clarity::utils::hex_str_to_bytes;
+
+    #[test]
+    fn test_parse_param_type() {
+        assert_eq!("uint".parse::<ParamType>().unwrap(), ParamType::Uint(256));
+        assert_eq!(
+            "bytes32[2][]".parse::<ParamType>().unwrap(),
+            ParamType::Array(Box::new(ParamType::FixedArray(
+                Box::new(ParamType::FixedBytes(32)),
+                2
+            )))
+        );
+        assert!("uint7".parse::<ParamType>().is_err());
+        assert!("bytes33".parse::<ParamType>().is_err());
+        assert!("foo".parse::<ParamType>().is_err());
+        let tuple = ParamType::Tuple(vec![ParamType::Address, ParamType::Int(24)]);
+        assert_eq!(tuple.to_string(), "(address,int24)");
+        assert_eq!(
+            "(address, int24)[]".parse::<ParamType>().unwrap(),
+            ParamType::Array(Box::new(tuple.clone()))
+        );
+        assert_eq!(
+            parse_types("uint256,(address,int24),bool").unwrap(),
+            vec![ParamType::Uint(256), tuple, ParamType::Bool]
+        );
+        assert!(parse_types("(uint256").is_err());
+        assert!(parse_types("uint256)").is_err());
+    }
+
+    #[test]
+    fn test_decode_return() {
+        let address: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        // getAmountsOut style uint256[] return
+        let data = encode_tokens(&[Token::Dynamic(vec![
+            Token::Uint(1000u16.into()),
+            Token::Uint(2000u16.into()),
+        ])]);
+        let amounts = decode_single("uint256[]", &data)
+            .unwrap()
+            .into_array()
+            .unwrap();
+        assert_eq!(amounts.len(), 2);
+        assert_eq!(amounts[1].clone().into_uint().unwrap(), 2000u16.into());
+
+        // a list and the equivalent tuple produce the same tokens
+        let data = encode_tokens(&[Token::Uint(5u8.into()), address.into()]);
+        let list = decode_return("uint160, address", &data).unwrap();
+        assert_eq!(
+            format!("{:?}", decode_return("(uint160,address)", &data).unwrap()),
+            format!("{list:?}")
+        );
+        assert_eq!(list[1].clone().into_address().unwrap(), address);
+        assert!(list[1].clone().into_uint().is_err());
+        assert!(decode_return("(uint160,address)", &data[..32]).is_err());
+    }
+
+    #[test]
+    fn test_decode_tokens() {
+        let address: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let tokens = vec![
+            Token::Uint(1000u32.into()),
+            Token::Dynamic(vec![address.into(), address.into()]),
+            Token::String("Dai Stablecoin".to_string()),
+            Token::UnboundedBytes(vec![1, 2, 3]),
+            Token::Bool(true),
+        ];
+        let types = [
+            ParamType::Uint(256),
+            ParamType::Array(Box::new(ParamType::Address)),
+            ParamType::String,
+            ParamType::Bytes,
+            ParamType::Bool,
+        ];
+        let data = encode_tokens(&tokens);
+        // clarity tokens are not comparable, their debug output is
+        assert_eq!(
+            format!("{:?}", decode_tokens(&types, &data).unwrap()),
+            format!("{tokens:?}")
+        );
+        assert!(decode_tokens(&types, &data[..data.len() - 32]).is_err());
+
+        // int24 -1 followed by a uint8 that does not fit
+        let data = hex_str_to_bytes(
+            "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\
+            0000000000000000000000000000000000000000000000000000000000000100",
+        )
+        .unwrap();
+        let value = decode_tokens(&[ParamType::Int(24)], &data)
+            .unwrap()
+            .remove(0);
+        assert_eq!(value.into_int().unwrap(), Int256::from(-1i64));
+        assert!(decode_tokens(&[ParamType::Int(24), ParamType::Uint(8)], &data).is_err());
+
+        // an array length pointing far past the end of the data
+        let data = hex_str_to_bytes(
+            "0x0000000000000000000000000000000000000000000000000000000000000020\
+            00000000000000000000000000000000000000000000000000000000ffffffff",
+        )
+        .unwrap();
+        assert!(decode_tokens(&[ParamType::Array(Box::new(ParamType::Bool))], &data).is_err());
+    }
+}
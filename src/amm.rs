@@ -1,10 +1,13 @@
 // Performs interactions with AMMs (Automated Market Makers) on ethereum
+use crate::abi::{decode_single, AbiTokenExt};
+use crate::multicall::{Call3, CallResult};
+use crate::signer::Signer;
 use crate::{client::Web3, jsonrpc::error::Web3Error, types::SendTxOption};
 use clarity::utils::display_uint256_as_address;
 use clarity::{
     abi::{encode_call, AbiToken as Token},
-    constants::{tt160m1, tt24m1},
-    Address, PrivateKey, Uint256,
+    constants::{tt160m1, tt24m1, zero_address},
+    Address, Uint256,
 };
 use num_traits::Inv;
 use std::time::Duration;
@@ -95,18 +98,11 @@ impl Web3 {
             .await?;
         trace!("getAmountsOut response is {:02X?}", amounts_bytes);
 
-        // Convert Some(Vec<u8>) -> Some(Vec<Uint256>)
-        if amounts_bytes.len() % 32 != 0 || amounts_bytes.len() <= 64 {
-            return Err(Web3Error::BadResponse(format!(
-                "Unexpected response byte length: {}",
-                amounts_bytes.len()
-            )));
-        }
-        // Throw away the first two values (type code and response length), then parse Uint256's from each 32 byte chunk
-        let amounts = amounts_bytes[64..]
-            .chunks(32)
-            .map(Uint256::from_be_bytes)
-            .collect::<Vec<Uint256>>();
+        let amounts = decode_single("uint256[]", &amounts_bytes)?
+            .into_array()?
+            .into_iter()
+            .map(|amount| amount.into_uint())
+            .collect::<Result<Vec<Uint256>, Web3Error>>()?;
         debug!("Got amounts from response: {:?}", amounts);
         // The last amount is the output
         if amounts.len() != 2 {
@@ -353,14 +349,7 @@ impl Web3 {
 
         let decoded_sqrt_price = decode_uniswap_v3_sqrt_price(sqrt_price_limit_x96);
 
-        let amount_out = Uint256::from_be_bytes(match result.get(0..32) {
-            Some(val) => val,
-            None => {
-                return Err(Web3Error::ContractCallError(
-                    "Bad response from swap price".to_string(),
-                ))
-            }
-        });
+        let amount_out = decode_single("uint256", &result)?.into_uint()?;
 
         if amount_out < amount_out_min {
             let amount_in_pretty = amount.to_string().parse::<f64>().unwrap() / 10f64.powi(18);
@@ -383,7 +372,7 @@ impl Web3 {
     /// This method calls exactInputSingle on the Uniswap v3 Router
     ///
     /// # Arguments
-    /// * `eth_private_key` - The private key of the holder of `token_in` who will receive `token_out`
+    /// * `signer` - The signer for the holder of `token_in` who will receive `token_out`
     /// * `token_in` - The address of the ERC20 token to exchange for `token_out`
     /// * `token_out` - The address of the ERC20 token to receive
     /// * `fee_uint24` - Optional fee level of the `token_in`<->`token_out` pool to query - limited to uint24 in size.
@@ -398,7 +387,7 @@ impl Web3 {
     #[allow(clippy::too_many_arguments)]
     pub async fn swap_uniswap_v3_with_slippage(
         &self,
-        eth_private_key: PrivateKey,        // The address swapping tokens
+        signer: impl Signer,                // The address swapping tokens
         token_in: Address,                  // The token held
         token_out: Address,                 // The desired token
         fee_uint24: Option<Uint256>,        // Actually a uint24 on the callee side
@@ -411,7 +400,7 @@ impl Web3 {
     ) -> Result<Uint256, Web3Error> {
         let max_slippage = max_slippage.unwrap_or(0.005f64);
         let fee = fee_uint24.unwrap_or_else(|| 3000u16.into());
-        let caller_address = eth_private_key.to_address();
+        let caller_address = signer.address();
         let sqrt_price_limit = self
             .get_v3_slippage_sqrt_price(
                 caller_address,
@@ -433,7 +422,7 @@ impl Web3 {
             .await?;
 
         self.swap_uniswap_v3(
-            eth_private_key,
+            signer,
             token_in,
             token_out,
             Some(fee),
@@ -452,7 +441,7 @@ impl Web3 {
     /// This method calls exactInputSingle on the Uniswap v3 Router
     ///
     /// # Arguments
-    /// * `eth_private_key` - The private key of the holder of `token_in` who will receive `token_out`
+    /// * `signer` - The signer for the holder of `token_in` who will receive `token_out`
     /// * `token_in` - The address of the ERC20 token to exchange for `token_out`
     /// * `token_out` - The address of the ERC20 token to receive
     /// * `fee_uint24` - Optional fee level of the `token_in`<->`token_out` pool to query - limited to uint24 in size.
@@ -475,7 +464,7 @@ impl Web3 {
     /// use web30::client::Web3;
     /// let web3 = Web3::new("http://localhost:8545", Duration::from_secs(5));
     /// let result = web3.swap_uniswap_v3(
-    ///     "0x1111111111111111111111111111111111111111111111111111111111111111".parse().unwrap(),
+    ///     "0x1111111111111111111111111111111111111111111111111111111111111111".parse::<PrivateKey>().unwrap(),
     ///     *WETH_CONTRACT_ADDRESS,
     ///     *DAI_CONTRACT_ADDRESS,
     ///     Some(500u16.into()),
@@ -491,7 +480,7 @@ impl Web3 {
     #[allow(clippy::too_many_arguments)]
     pub async fn swap_uniswap_v3(
         &self,
-        eth_private_key: PrivateKey,     // The address swapping tokens
+        signer: impl Signer,             // The address swapping tokens
         token_in: Address,               // The token held
         token_out: Address,              // The desired token
         fee_uint24: Option<Uint256>,     // Actually a uint24 on the callee side
@@ -518,7 +507,7 @@ impl Web3 {
             ));
         }
 
-        let eth_address = eth_private_key.to_address();
+        let eth_address = signer.address();
         let router = uniswap_router.unwrap_or(*UNISWAP_V3_ROUTER_ADDRESS);
         let deadline = match deadline {
             // Default to latest block + 10 minutes
@@ -577,21 +566,10 @@ impl Web3 {
             .await?;
         if !approved {
             debug!("token_in being approved");
-            // the nonce we will be using, if there's no timeout we must hack the nonce
-            // of the following swap to queue properly
-            let nonce = self.eth_get_transaction_count(eth_address).await?;
+            // if there's no timeout the nonce manager queues the following swap after the approval
             let _token_in_approval = self
-                .approve_erc20_transfers(
-                    token_in,
-                    eth_private_key,
-                    router,
-                    wait_timeout,
-                    options.clone(),
-                )
+                .approve_erc20_transfers(token_in, &signer, router, wait_timeout, options.clone())
                 .await?;
-            if wait_timeout.is_none() {
-                options.push(SendTxOption::Nonce(nonce + 1u8.into()));
-            }
         }
 
         trace!("tokens  {:?}", tokens);
@@ -602,7 +580,7 @@ impl Web3 {
                 &tokens,
                 0u32.into(),
                 eth_address,
-                eth_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -627,7 +605,7 @@ impl Web3 {
     /// and calling swap_uniswap_with_slippage() instead.
     ///
     /// # Arguments
-    /// * `eth_private_key` - The private key of the holder of `token_in` who will receive `token_out`
+    /// * `signer` - The signer for the holder of `token_in` who will receive `token_out`
     /// * `token_out` - The address of the ERC20 token to receive
     /// * `fee_uint24` - Optional fee level of the `token_in`<->`token_out` pool to query - limited to uint24 in size.
     ///    Defaults to the medium pool fee of 0.3%
@@ -641,7 +619,7 @@ impl Web3 {
     #[allow(clippy::too_many_arguments)]
     pub async fn swap_uniswap_v3_eth_in_with_slippage(
         &self,
-        eth_private_key: PrivateKey,        // The address swapping tokens
+        signer: impl Signer,                // The address swapping tokens
         token_out: Address,                 // The desired token
         fee_uint24: Option<Uint256>,        // Actually a uint24 on the callee side
         amount: Uint256,                    // The amount of tokens offered up
@@ -653,7 +631,7 @@ impl Web3 {
     ) -> Result<Uint256, Web3Error> {
         let max_slippage = max_slippage.unwrap_or(0.005f64);
         let fee = fee_uint24.unwrap_or_else(|| 3000u16.into());
-        let caller_address = eth_private_key.to_address();
+        let caller_address = signer.address();
         let sqrt_price_limit = self
             .get_v3_slippage_sqrt_price(
                 caller_address,
@@ -675,7 +653,7 @@ impl Web3 {
             .await?;
 
         self.swap_uniswap_v3_eth_in(
-            eth_private_key,
+            signer,
             token_out,
             Some(fee),
             amount,
@@ -698,7 +676,7 @@ impl Web3 {
     /// and calling swap_uniswap() instead.
     ///
     /// # Arguments
-    /// * `eth_private_key` - The private key of the holder of `token_in` who will receive `token_out`
+    /// * `signer` - The signer for the holder of `token_in` who will receive `token_out`
     /// * `token_out` - The address of the ERC20 token to receive
     /// * `fee_uint24` - Optional fee level of the `token_in`<->`token_out` pool to query - limited to uint24 in size.
     ///    Defaults to the medium pool fee of 0.3%
@@ -721,7 +699,7 @@ impl Web3 {
     /// use web30::client::Web3;
     /// let web3 = Web3::new("http://localhost:8545", Duration::from_secs(5));
     /// let result = web3.swap_uniswap_v3_eth_in(
-    ///     "0x1111111111111111111111111111111111111111111111111111111111111111".parse().unwrap(),
+    ///     "0x1111111111111111111111111111111111111111111111111111111111111111".parse::<PrivateKey>().unwrap(),
     ///     *DAI_CONTRACT_ADDRESS,
     ///     Some(500u16.into()),
     ///     1000000000000000000u128.into(), // 1 ETH
@@ -736,7 +714,7 @@ impl Web3 {
     #[allow(clippy::too_many_arguments)]
     pub async fn swap_uniswap_v3_eth_in(
         &self,
-        eth_private_key: PrivateKey,     // the address swapping tokens
+        signer: impl Signer,             // the address swapping tokens
         token_out: Address,              // the desired token
         fee_uint24: Option<Uint256>,     // actually a uint24 on the callee side
         amount: Uint256,                 // the amount of tokens offered up
@@ -763,7 +741,7 @@ impl Web3 {
             ));
         }
 
-        let eth_address = eth_private_key.to_address();
+        let eth_address = signer.address();
         let router = uniswap_router.unwrap_or(*UNISWAP_V3_ROUTER_ADDRESS);
         let deadline = match deadline {
             // Default to latest block + 10 minutes
@@ -825,7 +803,7 @@ impl Web3 {
             &tokens,
                 amount,
                 eth_address,
-                eth_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -858,14 +836,12 @@ impl Web3 {
             .simulate_transaction(factory, payload, caller_address, None)
             .await?;
         trace!("pool result is {:X?}", pool_result);
-        let zero_result = vec![0; 32];
-        let result_len = pool_result.len();
-        if pool_result == zero_result || result_len < 20 {
+        let pool = decode_single("address", &pool_result)?.into_address()?;
+        if pool == zero_address() {
             return Err(Web3Error::BadResponse("No such Uniswap pool".to_string()));
         }
-        let pool_bytes: &[u8] = &pool_result[result_len - 20..result_len];
 
-        Ok(Address::from_slice(pool_bytes).expect("Received invalid pool address from Uniswap"))
+        Ok(pool)
     }
 
     /// Identifies token0 and token1 in a Uniswap v3 pool, which all stored data is based off of
@@ -883,6 +859,35 @@ impl Web3 {
         Ok((token0, token1))
     }
 
+    /// Identifies token0 and token1 of many Uniswap v3 pools with a single Multicall3 call,
+    /// returns the tokens of each pool in order, None if the pool could not be queried.
+    /// The default Multicall3 deployment is used if `multicall` is None.
+    pub async fn get_uniswap_v3_pools_tokens(
+        &self,
+        caller_address: Address, // an arbitrary ethereum address with any amount of ether
+        pool_addrs: &[Address],  // the ethereum addresses of the Uniswap v3 pools
+        multicall: Option<Address>,
+    ) -> Result<Vec<Option<(Address, Address)>>, Web3Error> {
+        let token0 = encode_call("token0()", &[])?;
+        let token1 = encode_call("token1()", &[])?;
+        let mut calls = Vec::with_capacity(pool_addrs.len() * 2);
+        for pool_addr in pool_addrs {
+            calls.push(Call3::new(*pool_addr, token0.clone()));
+            calls.push(Call3::new(*pool_addr, token1.clone()));
+        }
+        let results = self.multicall(&calls, caller_address, multicall).await?;
+        let decode_token = |result: &CallResult| {
+            decode_single("address", result.data()?)
+                .ok()?
+                .into_address()
+                .ok()
+        };
+        Ok(results
+            .chunks(2)
+            .map(|pool| Some((decode_token(&pool[0])?, decode_token(&pool[1])?)))
+            .collect())
+    }
+
     /// Returns either token0 or token1 from a Uniswap v3 pool, depending on input
     pub async fn get_uniswap_v3_pool_token(
         &self,
@@ -896,14 +901,7 @@ impl Web3 {
             .simulate_transaction(pool_addr, payload, caller_address, None)
             .await?;
         trace!("token_result: {:X?}", token_result);
-        let result_len = token_result.len();
-        if result_len < 20 {
-            return Err(Web3Error::BadResponse("Invalid token result".to_string()));
-        }
-        let token_bytes: &[u8] = &token_result[result_len - 20..result_len];
-
-        let token = Address::from_slice(token_bytes)?;
-        Ok(token)
+        decode_single("address", &token_result)?.into_address()
     }
 
     /// Fetches the "slot0" data from a Uniswap pool, which contains the following binary encoded data:
@@ -940,12 +938,8 @@ impl Web3 {
         let slot0_result = self
             .get_uniswap_v3_pool_slot0(caller_address, pool_address)
             .await?;
-        if slot0_result.is_empty() || slot0_result.len() < 32 {
-            return Err(Web3Error::BadResponse("Zero slot0 response".to_string()));
-        }
-
-        // we only want the first value: sqrtPriceX96, a uint160 which occupies 20 bytes but is put at the right of a 32 byte buffer
-        let sqrt_price = Uint256::from_be_bytes(&slot0_result[32 - 20..32]);
+        // we only want the first value: sqrtPriceX96, forks differ in the types of the others
+        let sqrt_price = decode_single("uint160", &slot0_result)?.into_uint()?;
 
         trace!("parsed sqrt_price {:X?}", sqrt_price);
         Ok(sqrt_price)
@@ -1425,6 +1419,7 @@ fn swap_hardhat_test() {
     // this key is the private key for the public key defined in tests/assets/ETHGenesis.json
     // where the full node / miner sends its rewards. Therefore it's always going
     // to have a lot of ETH to pay for things like contract deployments
+    use clarity::PrivateKey;
     let miner_private_key: PrivateKey =
         "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
             .parse()
@@ -1548,6 +1543,7 @@ fn swap_hardhat_eth_in_test() {
     // this key is the private key for the public key defined in tests/assets/ETHGenesis.json
     // where the full node / miner sends its rewards. Therefore it's always going
     // to have a lot of ETH to pay for things like contract deployments
+    use clarity::PrivateKey;
     let miner_private_key: PrivateKey =
         "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
             .parse()
@@ -0,0 +1,283 @@
+//! This file contains a block tracker which follows the chain head and keeps a window of recent
+//! headers linked by parent hash. Block height alone can not tell if a block was replaced, so
+//! anything that depends on inclusion (confirmations, events) should check it against the tracker.
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{ConciseBlock, Log};
+use clarity::Uint256;
+use num_traits::ToPrimitive;
+use std::collections::VecDeque;
+
+/// A change to the canonical chain observed by the BlockTracker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockEvent {
+    /// A block extending the previous head
+    NewBlock(ConciseBlock),
+    /// The chain switched to a different branch, `dropped` contains the formerly canonical
+    /// blocks and `added` the blocks of the new branch, both ordered oldest first.
+    /// `depth` is the number of blocks that where dropped
+    Reorg {
+        depth: usize,
+        dropped: Vec<ConciseBlock>,
+        added: Vec<ConciseBlock>,
+    },
+}
+
+/// A struct for tracking the most recent block headers and detecting reorgs
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BlockTracker {
+    /// canonical headers ordered oldest first, each is the parent of the next
+    headers: VecDeque<ConciseBlock>,
+    size: usize,
+}
+
+impl BlockTracker {
+    /// create a new block tracker storing at most size headers, this is
+    /// also the deepest reorg that can be described with exact dropped blocks
+    pub fn new(size: usize) -> Self {
+        BlockTracker {
+            headers: VecDeque::new(),
+            size: size.max(1),
+        }
+    }
+
+    /// Returns the current number of stored headers
+    pub fn get_current_size(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Returns a copy of the stored headers, oldest first
+    pub fn get_headers(&self) -> VecDeque<ConciseBlock> {
+        self.headers.clone()
+    }
+
+    /// Gets the current canonical head
+    pub fn head(&self) -> Option<&ConciseBlock> {
+        self.headers.back()
+    }
+
+    /// Gets the canonical header at a given height if it is inside the tracked window
+    pub fn get_by_number(&self, number: Uint256) -> Option<&ConciseBlock> {
+        let first = self.headers.front()?;
+        if number < first.number {
+            return None;
+        }
+        let index = (number - first.number).to_usize()?;
+        self.headers.get(index)
+    }
+
+    /// Returns true if the block hash is part of the tracked canonical chain
+    pub fn contains(&self, block_hash: Uint256) -> bool {
+        self.headers.iter().any(|b| b.hash == block_hash)
+    }
+
+    /// Checks if a block is canonical, returns `None` if the height is outside the tracked
+    /// window and the question can not be answered locally
+    pub fn is_canonical(&self, number: Uint256, block_hash: Uint256) -> Option<bool> {
+        self.get_by_number(number).map(|b| b.hash == block_hash)
+    }
+
+    /// Checks if a log is from a canonical block, returns `None` for pending logs or
+    /// logs outside the tracked window
+    pub fn is_log_canonical(&self, log: &Log) -> Option<bool> {
+        match (log.block_number, &log.block_hash) {
+            (Some(number), Some(hash)) => self.is_canonical(number, Uint256::from_be_bytes(hash)),
+            _ => None,
+        }
+    }
+
+    /// Returns how many blocks have been built on top of the given block, or `None` if
+    /// the block is not known to be canonical
+    pub fn confirmations(&self, number: Uint256, block_hash: Uint256) -> Option<Uint256> {
+        match self.is_canonical(number, block_hash) {
+            Some(true) => self.head().map(|head| head.number - number),
+            _ => None,
+        }
+    }
+
+    /// Applies a branch of headers ordered oldest first, each linked to the one before by
+    /// parent hash. If the first header not already tracked is a child of a tracked header the
+    /// tracked blocks after that header are dropped. If it can not be linked to anything we know
+    /// about only the tracked blocks at its height or above are dropped, the older ones are
+    /// forgotten since they can no longer be proven canonical.
+    pub fn apply(&mut self, branch: Vec<ConciseBlock>) -> Result<Vec<BlockEvent>, Web3Error> {
+        for pair in branch.windows(2) {
+            if pair[1].parent_hash != pair[0].hash {
+                return Err(Web3Error::BadResponse(format!(
+                    "Block {:#066x} is not a child of {:#066x}",
+                    pair[1].hash, pair[0].hash
+                )));
+            }
+        }
+
+        // skip anything we already have, such as the ancestor the branch was fetched down to
+        let added: Vec<ConciseBlock> = branch
+            .into_iter()
+            .skip_while(|b| self.contains(b.hash))
+            .collect();
+        let first = match added.first() {
+            Some(first) => first,
+            None => return Ok(Vec::new()),
+        };
+
+        let dropped: Vec<ConciseBlock> = match self
+            .headers
+            .iter()
+            .position(|b| b.hash == first.parent_hash)
+        {
+            Some(ancestor) => self.headers.drain(ancestor + 1..).collect(),
+            None => {
+                let replaced = self
+                    .headers
+                    .iter()
+                    .position(|b| b.number >= first.number)
+                    .unwrap_or(self.headers.len());
+                let dropped = self.headers.drain(replaced..).collect();
+                self.headers.clear();
+                dropped
+            }
+        };
+
+        self.headers.extend(added.iter().cloned());
+        while self.headers.len() > self.size {
+            self.headers.pop_front();
+        }
+
+        if dropped.is_empty() {
+            Ok(added.into_iter().map(BlockEvent::NewBlock).collect())
+        } else {
+            Ok(vec![BlockEvent::Reorg {
+                depth: dropped.len(),
+                dropped,
+                added,
+            }])
+        }
+    }
+
+    /// Fetches the latest block and walks back by parent hash until it links up with the
+    /// tracked chain, returns the resulting events in order. The walk stops at the oldest tracked
+    /// height, if the branch still does not link there every tracked block was replaced. Each
+    /// block produced since the last update is fetched, so call this regularly.
+    pub async fn update(&mut self, web30: &Web3) -> Result<Vec<BlockEvent>, Web3Error> {
+        let latest = web30.eth_get_latest_block().await?;
+
+        let mut branch = vec![latest];
+        loop {
+            let oldest = branch.last().unwrap();
+            if self.contains(oldest.hash) || self.contains(oldest.parent_hash) {
+                break;
+            }
+            match self.headers.front() {
+                Some(first) if oldest.number > first.number => {}
+                _ => break,
+            }
+            let parent_hash = oldest.parent_hash;
+            match web30.eth_get_concise_block_by_hash(parent_hash).await? {
+                Some(parent) => branch.push(parent),
+                None => {
+                    return Err(Web3Error::BadResponse(format!(
+                        "Node does not know parent block {parent_hash:#066x}"
+                    )))
+                }
+            }
+        }
+        branch.reverse();
+
+        self.apply(branch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    lazy_static! {
+        static ref TEMPLATE: ConciseBlock =
+            serde_json::from_str(include_str!("../test_files/concise_geth_eth_block.json"))
+                .unwrap();
+    }
+
+    /// Creates a block at `number`, hashes are arbitrary ids so branches can be built by hand
+    fn block(number: u8, hash: u8, parent_hash: u8) -> ConciseBlock {
+        let mut block = TEMPLATE.clone();
+        block.number = number.into();
+        block.hash = hash.into();
+        block.parent_hash = parent_hash.into();
+        block
+    }
+
+    #[test]
+    fn test_track_new_blocks() {
+        let mut tracker = BlockTracker::new(3);
+        let events = tracker
+            .apply(vec![block(1, 1, 0), block(2, 2, 1), block(3, 3, 2)])
+            .unwrap();
+        assert_eq!(events.len(), 3);
+
+        let events = tracker.apply(vec![block(4, 4, 3)]).unwrap();
+        assert_eq!(events, vec![BlockEvent::NewBlock(block(4, 4, 3))]);
+        assert_eq!(tracker.get_current_size(), 3);
+        assert_eq!(tracker.head().unwrap().hash, 4u8.into());
+        assert!(tracker.get_by_number(1u8.into()).is_none());
+
+        // a lagging node returning an already known block is not a reorg
+        assert!(tracker.apply(vec![block(3, 3, 2)]).unwrap().is_empty());
+
+        assert_eq!(tracker.is_canonical(3u8.into(), 3u8.into()), Some(true));
+        assert_eq!(tracker.is_canonical(3u8.into(), 30u8.into()), Some(false));
+        assert_eq!(tracker.is_canonical(9u8.into(), 9u8.into()), None);
+        assert_eq!(
+            tracker.confirmations(2u8.into(), 2u8.into()),
+            Some(2u8.into())
+        );
+
+        // branches must be linked by parent hash
+        assert!(tracker.apply(vec![block(5, 5, 4), block(6, 6, 4)]).is_err());
+    }
+
+    #[test]
+    fn test_detect_reorg() {
+        let mut tracker = BlockTracker::new(10);
+        tracker
+            .apply((1..=5).map(|n| block(n, n, n - 1)).collect())
+            .unwrap();
+
+        // blocks 4 and 5 are replaced by a longer branch forking from 3
+        let branch = vec![
+            block(3, 3, 2),
+            block(4, 40, 3),
+            block(5, 50, 40),
+            block(6, 60, 50),
+        ];
+        let events = tracker.apply(branch).unwrap();
+        assert_eq!(
+            events,
+            vec![BlockEvent::Reorg {
+                depth: 2,
+                dropped: vec![block(4, 4, 3), block(5, 5, 4)],
+                added: vec![block(4, 40, 3), block(5, 50, 40), block(6, 60, 50)],
+            }]
+        );
+        assert_eq!(tracker.is_canonical(4u8.into(), 4u8.into()), Some(false));
+        assert_eq!(
+            tracker.confirmations(4u8.into(), 40u8.into()),
+            Some(2u8.into())
+        );
+
+        // a branch that does not link drops only the tracked blocks at its height or above
+        let events = tracker.apply(vec![block(5, 51, 41)]).unwrap();
+        match &events[0] {
+            BlockEvent::Reorg { depth, dropped, .. } => {
+                assert_eq!(*depth, 2);
+                assert_eq!(dropped, &vec![block(5, 50, 40), block(6, 60, 50)]);
+            }
+            _ => panic!("Expected reorg"),
+        }
+        assert_eq!(tracker.get_current_size(), 1);
+
+        // a node that moved far ahead is not a reorg, nothing tracked was replaced
+        let events = tracker.apply(vec![block(20, 200, 199)]).unwrap();
+        assert_eq!(events, vec![BlockEvent::NewBlock(block(20, 200, 199))]);
+        assert_eq!(tracker.get_current_size(), 1);
+    }
+}
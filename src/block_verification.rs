@@ -0,0 +1,633 @@
+//! This module contains local integrity checks for blocks fetched from untrusted providers.
+//! The block hash is recomputed from the RLP encoded header and the transactions root is
+//! recomputed from the full transaction list, neither requires trusting the node that served them.
+//!
+//! Only proof of work and proof of stake style headers are supported, proof of authority chains
+//! such as xDai seal their headers with extra fields that are not part of the JSONRPC response.
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::rlp::{encode_address, encode_bytes, encode_hash, encode_list, encode_uint};
+use crate::types::{Block, ConciseBlock, Data, TransactionResponse};
+use clarity::{Address, Uint256};
+
+/// The consensus fields of a header, borrowed from either a `Block` or `ConciseBlock`
+struct HeaderFields<'a> {
+    parent_hash: Uint256,
+    sha3_uncles: Uint256,
+    miner: &'a Address,
+    state_root: Uint256,
+    transactions_root: Uint256,
+    receipts_root: Uint256,
+    logs_bloom: &'a Data,
+    difficulty: Uint256,
+    number: Uint256,
+    gas_limit: Uint256,
+    gas_used: Uint256,
+    timestamp: Uint256,
+    extra_data: &'a Data,
+    mix_hash: Option<Uint256>,
+    nonce: Option<Uint256>,
+    base_fee_per_gas: Option<Uint256>,
+    withdrawals_root: Option<Uint256>,
+    blob_gas_used: Option<Uint256>,
+    excess_blob_gas: Option<Uint256>,
+    parent_beacon_block_root: Option<Uint256>,
+    requests_hash: Option<Uint256>,
+}
+
+impl HeaderFields<'_> {
+    /// RLP encodes the header, the fork layout (pre-London, London, Shanghai, Cancun, Prague) is
+    /// selected by which of the optional fields the node returned
+    fn encode(&self) -> Result<Vec<u8>, Web3Error> {
+        let (mix_hash, nonce) = match (self.mix_hash, self.nonce) {
+            (Some(mix_hash), Some(nonce)) => (mix_hash, nonce),
+            _ => {
+                return Err(Web3Error::BadResponse(
+                    "Block header is missing mixHash or nonce, can not compute hash".to_string(),
+                ))
+            }
+        };
+
+        let mut items = vec![
+            encode_hash(self.parent_hash),
+            encode_hash(self.sha3_uncles),
+            encode_address(self.miner),
+            encode_hash(self.state_root),
+            encode_hash(self.transactions_root),
+            encode_hash(self.receipts_root),
+            encode_bytes(self.logs_bloom),
+            encode_uint(self.difficulty),
+            encode_uint(self.number),
+            encode_uint(self.gas_limit),
+            encode_uint(self.gas_used),
+            encode_uint(self.timestamp),
+            encode_bytes(self.extra_data),
+            encode_hash(mix_hash),
+            // the nonce is a fixed 8 byte value
+            encode_bytes(&nonce.to_be_bytes()[24..]),
+        ];
+
+        // each fork appends fields to the end of the header, so a field can only
+        // be present if every field introduced before it is also present
+        let fork_fields = [
+            self.base_fee_per_gas.map(encode_uint),
+            self.withdrawals_root.map(encode_hash),
+            self.blob_gas_used.map(encode_uint),
+            self.excess_blob_gas.map(encode_uint),
+            self.parent_beacon_block_root.map(encode_hash),
+            self.requests_hash.map(encode_hash),
+        ];
+        let present = fork_fields.iter().take_while(|f| f.is_some()).count();
+        if fork_fields[present..].iter().any(Option::is_some) {
+            return Err(Web3Error::BadResponse(
+                "Block header contains fields from a fork without the fields of the forks before it"
+                    .to_string(),
+            ));
+        }
+        items.extend(fork_fields.into_iter().flatten());
+
+        Ok(encode_list(&items))
+    }
+}
+
+impl Block {
+    fn header_fields(&self) -> HeaderFields<'_> {
+        HeaderFields {
+            parent_hash: self.parent_hash,
+            sha3_uncles: self.sha3_uncles,
+            miner: &self.miner,
+            state_root: self.state_root,
+            transactions_root: self.transactions_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: &self.logs_bloom,
+            difficulty: self.difficulty,
+            number: self.number,
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: &self.extra_data,
+            mix_hash: self.mix_hash,
+            nonce: self.nonce,
+            base_fee_per_gas: self.base_fee_per_gas,
+            withdrawals_root: self.withdrawals_root,
+            blob_gas_used: self.blob_gas_used,
+            excess_blob_gas: self.excess_blob_gas,
+            parent_beacon_block_root: self.parent_beacon_block_root,
+            requests_hash: self.requests_hash,
+        }
+    }
+
+    /// Returns the RLP encoded header of this block
+    pub fn header_rlp(&self) -> Result<Vec<u8>, Web3Error> {
+        self.header_fields().encode()
+    }
+
+    /// Recomputes the hash of this block from its header fields
+    pub fn compute_hash(&self) -> Result<Uint256, Web3Error> {
+        Ok(Uint256::from_be_bytes(&keccak256(&self.header_rlp()?)))
+    }
+
+    /// Checks that the header fields of this block hash to `Block::hash`
+    pub fn verify_hash(&self) -> Result<(), Web3Error> {
+        let computed = self.compute_hash()?;
+        if computed != self.hash {
+            return Err(Web3Error::InvalidBlockHash {
+                expected: self.hash,
+                computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Recomputes the transactions root from the full transaction list, fails if the block
+    /// contains transactions of an unknown type
+    pub fn compute_transactions_root(&self) -> Result<Uint256, Web3Error> {
+        let encoded = self
+            .transactions
+            .iter()
+            .map(|tx| tx.to_bytes())
+            .collect::<Result<Vec<Vec<u8>>, Web3Error>>()?;
+        Ok(Uint256::from_be_bytes(&ordered_trie_root(&encoded)))
+    }
+
+    /// Checks that the transactions in this block match `Block::transactions_root`, combined
+    /// with `verify_hash` this proves the transaction list was not tampered with
+    pub fn verify_transactions_root(&self) -> Result<(), Web3Error> {
+        let computed = self.compute_transactions_root()?;
+        if computed != self.transactions_root {
+            return Err(Web3Error::InvalidTransactionsRoot {
+                expected: self.transactions_root,
+                computed,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ConciseBlock {
+    fn header_fields(&self) -> HeaderFields<'_> {
+        HeaderFields {
+            parent_hash: self.parent_hash,
+            sha3_uncles: self.sha3_uncles,
+            miner: &self.miner,
+            state_root: self.state_root,
+            transactions_root: self.transactions_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: &self.logs_bloom,
+            difficulty: self.difficulty,
+            number: self.number,
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: &self.extra_data,
+            mix_hash: self.mix_hash,
+            nonce: Some(self.nonce),
+            base_fee_per_gas: self.base_fee_per_gas,
+            withdrawals_root: self.withdrawals_root,
+            blob_gas_used: self.blob_gas_used,
+            excess_blob_gas: self.excess_blob_gas,
+            parent_beacon_block_root: self.parent_beacon_block_root,
+            requests_hash: self.requests_hash,
+        }
+    }
+
+    /// Returns the RLP encoded header of this block
+    pub fn header_rlp(&self) -> Result<Vec<u8>, Web3Error> {
+        self.header_fields().encode()
+    }
+
+    /// Recomputes the hash of this block from its header fields
+    pub fn compute_hash(&self) -> Result<Uint256, Web3Error> {
+        Ok(Uint256::from_be_bytes(&keccak256(&self.header_rlp()?)))
+    }
+
+    /// Checks that the header fields of this block hash to `ConciseBlock::hash`
+    pub fn verify_hash(&self) -> Result<(), Web3Error> {
+        let computed = self.compute_hash()?;
+        if computed != self.hash {
+            return Err(Web3Error::InvalidBlockHash {
+                expected: self.hash,
+                computed,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn encode_access_list(access_list: &[(Uint256, Vec<Uint256>)]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = access_list
+        .iter()
+        .map(|(address, storage_keys)| {
+            // the address is stored left padded to 32 bytes
+            let keys: Vec<Vec<u8>> = storage_keys.iter().map(|k| encode_hash(*k)).collect();
+            encode_list(&[
+                encode_bytes(&address.to_be_bytes()[12..]),
+                encode_list(&keys),
+            ])
+        })
+        .collect();
+    encode_list(&entries)
+}
+
+fn encode_optional_address(address: &Option<Address>) -> Vec<u8> {
+    match address {
+        Some(address) => encode_address(address),
+        // contract creation
+        None => encode_bytes(&[]),
+    }
+}
+
+/// Prefixes an RLP payload with the EIP-2718 transaction type
+fn typed_envelope(tx_type: u8, items: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = vec![tx_type];
+    out.extend(encode_list(items));
+    out
+}
+
+impl TransactionResponse {
+    /// Reproduces the signed network encoding of this transaction, this is the
+    /// value stored in the transactions trie and hashed to produce the tx hash.
+    /// Fails for `Unknown` transaction types whose encoding is not known
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Web3Error> {
+        Ok(match self {
+            TransactionResponse::Legacy {
+                nonce,
+                gas_price,
+                gas,
+                to,
+                value,
+                input,
+                v,
+                r,
+                s,
+                ..
+            } => encode_list(&[
+                encode_uint(*nonce),
+                encode_uint(*gas_price),
+                encode_uint(*gas),
+                encode_optional_address(to),
+                encode_uint(*value),
+                encode_bytes(input),
+                encode_uint(*v),
+                encode_uint(*r),
+                encode_uint(*s),
+            ]),
+            TransactionResponse::Eip2930 {
+                chain_id,
+                nonce,
+                gas_price,
+                gas,
+                to,
+                value,
+                input,
+                access_list,
+                v,
+                r,
+                s,
+                ..
+            } => typed_envelope(
+                1,
+                &[
+                    encode_uint(*chain_id),
+                    encode_uint(*nonce),
+                    encode_uint(*gas_price),
+                    encode_uint(*gas),
+                    encode_optional_address(to),
+                    encode_uint(*value),
+                    encode_bytes(input),
+                    encode_access_list(access_list),
+                    encode_uint(*v),
+                    encode_uint(*r),
+                    encode_uint(*s),
+                ],
+            ),
+            TransactionResponse::Eip1559 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas,
+                to,
+                value,
+                input,
+                access_list,
+                v,
+                r,
+                s,
+                ..
+            } => typed_envelope(
+                2,
+                &[
+                    encode_uint(*chain_id),
+                    encode_uint(*nonce),
+                    encode_uint(*max_priority_fee_per_gas),
+                    encode_uint(*max_fee_per_gas),
+                    encode_uint(*gas),
+                    encode_optional_address(to),
+                    encode_uint(*value),
+                    encode_bytes(input),
+                    encode_access_list(access_list),
+                    encode_uint(*v),
+                    encode_uint(*r),
+                    encode_uint(*s),
+                ],
+            ),
+            TransactionResponse::Eip4844 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas,
+                to,
+                value,
+                input,
+                access_list,
+                max_fee_per_blob_gas,
+                blob_versioned_hashes,
+                v,
+                r,
+                s,
+                ..
+            } => {
+                let hashes: Vec<Vec<u8>> = blob_versioned_hashes
+                    .iter()
+                    .map(|h| encode_bytes(h))
+                    .collect();
+                typed_envelope(
+                    3,
+                    &[
+                        encode_uint(*chain_id),
+                        encode_uint(*nonce),
+                        encode_uint(*max_priority_fee_per_gas),
+                        encode_uint(*max_fee_per_gas),
+                        encode_uint(*gas),
+                        encode_address(to),
+                        encode_uint(*value),
+                        encode_bytes(input),
+                        encode_access_list(access_list),
+                        encode_uint(*max_fee_per_blob_gas),
+                        encode_list(&hashes),
+                        encode_uint(*v),
+                        encode_uint(*r),
+                        encode_uint(*s),
+                    ],
+                )
+            }
+            TransactionResponse::Eip7702 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas,
+                to,
+                value,
+                input,
+                access_list,
+                authorization_list,
+                v,
+                r,
+                s,
+                ..
+            } => {
+                let authorizations: Vec<Vec<u8>> = authorization_list
+                    .iter()
+                    .map(|a| {
+                        encode_list(&[
+                            encode_uint(a.chain_id),
+                            encode_address(&a.address),
+                            encode_uint(a.nonce),
+                            encode_uint(a.y_parity),
+                            encode_uint(a.r),
+                            encode_uint(a.s),
+                        ])
+                    })
+                    .collect();
+                typed_envelope(
+                    4,
+                    &[
+                        encode_uint(*chain_id),
+                        encode_uint(*nonce),
+                        encode_uint(*max_priority_fee_per_gas),
+                        encode_uint(*max_fee_per_gas),
+                        encode_uint(*gas),
+                        encode_address(to),
+                        encode_uint(*value),
+                        encode_bytes(input),
+                        encode_access_list(access_list),
+                        encode_list(&authorizations),
+                        encode_uint(*v),
+                        encode_uint(*r),
+                        encode_uint(*s),
+                    ],
+                )
+            }
+            TransactionResponse::Unknown { tx_type, .. } => {
+                return Err(Web3Error::BadInput(format!(
+                    "Can not encode transaction type {tx_type:#x}"
+                )))
+            }
+        })
+    }
+}
+
+/// Computes the root of a Merkle Patricia Trie keyed by the RLP encoded index of each
+/// value, this is how the transactions, receipts and withdrawals roots are built
+pub fn ordered_trie_root(values: &[Vec<u8>]) -> [u8; 32] {
+    if values.is_empty() {
+        return keccak256(&encode_bytes(&[]));
+    }
+    let items: Vec<(Vec<u8>, &[u8])> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let key = encode_uint((i as u64).into());
+            let nibbles = key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+            (nibbles, value.as_slice())
+        })
+        .collect();
+    keccak256(&trie_node(&items, 0))
+}
+
+/// Builds the RLP encoded trie node containing every item, keys are nibble paths
+/// and `depth` is the number of nibbles already consumed by parent nodes
+fn trie_node(items: &[(Vec<u8>, &[u8])], depth: usize) -> Vec<u8> {
+    if items.len() == 1 {
+        let (key, value) = &items[0];
+        return encode_list(&[
+            encode_bytes(&hex_prefix(&key[depth..], true)),
+            encode_bytes(value),
+        ]);
+    }
+
+    let first = &items[0].0;
+    let mut common = first.len() - depth;
+    for (key, _) in &items[1..] {
+        let shared = key[depth..]
+            .iter()
+            .zip(&first[depth..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        common = common.min(shared);
+    }
+    if common > 0 {
+        let child = trie_node(items, depth + common);
+        return encode_list(&[
+            encode_bytes(&hex_prefix(&first[depth..depth + common], false)),
+            node_reference(child),
+        ]);
+    }
+
+    let mut branch = Vec::with_capacity(17);
+    for nibble in 0..16u8 {
+        let children: Vec<(Vec<u8>, &[u8])> = items
+            .iter()
+            .filter(|(key, _)| key.len() > depth && key[depth] == nibble)
+            .cloned()
+            .collect();
+        if children.is_empty() {
+            branch.push(encode_bytes(&[]));
+        } else {
+            branch.push(node_reference(trie_node(&children, depth + 1)));
+        }
+    }
+    match items.iter().find(|(key, _)| key.len() == depth) {
+        Some((_, value)) => branch.push(encode_bytes(value)),
+        None => branch.push(encode_bytes(&[])),
+    }
+    encode_list(&branch)
+}
+
+/// Nodes shorter than 32 bytes are embedded in their parent, larger ones by hash
+fn node_reference(node: Vec<u8>) -> Vec<u8> {
+    if node.len() < 32 {
+        node
+    } else {
+        encode_bytes(&keccak256(&node))
+    }
+}
+
+/// Compact encoding of a nibble path, the first nibble flags leaf vs extension nodes
+/// and odd vs even path length
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 } + (nibbles.len() % 2) as u8;
+    let mut out = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        out.push((flag << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        out.push(flag << 4);
+        nibbles
+    };
+    for pair in rest.chunks(2) {
+        out.push((pair[0] << 4) | pair[1]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    /// Blocks of every header layout, the pre-London ones are mainnet captures. The London,
+    /// Shanghai, Cancun and Prague blocks were assembled from the EIP encodings with real
+    /// signatures so that they cover transaction types 1 to 4
+    const FORK_BLOCKS: [&str; 4] = ["london", "shanghai", "cancun", "prague"];
+
+    #[test]
+    fn test_verify_block() {
+        let mut files = vec![
+            "test_files/complete_geth_eth_block.json".to_string(),
+            "test_files/complete_parity_eth_block.json".to_string(),
+            "test_files/eth_A40AFB_block.json".to_string(),
+        ];
+        files.extend(
+            FORK_BLOCKS
+                .iter()
+                .map(|f| format!("test_files/{f}_eth_block.json")),
+        );
+
+        let mut tx_types = Vec::new();
+        for file in files {
+            let file = read_to_string(file).expect("Failed to read test files!");
+            let block: Block = serde_json::from_str(&file).unwrap();
+            block.verify_hash().unwrap();
+            block.verify_transactions_root().unwrap();
+
+            // each transaction should also reproduce its own hash
+            for tx in block.transactions.iter() {
+                let hash = match tx {
+                    TransactionResponse::Legacy { hash, .. }
+                    | TransactionResponse::Eip2930 { hash, .. }
+                    | TransactionResponse::Eip1559 { hash, .. }
+                    | TransactionResponse::Eip4844 { hash, .. }
+                    | TransactionResponse::Eip7702 { hash, .. } => hash.clone(),
+                    TransactionResponse::Unknown { .. } => panic!("Unexpected transaction type"),
+                };
+                assert_eq!(keccak256(&tx.to_bytes().unwrap()).to_vec(), hash.0);
+                tx_types.push(tx.get_type());
+            }
+        }
+        for tx_type in 0..=4 {
+            assert!(tx_types.contains(&tx_type));
+        }
+    }
+
+    #[test]
+    fn test_verify_concise_block() {
+        let mut files = vec![
+            "test_files/concise_geth_eth_block.json".to_string(),
+            "test_files/concise_parity_eth_block.json".to_string(),
+        ];
+        files.extend(
+            FORK_BLOCKS
+                .iter()
+                .map(|f| format!("test_files/concise_{f}_eth_block.json")),
+        );
+        for file in files {
+            let file = read_to_string(file).expect("Failed to read test files!");
+            let block: ConciseBlock = serde_json::from_str(&file).unwrap();
+            block.verify_hash().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reject_tampered_block() {
+        let file = read_to_string("test_files/complete_geth_eth_block.json")
+            .expect("Failed to read test files!");
+        let block: Block = serde_json::from_str(&file).unwrap();
+
+        let mut tampered = block.clone();
+        tampered.gas_used += Uint256::from(1u8);
+        assert!(matches!(
+            tampered.verify_hash(),
+            Err(Web3Error::InvalidBlockHash { .. })
+        ));
+
+        let mut tampered = block.clone();
+        tampered.transactions.pop();
+        assert!(matches!(
+            tampered.verify_transactions_root(),
+            Err(Web3Error::InvalidTransactionsRoot { .. })
+        ));
+
+        // a cancun field without the shanghai field before it is not a valid layout
+        let mut tampered = block;
+        tampered.base_fee_per_gas = Some(7u8.into());
+        tampered.blob_gas_used = Some(0u8.into());
+        assert!(matches!(
+            tampered.verify_hash(),
+            Err(Web3Error::BadResponse(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_trie_root() {
+        assert_eq!(
+            Uint256::from_be_bytes(&ordered_trie_root(&[])),
+            "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+                .parse()
+                .unwrap()
+        );
+    }
+}
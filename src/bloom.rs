@@ -0,0 +1,181 @@
+//! This module contains the 2048 bit bloom filter found in block headers and receipts as
+//! `logsBloom`. Every log address and topic of a block is added to its bloom, so a block whose
+//! bloom does not contain a value certainly has no log with it, while a match may be a false
+//! positive. Checking headers first avoids `eth_getLogs` calls for blocks without relevant logs.
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{Data, NewFilter};
+use clarity::utils::hex_str_to_bytes;
+use clarity::Address;
+
+/// Size of a logs bloom in bytes
+pub const BLOOM_SIZE: usize = 256;
+
+/// A block or receipt logs bloom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bloom(pub [u8; BLOOM_SIZE]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom([0u8; BLOOM_SIZE])
+    }
+}
+
+impl TryFrom<&[u8]> for Bloom {
+    type Error = Web3Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value.try_into() {
+            Ok(bloom) => Ok(Bloom(bloom)),
+            Err(_) => Err(Web3Error::BadResponse(format!(
+                "Logs bloom must be {BLOOM_SIZE} bytes, got {}",
+                value.len()
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&Data> for Bloom {
+    type Error = Web3Error;
+
+    fn try_from(value: &Data) -> Result<Self, Self::Error> {
+        Bloom::try_from(value.as_slice())
+    }
+}
+
+/// The three bits set for `input`, each taken from the low 11 bits of a pair of bytes of its
+/// hash, as byte index and mask with bit 0 being the last bit of the bloom
+fn bloom_bits(input: &[u8]) -> [(usize, u8); 3] {
+    let hash = keccak256(input);
+    let mut bits = [(0, 0); 3];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        let index = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) % (BLOOM_SIZE * 8);
+        *bit = (BLOOM_SIZE - 1 - index / 8, 1 << (index % 8));
+    }
+    bits
+}
+
+impl Bloom {
+    /// Adds `input`, an address or topic, to the bloom
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (byte, mask) in bloom_bits(input) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    /// Returns false if `input` is certainly not in the bloom, true if it may be
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        bloom_bits(input)
+            .iter()
+            .all(|(byte, mask)| self.0[*byte] & mask != 0)
+    }
+
+    /// Returns false if no log was emitted by `address`, true if one may have been
+    pub fn contains_address(&self, address: Address) -> bool {
+        self.contains_input(address.as_bytes())
+    }
+
+    /// Returns false if no log has `topic`, true if one may have it
+    pub fn contains_topic(&self, topic: &[u8; 32]) -> bool {
+        self.contains_input(topic)
+    }
+
+    /// Returns false if no log can match `filter`, that is if none of the filter addresses is in
+    /// the bloom or if for some topic slot none of the allowed values is. Block ranges of the
+    /// filter are not considered.
+    pub fn matches_filter(&self, filter: &NewFilter) -> Result<bool, Web3Error> {
+        if !filter.address.is_empty() && !filter.address.iter().any(|a| self.contains_address(*a)) {
+            return Ok(false);
+        }
+        for slot in filter.topics.iter().flatten().flatten() {
+            let mut any = false;
+            let mut wildcard = false;
+            for value in slot {
+                match value {
+                    Some(value) => {
+                        let topic = hex_str_to_bytes(value).map_err(|e| {
+                            Web3Error::BadInput(format!("Invalid topic {value} {e}"))
+                        })?;
+                        any |= self.contains_input(&topic);
+                    }
+                    None => wildcard = true,
+                }
+            }
+            if !(any || wildcard || slot.is_empty()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterBuilder;
+    use crate::types::ConciseBlock;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_bloom() {
+        let usdt: Address = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+            .parse()
+            .unwrap();
+        let transfer =
+            hex_str_to_bytes("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef")
+                .unwrap();
+        let mut bloom = Bloom::default();
+        bloom.accrue(usdt.as_bytes());
+        bloom.accrue(&transfer);
+        let mut expected = [0u8; BLOOM_SIZE];
+        for (byte, value) in [
+            (46, 0x01),
+            (75, 0x08),
+            (123, 0x10),
+            (157, 0x10),
+            (171, 0x80),
+            (195, 0x02),
+        ] {
+            expected[byte] = value;
+        }
+        assert_eq!(bloom, Bloom(expected));
+        assert!(bloom.contains_address(usdt));
+        assert!(!bloom.contains_address(Address::default()));
+
+        assert!(Bloom::try_from(&[0u8; 255][..]).is_err());
+    }
+
+    #[test]
+    fn test_block_bloom() {
+        let file = read_to_string("test_files/concise_geth_eth_block.json")
+            .expect("Failed to read test files!");
+        let block: ConciseBlock = serde_json::from_str(&file).unwrap();
+        let bloom = Bloom::try_from(&block.logs_bloom).unwrap();
+
+        let weth: Address = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+            .parse()
+            .unwrap();
+        let unused: Address = "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap();
+        assert!(bloom.contains_address(weth));
+        assert!(!bloom.contains_address(unused));
+
+        let transfer = "Transfer(address,address,uint256)";
+        let filter = FilterBuilder::new()
+            .addresses(&[unused, weth])
+            .event(transfer)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(bloom.matches_filter(&filter).unwrap());
+        let filter = FilterBuilder::new()
+            .address(weth)
+            .event(transfer)
+            .unwrap()
+            .topic(1, unused)
+            .build()
+            .unwrap();
+        assert!(!bloom.matches_filter(&filter).unwrap());
+    }
+}
@@ -4,16 +4,22 @@
 //! work on big endian. We can do better than that just crafting our own
 //! JSONRPC requests.
 //!
+use crate::block_tracker::{BlockEvent, BlockTracker};
 use crate::event_utils::{ContractEvent, Web3Event};
 use crate::jsonrpc::client::HttpClient;
 use crate::jsonrpc::error::Web3Error;
+use crate::nonce_manager::NonceManager;
+use crate::revert::{revert_error, ErrorRegistry};
+use crate::signer::{verify_signed_transaction, Signer};
 use crate::tron_utils;
+use crate::types::{
+    clarity_destination, ConciseBlock, Data, SendTxOption, SignedTransaction, TransactionReceipt,
+};
 use crate::types::{Block, Log, NewFilter, SyncingStatus, TransactionRequest, TransactionResponse};
-use crate::types::{ConciseBlock, Data, SendTxOption};
 use clarity::abi::{encode_call, AbiToken as Token};
 use clarity::utils::bytes_to_hex_str;
-use clarity::{Address, PrivateKey, Transaction, Uint256};
-use futures::future::join4;
+use clarity::{Address, Transaction, Uint256};
+use futures::future::join3;
 use heliosphere::core::transaction::TransactionId;
 use heliosphere::RpcClient;
 use num_traits::{ToPrimitive, Zero};
@@ -23,7 +29,7 @@ use std::{cmp::min, time::Duration};
 use std::{sync::Arc, time::Instant};
 use tokio::time::sleep as delay_for;
 
-const ETHEREUM_INTRINSIC_GAS: u32 = 21000;
+pub(crate) const ETHEREUM_INTRINSIC_GAS: u32 = 21000;
 
 /// An instance of Web3Client.
 #[derive(Clone)]
@@ -34,6 +40,9 @@ pub struct Web3 {
     jsonrpc_client: Arc<HttpClient>,
     url: String,
     headers: HashMap<String, String>,
+    nonce_manager: Arc<NonceManager>,
+    expected_chain_id: Option<u64>,
+    error_registry: Arc<ErrorRegistry>,
 }
 
 impl Web3 {
@@ -72,6 +81,9 @@ impl Web3 {
                 headers,
                 tron: Some(Arc::new(tron)),
                 url,
+                nonce_manager: Arc::new(NonceManager::new()),
+                expected_chain_id: None,
+                error_registry: Arc::new(ErrorRegistry::new()),
             }
         } else {
             Self {
@@ -81,10 +93,34 @@ impl Web3 {
                 headers,
                 tron: None,
                 url: url.to_string(),
+                nonce_manager: Arc::new(NonceManager::new()),
+                expected_chain_id: None,
+                error_registry: Arc::new(ErrorRegistry::new()),
             }
         }
     }
 
+    /// Creates a client that refuses to sign transactions unless the node reports
+    /// `chain_id`, this protects against a node tricking us into signing for another chain
+    pub fn new_with_chain_id(url: &str, timeout: Duration, chain_id: u64) -> Self {
+        let mut web3 = Web3::new(url, timeout);
+        web3.expected_chain_id = Some(chain_id);
+        web3
+    }
+
+    pub fn get_expected_chain_id(&self) -> Option<u64> {
+        self.expected_chain_id
+    }
+
+    /// Returns true if this instance is connected to a Tron node
+    pub fn is_tron(&self) -> bool {
+        self.tron.is_some()
+    }
+
+    pub(crate) fn tron_client(&self) -> Option<&RpcClient> {
+        self.tron.as_deref()
+    }
+
     pub fn set_header(&mut self, key: &str, value: &str) {
         self.headers.insert(key.to_string(), value.to_string());
     }
@@ -97,6 +133,27 @@ impl Web3 {
         self.headers.keys().map(|k| k.clone()).collect()
     }
 
+    /// The custom errors used to decode reverts found by `SendTxOption::Preflight`
+    pub fn error_registry(&self) -> Arc<ErrorRegistry> {
+        self.error_registry.clone()
+    }
+
+    /// Sets the custom error signatures of the contracts this instance interacts with
+    pub fn set_error_registry(&mut self, error_registry: ErrorRegistry) {
+        self.error_registry = Arc::new(error_registry);
+    }
+
+    /// The nonce manager used by `send_transaction`, shared by all clones of this instance
+    pub fn nonce_manager(&self) -> Arc<NonceManager> {
+        self.nonce_manager.clone()
+    }
+
+    /// Replaces the nonce manager, this allows several instances pointing at different
+    /// nodes for the same chain to share one nonce sequence
+    pub fn set_nonce_manager(&mut self, nonce_manager: Arc<NonceManager>) {
+        self.nonce_manager = nonce_manager;
+    }
+
     pub async fn eth_accounts(&self) -> Result<Vec<Address>, Web3Error> {
         self.jsonrpc_client
             .request_method(
@@ -123,6 +180,33 @@ impl Web3 {
         Ok(Some(ret?))
     }
 
+    /// Returns the chain id transactions should be signed with, this is `eth_chainId` as
+    /// reported by the node checked against the expected chain id, if one was set
+    pub async fn signing_chain_id(&self) -> Result<u64, Web3Error> {
+        let reported = match self.eth_chainid().await? {
+            Some(chain_id) => chain_id,
+            None => {
+                return Err(Web3Error::BadResponse(
+                    "Node did not report a chain id".to_string(),
+                ))
+            }
+        };
+        let reported = match reported.to_u64() {
+            Some(chain_id) => chain_id,
+            None => {
+                return Err(Web3Error::BadResponse(format!(
+                    "Chain id {reported} does not fit in a u64"
+                )))
+            }
+        };
+        match self.expected_chain_id {
+            Some(expected) if expected != reported => {
+                Err(Web3Error::ChainIdMismatch { expected, reported })
+            }
+            _ => Ok(reported),
+        }
+    }
+
     pub async fn net_version(&self) -> Result<u64, Web3Error> {
         let ret: Uint256 = self
             .jsonrpc_client
@@ -199,6 +283,34 @@ impl Web3 {
         }
     }
 
+    /// Gets the transaction count of an address including transactions still in the
+    /// node's mempool, this is the next nonce the node expects for the address
+    pub async fn eth_get_pending_transaction_count(
+        &self,
+        address: Address,
+    ) -> Result<Uint256, Web3Error> {
+        // tron does not support this method
+        if self.tron.is_some() {
+            return Ok(Uint256::zero());
+        }
+        //check if the node is still syncing
+        match self.eth_syncing().await? {
+            false => {
+                self.jsonrpc_client
+                    .request_method(
+                        "eth_getTransactionCount",
+                        vec![address.to_string(), "pending".to_string()],
+                        self.timeout,
+                        &self.headers,
+                    )
+                    .await
+            }
+            true => Err(Web3Error::SyncingNode(
+                "Cannot perform eth_getTransactionCount".to_string(),
+            )),
+        }
+    }
+
     /// Get the median gas price over the last 10 blocks. This function does not
     /// simply wrap eth_gasPrice, in post London chains it also requests the base
     /// gas from the previous block and prevents the use of a lower value
@@ -415,6 +527,22 @@ impl Web3 {
         }
     }
 
+    /// Gets a block by hash including tx hashes instead of full tx data, returns `None`
+    /// if the node does not know the block
+    pub async fn eth_get_concise_block_by_hash(
+        &self,
+        block_hash: Uint256,
+    ) -> Result<Option<ConciseBlock>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getBlockByHash",
+                (format!("{block_hash:#066x}"), false),
+                self.timeout,
+                &self.headers,
+            )
+            .await
+    }
+
     /// Gets the latest (non finalized) block including tx hashes instead of full tx data
     pub async fn eth_get_latest_block(&self) -> Result<ConciseBlock, Web3Error> {
         match self.eth_syncing().await? {
@@ -522,6 +650,21 @@ impl Web3 {
             .await
     }
 
+    /// Gets the receipt of a mined transaction, returns `None` while it is pending or unknown
+    pub async fn eth_get_transaction_receipt(
+        &self,
+        hash: Uint256,
+    ) -> Result<Option<TransactionReceipt>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getTransactionReceipt",
+                vec![format!("{hash:#066x}")],
+                self.timeout,
+                &self.headers,
+            )
+            .await
+    }
+
     pub async fn evm_snapshot(&self) -> Result<Uint256, Web3Error> {
         self.jsonrpc_client
             .request_method(
@@ -549,10 +692,13 @@ impl Web3 {
     /// unlike the lower level eth_send_transaction() this call builds
     /// the transaction abstracting away details like chain id, gas,
     /// and network id.
-    /// WARNING: you must specify networkID in situations where a single
-    /// node is operating no more than one chain. Otherwise it is possible
-    /// for the full node to trick the client into signing transactions
+    /// The chain id is taken from `eth_chainId`. WARNING: unless an expected chain id
+    /// is set with `Web3::new_with_chain_id` or passed as `SendTxOption::NetworkId`
+    /// it is possible for the full node to trick the client into signing transactions
     /// on unintended chains potentially to their benefit
+    ///
+    /// Unless `SendTxOption::Nonce` is provided the nonce is reserved from the
+    /// shared nonce manager, so concurrent sends from the same key are safe
     pub async fn send_transaction(
         &self,
         to_address: Address,
@@ -560,43 +706,185 @@ impl Web3 {
         tokens: &[Token],
         value: Uint256,
         own_address: Address,
-        secret: PrivateKey,
+        signer: impl Signer,
         options: Vec<SendTxOption>,
     ) -> Result<Uint256, Web3Error> {
         if let Some(tron) = &self.tron {
+            let secret = match signer.private_key() {
+                Some(secret) => secret,
+                None => {
+                    return Err(Web3Error::BadInput(
+                        "Tron transactions require an in memory private key".to_string(),
+                    ))
+                }
+            };
             return tron_utils::send_transaction(
                 tron, to_address, selector, tokens, value, secret, options,
             )
             .await;
         }
 
-        let mut max_priority_fee_per_gas = 1u8.into();
-        let mut gas_limit_multiplier = 1f32;
-        let mut gas_limit = None;
-        let mut access_list = Vec::new();
+        let data = encode_call(selector, tokens)?;
+        self.send_transaction_data(Some(to_address), data, value, own_address, signer, options)
+            .await
+    }
 
-        let our_balance = self.eth_get_balance(own_address);
-        let nonce = self.eth_get_transaction_count(own_address);
-        let max_fee_per_gas = self.get_base_fee_per_gas();
-        let chain_id = self.net_version();
+    /// Sends a transaction with an already encoded payload, the shared implementation of
+    /// `send_transaction` and `deploy_contract`. A `to_address` of `None` creates a contract.
+    /// Not supported on Tron.
+    pub(crate) async fn send_transaction_data(
+        &self,
+        to_address: Option<Address>,
+        data: Vec<u8>,
+        value: Uint256,
+        own_address: Address,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<Uint256, Web3Error> {
+        // a nonce provided by the caller bypasses the nonce manager entirely
+        let manual_nonce = options
+            .iter()
+            .any(|option| matches!(option, SendTxOption::Nonce(_)));
+        let (raw, nonce) = self
+            .sign_transaction_data(to_address, data, value, own_address, signer, options)
+            .await?;
 
-        // request in parallel
-        let (our_balance, nonce, base_fee_per_gas, chain_id) =
-            join4(our_balance, nonce, max_fee_per_gas, chain_id).await;
+        let result = self.eth_send_raw_transaction(raw).await;
+        if !manual_nonce {
+            match &result {
+                Ok(_) => {}
+                // the node refused the tx itself so the nonce is still free
+                Err(e) if is_tx_rejected_error(e) => {
+                    self.nonce_manager.release(own_address, nonce).await
+                }
+                // anything else may mean the nonce is already in the mempool, such as a
+                // replacement being underpriced or the tx being known, so ask the node
+                Err(_) => self.nonce_manager.resync(own_address).await,
+            }
+        }
+        result
+    }
 
-        let (our_balance, mut nonce, base_fee_per_gas, chain_id) =
-            (our_balance?, nonce?, base_fee_per_gas?, chain_id?);
+    /// Builds and signs a transaction exactly like `send_transaction` does, including nonce
+    /// reservation, fee selection and gas estimation, but returns it instead of broadcasting.
+    /// Use `broadcast_signed_transaction` to send it later.
+    ///
+    /// Unless `SendTxOption::Nonce` is provided the nonce is reserved from the shared nonce
+    /// manager, if the transaction is discarded rather than broadcast hand the nonce back with
+    /// `Web3::nonce_manager().release()` or later transactions will wait on it. Not supported
+    /// on Tron.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn build_signed_transaction(
+        &self,
+        to_address: Address,
+        selector: &str,
+        tokens: &[Token],
+        value: Uint256,
+        own_address: Address,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<SignedTransaction, Web3Error> {
+        if self.tron.is_some() {
+            return Err(Web3Error::BadInput(
+                "Signing without broadcast is not supported on Tron".to_string(),
+            ));
+        }
+        let data = encode_call(selector, tokens)?;
+        let (raw, _) = self
+            .sign_transaction_data(Some(to_address), data, value, own_address, signer, options)
+            .await?;
+        SignedTransaction::from_raw(raw)
+    }
+
+    /// Broadcasts a transaction signed earlier with `build_signed_transaction` or by
+    /// any other means, returns its hash
+    pub async fn broadcast_signed_transaction(
+        &self,
+        transaction: &SignedTransaction,
+    ) -> Result<Uint256, Web3Error> {
+        self.eth_send_raw_transaction(transaction.raw.clone()).await
+    }
+
+    /// Reserves a nonce, builds and signs a transaction, returns the raw signed transaction
+    /// and its nonce. The nonce is released again if anything fails
+    async fn sign_transaction_data(
+        &self,
+        to_address: Option<Address>,
+        data: Vec<u8>,
+        value: Uint256,
+        own_address: Address,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<(Vec<u8>, Uint256), Web3Error> {
+        if signer.address() != own_address {
+            return Err(Web3Error::BadInput(format!(
+                "Signer address {} does not match sender {}",
+                signer.address(),
+                own_address
+            )));
+        }
 
-        // check if we can send an EIP1559 tx on this chain
-        let base_fee_per_gas = match base_fee_per_gas {
-            Some(bf) => bf,
-            None => return Err(Web3Error::PreLondon),
+        let manual_nonce = options.iter().find_map(|option| match option {
+            SendTxOption::Nonce(n) => Some(*n),
+            _ => None,
+        });
+        let nonce = match manual_nonce {
+            Some(nonce) => nonce,
+            None => self.nonce_manager.reserve(self, own_address).await?,
+        };
+
+        let raw = match self
+            .build_transaction(to_address, data, value, own_address, options, nonce)
+            .await
+        {
+            Ok((transaction, network_id)) => {
+                match signer
+                    .sign_transaction(transaction.clone(), network_id)
+                    .await
+                {
+                    Ok(raw) => {
+                        verify_signed_transaction(raw, &transaction, network_id, own_address)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
         };
+        match raw {
+            Ok(signed) => Ok((signed.raw, nonce)),
+            Err(e) => {
+                if manual_nonce.is_none() {
+                    self.nonce_manager.release(own_address, nonce).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Builds the unsigned transaction for `send_transaction` with the given nonce along with
+    /// the EIP-155 network id to sign it with, this is an EIP1559 transaction unless the chain
+    /// does not support them or `SendTxOption::Legacy` is passed, in which case a legacy
+    /// (EIP-155) or EIP-2930 transaction is built instead
+    async fn build_transaction(
+        &self,
+        to_address: Option<Address>,
+        data: Vec<u8>,
+        value: Uint256,
+        own_address: Address,
+        options: Vec<SendTxOption>,
+        nonce: Uint256,
+    ) -> Result<(Transaction, Option<u64>), Web3Error> {
+        let to = clarity_destination(to_address)?;
+        let our_balance = self.eth_get_balance(own_address);
+        let max_fee_per_gas = self.get_base_fee_per_gas();
+        let chain_id = self.signing_chain_id();
+
+        // request in parallel
+        let (our_balance, base_fee_per_gas, chain_id) =
+            join3(our_balance, max_fee_per_gas, chain_id).await;
 
-        // max_fee_per_gas is base gas multiplied by 2, this is a maximum the actual price we pay is determined
-        // by the block the transaction enters, if we put the price exactly as the base fee the tx will fail if
-        // the price goes up at all in the next block. So some base level multiplier makes sense as a default
-        let mut max_fee_per_gas = base_fee_per_gas * 2u8.into();
+        let (our_balance, base_fee_per_gas, chain_id) =
+            (our_balance?, base_fee_per_gas?, chain_id?);
 
         if our_balance.is_zero() || our_balance < ETHEREUM_INTRINSIC_GAS.into() {
             // We only know that the balance is insufficient, we don't know how much gas is needed
@@ -607,57 +895,88 @@ impl Web3 {
             });
         }
 
-        for option in options {
+        let mut gas_limit_multiplier = 1f32;
+        let mut gas_limit = None;
+        let mut access_list = Vec::new();
+        let mut legacy = false;
+        let mut preflight = false;
+        for option in options.iter() {
             match option {
-                SendTxOption::GasMaxFee(gp) | SendTxOption::GasPrice(gp) => max_fee_per_gas = gp,
-                SendTxOption::GasPriorityFee(gp) => max_priority_fee_per_gas = gp,
-                SendTxOption::GasLimitMultiplier(glm) => gas_limit_multiplier = glm,
-                SendTxOption::GasLimit(gl) => gas_limit = Some(gl),
-                SendTxOption::Nonce(n) => nonce = n,
-                SendTxOption::AccessList(list) => access_list = list,
-                SendTxOption::GasPriceMultiplier(gm) | SendTxOption::GasMaxFeeMultiplier(gm) => {
-                    let f32_gas = base_fee_per_gas.to_u128();
-                    max_fee_per_gas = if let Some(v) = f32_gas {
-                        // convert to f32, multiply, then convert back, this
-                        // will be lossy but you want an exact price you can set it
-                        ((v as f32 * gm) as u128).into()
-                    } else {
-                        // gas price is insanely high, best effort rounding
-                        // perhaps we should panic here
-                        base_fee_per_gas * (gm.round() as u128).into()
-                    };
-                }
-                SendTxOption::NetworkId(_) => {
-                    return Err(Web3Error::BadInput(
-                        "Invalid option for eip1559 tx".to_string(),
-                    ))
+                SendTxOption::GasLimitMultiplier(glm) => gas_limit_multiplier = *glm,
+                SendTxOption::GasLimit(gl) => gas_limit = Some(*gl),
+                SendTxOption::AccessList(list) => access_list = list.clone(),
+                SendTxOption::NetworkId(id) if *id != chain_id => {
+                    return Err(Web3Error::ChainIdMismatch {
+                        expected: *id,
+                        reported: chain_id,
+                    });
                 }
+                SendTxOption::Legacy => legacy = true,
+                SendTxOption::Preflight => preflight = true,
+                // fee options depend on the transaction type and are handled below
+                // and the nonce is handled by send_transaction
+                _ => {}
             }
         }
 
-        let data = encode_call(selector, tokens)?;
-
-        let mut transaction = Transaction::Eip1559 {
-            chain_id: chain_id.into(),
-            nonce,
-            max_priority_fee_per_gas,
-            max_fee_per_gas,
-            gas_limit: 0u8.into(),
-            to: to_address,
-            value,
-            data,
-            signature: None,
-            access_list,
+        let mut transaction = match (base_fee_per_gas, legacy) {
+            (Some(base_fee_per_gas), false) => {
+                let (max_fee_per_gas, max_priority_fee_per_gas) =
+                    eip1559_fees(base_fee_per_gas, &options);
+                Transaction::Eip1559 {
+                    chain_id: chain_id.into(),
+                    nonce,
+                    max_priority_fee_per_gas,
+                    max_fee_per_gas,
+                    gas_limit: 0u8.into(),
+                    to,
+                    value,
+                    data,
+                    signature: None,
+                    access_list,
+                }
+            }
+            _ => {
+                let gas_price = legacy_gas_price(self.eth_gas_price().await?, &options)?;
+                if access_list.is_empty() {
+                    Transaction::Legacy {
+                        nonce,
+                        gas_price,
+                        gas_limit: 0u8.into(),
+                        to,
+                        value,
+                        data,
+                        signature: None,
+                    }
+                } else {
+                    Transaction::Eip2930 {
+                        access_list,
+                        chain_id: chain_id.into(),
+                        signature: None,
+                        nonce,
+                        gas_price,
+                        gas_limit: 0u8.into(),
+                        to,
+                        value,
+                        data,
+                    }
+                }
+            }
         };
 
         let mut gas_limit = if let Some(gl) = gas_limit {
             gl
         } else {
-            self.eth_estimate_gas(TransactionRequest::from_transaction(
-                &transaction,
-                own_address,
-            ))
-            .await?
+            // estimation already executes the transaction, so a revert shows up here first
+            let mut request = TransactionRequest::from_transaction(&transaction, own_address);
+            request.set_to(to_address);
+            self.eth_estimate_gas(request).await.map_err(|e| {
+                if preflight {
+                    revert_error(e, &self.error_registry)
+                } else {
+                    e
+                }
+            })?
         };
 
         // multiply limit by gasLimitMultiplier
@@ -674,34 +993,58 @@ impl Web3 {
         // be valid, we simply don't have the the funds to pay the full gas amount we are promising
         // this segment computes either the highest valid gas price we can pay or in the post-london
         // chain case errors if we can't meet the minimum fee
-        if max_fee_per_gas * gas_limit > our_balance {
-            if base_fee_per_gas * gas_limit > our_balance {
+        match (&mut transaction, base_fee_per_gas) {
+            (
+                Transaction::Eip1559 {
+                    max_fee_per_gas, ..
+                },
+                Some(base_fee_per_gas),
+            ) if *max_fee_per_gas * gas_limit > our_balance => {
+                if base_fee_per_gas * gas_limit > our_balance {
+                    return Err(Web3Error::InsufficientGas {
+                        balance: our_balance,
+                        base_gas: base_fee_per_gas,
+                        gas_required: gas_limit,
+                    });
+                }
+                // this will give some value >= base_fee_per_gas * gas_limit
+                *max_fee_per_gas = our_balance / gas_limit;
+            }
+            // the gas price of a legacy tx is exactly what we pay, lowering it
+            // would only produce a transaction that never gets mined
+            (Transaction::Legacy { gas_price, .. }, _)
+            | (Transaction::Eip2930 { gas_price, .. }, _)
+                if *gas_price * gas_limit > our_balance =>
+            {
                 return Err(Web3Error::InsufficientGas {
                     balance: our_balance,
-                    base_gas: base_fee_per_gas,
+                    base_gas: *gas_price,
                     gas_required: gas_limit,
                 });
             }
-            // this will give some value >= base_fee_per_gas * gas_limit
-            // in post-london and some non zero value in pre-london
-            max_fee_per_gas = our_balance / gas_limit;
+            _ => {}
         }
 
-        transaction.set_max_fee_per_gas(max_fee_per_gas);
-
         if !transaction.is_valid() {
             return Err(Web3Error::BadInput("About to send invalid tx".to_string()));
         }
 
-        let transaction = transaction.sign(&secret, None);
-
-        if !transaction.is_valid() {
-            return Err(Web3Error::BadInput("About to send invalid tx".to_string()));
+        if preflight {
+            // simulate with the exact gas and fee values that will be signed
+            let mut request = TransactionRequest::from_transaction(&transaction, own_address);
+            request.set_to(to_address);
+            self.eth_call(request)
+                .await
+                .map_err(|e| revert_error(e, &self.error_registry))?;
         }
 
-        let transaction = transaction.sign(&secret, None);
+        // legacy transactions carry the chain id in the signature (EIP-155)
+        let network_id = match transaction {
+            Transaction::Legacy { .. } => Some(chain_id),
+            _ => None,
+        };
 
-        self.eth_send_raw_transaction(transaction.to_bytes()).await
+        Ok((transaction, network_id))
     }
 
     /// Simulates an Ethereum contract call by making a fake transaction and sending it to a special endpoint
@@ -779,7 +1122,7 @@ impl Web3 {
 
     /// Waits for a transaction with the given hash to be included in a block
     /// it will wait for at most timeout time and optionally can wait for n
-    /// blocks to have passed
+    /// blocks to have passed on top of the canonical block containing it
     pub async fn eth_wait_for_transaction(
         &self,
         tx_hash: Uint256,
@@ -787,26 +1130,50 @@ impl Web3 {
         blocks_to_wait: Option<Uint256>,
     ) -> Result<TransactionResponse, Web3Error> {
         let start = Instant::now();
+        // confirmations are only counted while the block containing the tx stays canonical,
+        // if it is reorged out we keep waiting for the tx to be included again
+        let mut tracker = blocks_to_wait.map(|blocks| {
+            BlockTracker::new(blocks.to_usize().unwrap_or(usize::MAX).saturating_add(1))
+        });
         loop {
             delay_for(Duration::from_secs(1)).await;
             match self.eth_get_transaction_by_hash(tx_hash).await {
                 Ok(maybe_transaction) => {
                     if let Some(transaction) = maybe_transaction {
                         let block_number = transaction.get_block_number();
+                        let block_hash = transaction
+                            .get_block_hash()
+                            .map(|hash| Uint256::from_be_bytes(&hash));
                         // if no wait time is specified and the tx is in a block return right away
                         if blocks_to_wait.clone().is_none() && block_number.is_some() {
                             return Ok(transaction);
                         }
                         // One the tx is in a block we start waiting here
-                        else if let (Some(blocks_to_wait), Some(tx_block)) =
-                            (blocks_to_wait, block_number)
+                        else if let (
+                            Some(blocks_to_wait),
+                            Some(tx_block),
+                            Some(tx_block_hash),
+                            Some(tracker),
+                        ) = (blocks_to_wait, block_number, block_hash, tracker.as_mut())
                         {
-                            let current_block = self.eth_block_number().await?;
-                            // we check for underflow, which is possible on testnets
-                            if current_block > blocks_to_wait
-                                && current_block - blocks_to_wait >= tx_block
-                            {
-                                return Ok(transaction);
+                            for event in tracker.update(self).await? {
+                                if let BlockEvent::Reorg { depth, .. } = event {
+                                    warn!("Reorg of depth {depth} while waiting for tx {tx_hash:#066x}");
+                                }
+                            }
+                            let canonical = match tracker.is_canonical(tx_block, tx_block_hash) {
+                                Some(canonical) => canonical,
+                                // the tx block is older than the tracked window
+                                None => {
+                                    self.eth_get_concise_block_by_number(tx_block).await?.hash
+                                        == tx_block_hash
+                                }
+                            };
+                            let current_block = tracker.head().map(|head| head.number);
+                            if let (true, Some(current_block)) = (canonical, current_block) {
+                                if tx_block + blocks_to_wait <= current_block {
+                                    return Ok(transaction);
+                                }
                             }
                         }
                     }
@@ -861,7 +1228,7 @@ impl Web3 {
     /// what network (xDai or ETH) is being used. Returns `None` if a pre-London fork
     /// network is in use and `Some(base_fee_per_gas)` if a post London network is in
     /// use
-    async fn get_base_fee_per_gas(&self) -> Result<Option<Uint256>, Web3Error> {
+    pub(crate) async fn get_base_fee_per_gas(&self) -> Result<Option<Uint256>, Web3Error> {
         match self.eth_get_latest_block().await {
             Ok(eth_block) => Ok(eth_block.base_fee_per_gas),
             Err(e) => Err(e),
@@ -929,6 +1296,86 @@ impl Web3 {
         T::from_events(&event)
     }
 }
+/// Computes the max fee and priority fee of an EIP1559 transaction from `SendTxOption`s
+pub(crate) fn eip1559_fees(
+    base_fee_per_gas: Uint256,
+    options: &[SendTxOption],
+) -> (Uint256, Uint256) {
+    // max_fee_per_gas is base gas multiplied by 2, this is a maximum the actual price we pay is determined
+    // by the block the transaction enters, if we put the price exactly as the base fee the tx will fail if
+    // the price goes up at all in the next block. So some base level multiplier makes sense as a default
+    let mut max_fee_per_gas = base_fee_per_gas * 2u8.into();
+    let mut max_priority_fee_per_gas = 1u8.into();
+    for option in options {
+        match option {
+            SendTxOption::GasMaxFee(gp) | SendTxOption::GasPrice(gp) => max_fee_per_gas = *gp,
+            SendTxOption::GasPriorityFee(gp) => max_priority_fee_per_gas = *gp,
+            SendTxOption::GasPriceMultiplier(gm) | SendTxOption::GasMaxFeeMultiplier(gm) => {
+                max_fee_per_gas = multiply_fee(base_fee_per_gas, *gm)
+            }
+            _ => {}
+        }
+    }
+    (max_fee_per_gas, max_priority_fee_per_gas)
+}
+
+/// Computes the gas price of a legacy or EIP2930 transaction from `SendTxOption`s
+pub(crate) fn legacy_gas_price(
+    gas_price: Uint256,
+    options: &[SendTxOption],
+) -> Result<Uint256, Web3Error> {
+    let mut result = gas_price;
+    for option in options {
+        match option {
+            SendTxOption::GasMaxFee(gp) | SendTxOption::GasPrice(gp) => result = *gp,
+            SendTxOption::GasPriceMultiplier(gm) | SendTxOption::GasMaxFeeMultiplier(gm) => {
+                result = multiply_fee(gas_price, *gm)
+            }
+            SendTxOption::GasPriorityFee(_) => {
+                return Err(Web3Error::BadInput(
+                    "Invalid option for legacy tx".to_string(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+/// Multiplies a fee by `multiplier` rounded to a whole percentage, the fee itself is scaled
+/// with integer math so large fees keep their precision. The result is rounded up
+pub(crate) fn multiply_fee(fee: Uint256, multiplier: f32) -> Uint256 {
+    let percent: Uint256 = ((multiplier * 100.0).round() as u32).into();
+    let hundred: Uint256 = 100u8.into();
+    (fee * percent + hundred - 1u8.into()) / hundred
+}
+
+/// Error fragments of nodes refusing a transaction for reasons that prove it never entered
+/// the mempool, matched case insensitively. Errors about the nonce, replacements or already
+/// known transactions are deliberately absent since those mean the nonce is taken
+const TX_REJECTED_ERRORS: [&str; 7] = [
+    "insufficient funds",
+    "intrinsic gas too low",
+    "execution reverted",
+    "exceeds block gas limit",
+    "fee cap less than block base fee",
+    "max fee per gas less than block base fee",
+    "exceeds the configured cap",
+];
+
+/// Returns true if a broadcast failed in a way that proves the transaction was not accepted,
+/// so its nonce can safely be used again
+pub(crate) fn is_tx_rejected_error(error: &Web3Error) -> bool {
+    match error {
+        Web3Error::TransactionReverted { .. } => true,
+        Web3Error::JsonRpcError { message, .. } => {
+            let message = message.to_lowercase();
+            TX_REJECTED_ERRORS.iter().any(|e| message.contains(e))
+        }
+        _ => false,
+    }
+}
+
 struct SimulatedGas {
     limit: Uint256,
     price: Uint256,
@@ -1160,3 +1607,57 @@ fn url_matching() {
     assert_eq!(&matched[1], "https://trx.getblock.io/mainnet/fullnode");
     assert_eq!(&matched[2], "9ec2f6d8-9cec-4157-93d4-f44b1b7418d8");
 }
+
+#[test]
+fn test_fee_options() {
+    let base_fee: Uint256 = 100u8.into();
+    assert_eq!(eip1559_fees(base_fee, &[]), (200u8.into(), 1u8.into()));
+    assert_eq!(
+        eip1559_fees(
+            base_fee,
+            &[
+                SendTxOption::GasMaxFeeMultiplier(1.5),
+                SendTxOption::GasPriorityFee(7u8.into())
+            ]
+        ),
+        (150u8.into(), 7u8.into())
+    );
+
+    assert_eq!(legacy_gas_price(base_fee, &[]).unwrap(), base_fee);
+    assert_eq!(
+        legacy_gas_price(base_fee, &[SendTxOption::GasPriceMultiplier(2.0)]).unwrap(),
+        200u8.into()
+    );
+    assert!(legacy_gas_price(base_fee, &[SendTxOption::GasPriorityFee(1u8.into())]).is_err());
+
+    assert_eq!(multiply_fee(101u8.into(), 1.5), 152u8.into());
+    assert_eq!(
+        multiply_fee(123_456_789_012_345u64.into(), 1.1),
+        135_802_467_913_580u64.into()
+    );
+}
+
+#[test]
+fn test_tx_rejected_errors() {
+    let error = |message: &str| Web3Error::JsonRpcError {
+        code: -32000,
+        message: message.to_string(),
+        data: None,
+    };
+    assert!(is_tx_rejected_error(&error(
+        "insufficient funds for gas * price + value"
+    )));
+    assert!(is_tx_rejected_error(&error("intrinsic gas too low")));
+    for taken in [
+        "replacement transaction underpriced",
+        "known transaction: 0x1234",
+        "transaction already imported",
+        "already known",
+        "nonce too low",
+    ] {
+        assert!(!is_tx_rejected_error(&error(taken)));
+    }
+    assert!(!is_tx_rejected_error(&Web3Error::BadResponse(
+        "insufficient funds".to_string()
+    )));
+}
@@ -0,0 +1,612 @@
+//! This module contains dynamic contract bindings, a `Contract` is built from a standard ABI
+//! JSON and an address and can call functions, send transactions and decode events by name
+//! without hand writing signatures or slicing return data.
+use crate::abi::{decode_tokens, split_list, FromToken, ParamType};
+use crate::client::Web3;
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::signer::Signer;
+use crate::tron_utils::parse_tron_address;
+use crate::types::{Log, SendTxOption};
+use clarity::abi::{encode_call, AbiToken as Token};
+use clarity::constants::zero_address;
+use clarity::utils::hex_str_to_bytes;
+use clarity::{Address, Uint256};
+use heliosphere::core::event::EventData;
+use num256::Int256;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// A function or event parameter as it appears in an ABI JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AbiParam {
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The members of a `tuple` type
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<AbiParam>,
+    /// Only used by event parameters
+    #[serde(default)]
+    pub indexed: bool,
+}
+
+impl AbiParam {
+    pub fn param_type(&self) -> Result<ParamType, Web3Error> {
+        let suffix = match self.kind.strip_prefix("tuple") {
+            Some(suffix) => suffix,
+            None => return self.kind.parse(),
+        };
+        let components: Result<Vec<ParamType>, Web3Error> =
+            self.components.iter().map(|c| c.param_type()).collect();
+        let mut param_type = ParamType::Tuple(components?);
+        // array suffixes such as tuple[] or tuple[2][] wrap the tuple from left to right
+        for dimension in suffix.split_terminator(']') {
+            param_type = match dimension.strip_prefix('[') {
+                Some("") => ParamType::Array(Box::new(param_type)),
+                Some(len) => match len.parse() {
+                    Ok(len) => ParamType::FixedArray(Box::new(param_type), len),
+                    Err(_) => return Err(self.invalid()),
+                },
+                None => return Err(self.invalid()),
+            };
+        }
+        Ok(param_type)
+    }
+
+    fn invalid(&self) -> Web3Error {
+        Web3Error::BadInput(format!("Invalid ABI type {}", self.kind))
+    }
+}
+
+fn param_types(params: &[AbiParam]) -> Result<Vec<ParamType>, Web3Error> {
+    params.iter().map(|p| p.param_type()).collect()
+}
+
+fn signature(name: &str, params: &[AbiParam]) -> Result<String, Web3Error> {
+    let types: Vec<String> = param_types(params)?.iter().map(|t| t.to_string()).collect();
+    Ok(format!("{name}({})", types.join(",")))
+}
+
+/// A function entry of an ABI JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AbiFunction {
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default)]
+    pub outputs: Vec<AbiParam>,
+    #[serde(rename = "stateMutability", default)]
+    pub state_mutability: String,
+}
+
+impl AbiFunction {
+    /// The canonical signature, for example `transfer(address,uint256)`
+    pub fn signature(&self) -> Result<String, Web3Error> {
+        signature(&self.name, &self.inputs)
+    }
+
+    /// Decodes the return data of a call to this function
+    pub fn decode_output(&self, data: &[u8]) -> Result<Vec<Token>, Web3Error> {
+        decode_tokens(&param_types(&self.outputs)?, data)
+    }
+}
+
+/// An event entry of an ABI JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AbiEvent {
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+impl AbiEvent {
+    /// The canonical signature, for example `Transfer(address,address,uint256)`
+    pub fn signature(&self) -> Result<String, Web3Error> {
+        signature(&self.name, &self.inputs)
+    }
+
+    /// The first topic of logs of this event, the hash of its signature
+    pub fn topic(&self) -> Result<[u8; 32], Web3Error> {
+        Ok(keccak256(self.signature()?.as_bytes()))
+    }
+
+    /// Returns true if `log` was produced by this event, anonymous events can only be told
+    /// apart by their number of indexed parameters
+    pub fn matches(&self, log: &Log) -> bool {
+        let indexed = self.inputs.iter().filter(|p| p.indexed).count();
+        if self.anonymous {
+            return log.topics.len() == indexed;
+        }
+        match (log.topics.first(), self.topic()) {
+            (Some(topic), Ok(expected)) => {
+                topic.as_slice() == expected && log.topics.len() == indexed + 1
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes `log` into a `DecodedEvent`, see `decode_params`
+    pub fn decode(&self, log: &Log) -> Result<DecodedEvent, Web3Error> {
+        Ok(DecodedEvent {
+            name: self.name.clone(),
+            params: self.decode_params(log)?,
+            log: log.clone(),
+        })
+    }
+
+    /// Decodes the parameters of `log` by name, in declaration order. Indexed parameters of
+    /// reference types (strings, bytes, arrays and tuples) are stored as their hash and
+    /// decoded as 32 `Bytes`.
+    pub fn decode_params(&self, log: &Log) -> Result<Vec<(String, Token)>, Web3Error> {
+        if !self.matches(log) {
+            return Err(Web3Error::InvalidEventLog(format!(
+                "Log is not a {} event",
+                self.name
+            )));
+        }
+        let mut topics = log.topics.iter().skip(if self.anonymous { 0 } else { 1 });
+        let data_params: Vec<&AbiParam> = self.inputs.iter().filter(|p| !p.indexed).collect();
+        let data_types: Result<Vec<ParamType>, Web3Error> =
+            data_params.iter().map(|p| p.param_type()).collect();
+        let mut data_tokens = decode_tokens(&data_types?, &log.data)?.into_iter();
+
+        let mut params = Vec::with_capacity(self.inputs.len());
+        for param in self.inputs.iter() {
+            let token = if param.indexed {
+                // matches() checked the number of topics
+                let topic = topics.next().unwrap();
+                match param.param_type()? {
+                    ParamType::Bytes
+                    | ParamType::String
+                    | ParamType::Array(_)
+                    | ParamType::FixedArray(_, _)
+                    | ParamType::Tuple(_) => Token::Bytes(topic.to_vec()),
+                    param_type => decode_tokens(&[param_type], topic)?.remove(0),
+                }
+            } else {
+                data_tokens.next().unwrap()
+            };
+            params.push((param.name.clone(), token));
+        }
+        Ok(params)
+    }
+
+    /// Decodes the parameters of a Tron event by name, in declaration order. Tron nodes
+    /// return events already decoded as strings keyed by parameter name (or position if the
+    /// parameter is unnamed), these are converted back into tokens. Arrays and tuples are
+    /// not supported.
+    pub fn decode_tron_params(&self, event: &EventData) -> Result<Vec<(String, Token)>, Web3Error> {
+        let event = match serde_json::to_value(event) {
+            Ok(event) => event,
+            Err(e) => return Err(Web3Error::InvalidEventLog(e.to_string())),
+        };
+        if let Some(name) = event.get("event_name").and_then(Value::as_str) {
+            if name != self.name {
+                return Err(Web3Error::InvalidEventLog(format!(
+                    "Event {name} is not a {} event",
+                    self.name
+                )));
+            }
+        }
+        let result = match event.get("result").and_then(Value::as_object) {
+            Some(result) => result,
+            None => {
+                return Err(Web3Error::InvalidEventLog(
+                    "Tron event has no result".to_string(),
+                ))
+            }
+        };
+
+        let mut params = Vec::with_capacity(self.inputs.len());
+        for (i, param) in self.inputs.iter().enumerate() {
+            let value = result
+                .get(&param.name)
+                .or_else(|| result.get(&i.to_string()))
+                .ok_or_else(|| {
+                    Web3Error::InvalidEventLog(format!(
+                        "Tron event has no value for {}",
+                        param.name
+                    ))
+                })?;
+            let token = tron_value_to_token(&param.param_type()?, value)?;
+            params.push((param.name.clone(), token));
+        }
+        Ok(params)
+    }
+}
+
+impl FromStr for AbiEvent {
+    type Err = Web3Error;
+
+    /// Parses a human readable event signature with `indexed` markers and optional parameter
+    /// names, such as `Transfer(address indexed from, address indexed to, uint256 value)`.
+    /// Anonymous events are marked as in Solidity, `Foo(uint256 a) anonymous`. Unnamed
+    /// parameters are named by their position.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Web3Error::BadInput(format!("Invalid event signature {s}"));
+        let s = s.trim();
+        let (s, trailing_anonymous) = match s.strip_suffix("anonymous") {
+            Some(rest) if rest.trim_end().ends_with(')') => (rest.trim_end(), true),
+            _ => (s, false),
+        };
+        let (name, params) = s
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(invalid)?;
+        // `Foo anonymous(uint256 a)` is accepted as well
+        let (name, anonymous) = match name.trim().strip_suffix(" anonymous") {
+            Some(name) => (name.trim(), true),
+            None => (name.trim(), trailing_anonymous),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut inputs = Vec::new();
+        for (i, param) in split_list(params)?.into_iter().enumerate() {
+            let mut words: Vec<&str> = param.split_whitespace().collect();
+            let indexed = words.contains(&"indexed");
+            words.retain(|w| *w != "indexed");
+            // a trailing identifier after a complete type is the parameter name
+            let name = match words.split_last() {
+                Some((last, rest))
+                    if !rest.is_empty()
+                        && last.chars().all(|c| c.is_alphanumeric() || c == '_')
+                        && rest.join(" ").parse::<ParamType>().is_ok() =>
+                {
+                    words.pop().unwrap().to_string()
+                }
+                _ => i.to_string(),
+            };
+            let kind = words.join(" ");
+            // validate the type now rather than when decoding
+            kind.parse::<ParamType>()?;
+            inputs.push(AbiParam {
+                name,
+                kind,
+                components: Vec::new(),
+                indexed,
+            });
+        }
+        Ok(AbiEvent {
+            name: name.to_string(),
+            inputs,
+            anonymous,
+        })
+    }
+}
+
+/// Converts a Tron event value, which is a string for every type, into a token
+fn tron_value_to_token(param_type: &ParamType, value: &Value) -> Result<Token, Web3Error> {
+    let invalid = || Web3Error::InvalidEventLog(format!("Invalid Tron {param_type} value {value}"));
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return Err(invalid()),
+    };
+    match param_type {
+        ParamType::Address => Ok(Token::Address(parse_tron_address(&text)?)),
+        ParamType::Bool => match text.as_str() {
+            "true" => Ok(Token::Bool(true)),
+            "false" => Ok(Token::Bool(false)),
+            _ => Err(invalid()),
+        },
+        ParamType::Uint(_) => Ok(Token::Uint(text.parse().map_err(|_| invalid())?)),
+        // as in decoded logs a signed integer is carried as its two's complement word
+        ParamType::Int(_) => {
+            let value: Int256 = text.parse().map_err(|_| invalid())?;
+            Ok(Token::Uint(Uint256::from_be_bytes(&value.to_be_bytes())))
+        }
+        ParamType::FixedBytes(_) => Ok(Token::Bytes(parse_hex(&text).ok_or_else(invalid)?)),
+        ParamType::Bytes => Ok(Token::UnboundedBytes(parse_hex(&text).ok_or_else(invalid)?)),
+        ParamType::String => Ok(Token::String(text)),
+        _ => Err(Web3Error::InvalidEventLog(format!(
+            "Tron events with {param_type} parameters are not supported"
+        ))),
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    hex_str_to_bytes(&format!("0x{digits}")).ok()
+}
+
+/// Returns the parameter called `name` of a decoded event converted to `T`, this is used
+/// by `#[derive(ContractEvent)]`
+pub fn event_param<T: FromToken>(params: &[(String, Token)], name: &str) -> Result<T, Web3Error> {
+    match params.iter().find(|(param, _)| param == name) {
+        Some((_, token)) => T::from_token(token.clone()),
+        None => Err(Web3Error::InvalidEventLog(format!(
+            "Event has no parameter {name}"
+        ))),
+    }
+}
+
+/// An event decoded from a log, `params` are in declaration order
+#[derive(Debug, Clone)]
+pub struct DecodedEvent {
+    pub name: String,
+    pub params: Vec<(String, Token)>,
+    pub log: Log,
+}
+
+impl DecodedEvent {
+    /// Returns the value of the parameter called `name`
+    pub fn param(&self, name: &str) -> Option<&Token> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, token)| token)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum AbiItem {
+    Function(AbiFunction),
+    Event(AbiEvent),
+    #[serde(other)]
+    Other,
+}
+
+/// A contract at `address` described by an ABI JSON
+#[derive(Clone)]
+pub struct Contract {
+    web3: Web3,
+    address: Address,
+    caller: Address,
+    functions: Vec<AbiFunction>,
+    events: Vec<AbiEvent>,
+}
+
+impl Contract {
+    /// Builds a contract from the ABI JSON output by solc or hardhat, an array of function,
+    /// event, constructor and error entries
+    pub fn new(web3: Web3, address: Address, abi_json: &str) -> Result<Self, Web3Error> {
+        let items: Vec<AbiItem> = match serde_json::from_str(abi_json) {
+            Ok(items) => items,
+            Err(e) => return Err(Web3Error::BadInput(format!("Invalid ABI JSON {e}"))),
+        };
+        let mut functions = Vec::new();
+        let mut events = Vec::new();
+        for item in items {
+            match item {
+                AbiItem::Function(function) => functions.push(function),
+                AbiItem::Event(event) => events.push(event),
+                AbiItem::Other => {}
+            }
+        }
+        Ok(Contract {
+            web3,
+            address,
+            caller: zero_address(),
+            functions,
+            events,
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Sets the `from` address used by `call`, the zero address by default
+    pub fn set_caller(&mut self, caller: Address) {
+        self.caller = caller;
+    }
+
+    /// Finds a function by name, overloaded functions are told apart by the number of
+    /// arguments or can be selected by their full signature such as `transfer(address,uint256)`
+    pub fn function(&self, name: &str, args: usize) -> Result<&AbiFunction, Web3Error> {
+        let mut candidates = self.functions.iter().filter(|f| {
+            if name.contains('(') {
+                f.signature().map(|s| s == name).unwrap_or(false)
+            } else {
+                f.name == name && f.inputs.len() == args
+            }
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(function), None) if function.inputs.len() == args => Ok(function),
+            (Some(function), None) => Err(Web3Error::BadInput(format!(
+                "{name} takes {} arguments, {args} provided",
+                function.inputs.len()
+            ))),
+            (Some(_), Some(_)) => Err(Web3Error::BadInput(format!(
+                "{name} is overloaded, use its full signature"
+            ))),
+            (None, _) => Err(Web3Error::BadInput(format!(
+                "No function {name} with {args} arguments"
+            ))),
+        }
+    }
+
+    /// Finds an event by name
+    pub fn event(&self, name: &str) -> Result<&AbiEvent, Web3Error> {
+        match self.events.iter().find(|e| e.name == name) {
+            Some(event) => Ok(event),
+            None => Err(Web3Error::BadInput(format!("No event {name}"))),
+        }
+    }
+
+    /// ABI encodes a call to `fn_name` with `args`
+    pub fn encode_call(&self, fn_name: &str, args: &[Token]) -> Result<Vec<u8>, Web3Error> {
+        let function = self.function(fn_name, args.len())?;
+        Ok(encode_call(&function.signature()?, args)?)
+    }
+
+    /// Calls `fn_name` with `eth_call` and returns its decoded outputs
+    pub async fn call(&self, fn_name: &str, args: &[Token]) -> Result<Vec<Token>, Web3Error> {
+        let function = self.function(fn_name, args.len())?;
+        let payload = encode_call(&function.signature()?, args)?;
+        let result = self
+            .web3
+            .simulate_transaction(self.address, payload, self.caller, None)
+            .await?;
+        function.decode_output(&result)
+    }
+
+    /// Sends a transaction calling `fn_name` with `args`, `options` are the same as for
+    /// `send_transaction`. Returns the transaction hash.
+    pub async fn send(
+        &self,
+        fn_name: &str,
+        args: &[Token],
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<Uint256, Web3Error> {
+        self.send_with_value(fn_name, args, 0u8.into(), signer, options)
+            .await
+    }
+
+    /// Like `send` but also transfers `value` to the contract, for payable functions
+    pub async fn send_with_value(
+        &self,
+        fn_name: &str,
+        args: &[Token],
+        value: Uint256,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<Uint256, Web3Error> {
+        let function = self.function(fn_name, args.len())?;
+        let own_address = signer.address();
+        self.web3
+            .send_transaction(
+                self.address,
+                &function.signature()?,
+                args,
+                value,
+                own_address,
+                signer,
+                options,
+            )
+            .await
+    }
+
+    /// Decodes every log in `logs` emitted by this contract as the event `name`, logs of
+    /// other contracts or events are skipped
+    pub fn events(&self, name: &str, logs: &[Log]) -> Result<Vec<DecodedEvent>, Web3Error> {
+        let event = self.event(name)?;
+        logs.iter()
+            .filter(|log| log.address == self.address && event.matches(log))
+            .map(|log| event.decode(log))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::AbiTokenExt;
+    use std::time::Duration;
+
+    const ABI: &str = r#"[
+        {"type": "constructor", "inputs": [{"name": "supply", "type": "uint256"}]},
+        {"type": "function", "name": "balanceOf", "stateMutability": "view",
+         "inputs": [{"name": "owner", "type": "address"}],
+         "outputs": [{"name": "", "type": "uint256"}]},
+        {"type": "function", "name": "transfer", "stateMutability": "nonpayable",
+         "inputs": [{"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}],
+         "outputs": [{"name": "", "type": "bool"}]},
+        {"type": "function", "name": "swap", "stateMutability": "payable",
+         "inputs": [{"name": "params", "type": "tuple[]", "components": [
+             {"name": "token", "type": "address"}, {"name": "fee", "type": "uint24"}]}],
+         "outputs": []},
+        {"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+             {"name": "from", "type": "address", "indexed": true},
+             {"name": "to", "type": "address", "indexed": true},
+             {"name": "value", "type": "uint256", "indexed": false}]},
+        {"type": "error", "name": "InsufficientBalance", "inputs": []}
+    ]"#;
+
+    #[test]
+    fn test_contract_abi() {
+        let web3 = Web3::new("http://localhost:8545", Duration::from_secs(5));
+        let address: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let contract = Contract::new(web3, address, ABI).unwrap();
+
+        assert_eq!(
+            contract.function("swap", 1).unwrap().signature().unwrap(),
+            "swap((address,uint24)[])"
+        );
+        assert!(contract.function("transfer", 1).is_err());
+        assert!(contract.function("transfer(address,uint256)", 2).is_ok());
+        let payload = contract
+            .encode_call("balanceOf", &[address.into()])
+            .unwrap();
+        assert_eq!(payload[..4], [0x70, 0xa0, 0x82, 0x31]);
+
+        let log: Log = serde_json::from_value(serde_json::json!({
+            "address": "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "topics": [
+                "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                "0x000000000000000000000000f39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+                "0x0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f"
+            ],
+            "data": "0x00000000000000000000000000000000000000000000000000000000000003e8"
+        }))
+        .unwrap();
+        let events = contract.events("Transfer", &[log]).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0]
+                .param("to")
+                .cloned()
+                .unwrap()
+                .into_address()
+                .unwrap(),
+            address
+        );
+        assert_eq!(
+            events[0]
+                .param("value")
+                .cloned()
+                .unwrap()
+                .into_uint()
+                .unwrap(),
+            1000u16.into()
+        );
+    }
+
+    #[test]
+    fn test_parse_event_signature() {
+        let event: AbiEvent = "Transfer(address indexed from, address indexed to, uint256 value)"
+            .parse()
+            .unwrap();
+        let contract = Contract::new(
+            Web3::new("http://localhost:8545", Duration::from_secs(5)),
+            zero_address(),
+            ABI,
+        )
+        .unwrap();
+        assert_eq!(&event, contract.event("Transfer").unwrap());
+
+        let event: AbiEvent = "Swap(address indexed, (address, uint24)[] route, int256)"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            event.signature().unwrap(),
+            "Swap(address,(address,uint24)[],int256)"
+        );
+        let names: Vec<&str> = event.inputs.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["0", "route", "2"]);
+        assert!(event.inputs[0].indexed);
+
+        // anonymous events as Solidity writes them
+        let event: AbiEvent = "Foo(uint256 a) anonymous".parse().unwrap();
+        assert!(event.anonymous);
+        assert_eq!(event.name, "Foo");
+        assert_eq!(event.inputs[0].name, "a");
+        let event: AbiEvent = "Foo(uint256 anonymous)".parse().unwrap();
+        assert!(!event.anonymous);
+        assert_eq!(event.inputs[0].name, "anonymous");
+
+        assert!("Transfer(address indexed from".parse::<AbiEvent>().is_err());
+        assert!("Transfer(foo bar)".parse::<AbiEvent>().is_err());
+    }
+}
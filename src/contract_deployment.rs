@@ -0,0 +1,126 @@
+//! This module contains contract deployment and the CREATE / CREATE2 address derivations, so the
+//! address of a contract can be known before (or without) deploying it.
+use crate::client::Web3;
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::rlp::{encode_address, encode_list, encode_uint};
+use crate::signer::Signer;
+use crate::types::SendTxOption;
+use clarity::abi::{encode_tokens, AbiToken as Token};
+use clarity::{Address, Uint256};
+use std::time::Duration;
+
+/// The address of a contract created with CREATE, keccak256(rlp([sender, nonce]))[12..]
+pub fn create_address(sender: Address, nonce: Uint256) -> Address {
+    let payload = encode_list(&[encode_address(&sender), encode_uint(nonce)]);
+    hash_to_address(&payload)
+}
+
+/// The address of a contract created with CREATE2,
+/// keccak256(0xff ‖ deployer ‖ salt ‖ keccak256(init_code))[12..]
+pub fn create2_address(deployer: Address, salt: [u8; 32], init_code_hash: [u8; 32]) -> Address {
+    let mut payload = vec![0xff];
+    payload.extend_from_slice(deployer.as_bytes());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&init_code_hash);
+    hash_to_address(&payload)
+}
+
+fn hash_to_address(payload: &[u8]) -> Address {
+    Address::from_slice(&keccak256(payload)[12..]).expect("20 bytes is a valid address")
+}
+
+impl Web3 {
+    /// Deploys a contract and waits up to `wait_timeout` for it to be mined, returns the
+    /// address of the new contract from the transaction receipt. `bytecode` is the creation
+    /// code as output by the compiler, the ABI encoded constructor arguments are appended to it.
+    /// `options` are the same as for `send_transaction`. Not supported on Tron.
+    pub async fn deploy_contract(
+        &self,
+        bytecode: Vec<u8>,
+        constructor_tokens: &[Token],
+        value: Uint256,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+        wait_timeout: Duration,
+    ) -> Result<Address, Web3Error> {
+        if self.is_tron() {
+            return Err(Web3Error::BadInput(
+                "Contract deployment is not supported on Tron".to_string(),
+            ));
+        }
+
+        let mut data = bytecode;
+        data.extend_from_slice(&encode_tokens(constructor_tokens));
+        let own_address = signer.address();
+        let tx_hash = self
+            .send_transaction_data(None, data, value, own_address, signer, options)
+            .await?;
+
+        self.eth_wait_for_transaction(tx_hash, wait_timeout, None)
+            .await?;
+        let receipt = match self.eth_get_transaction_receipt(tx_hash).await? {
+            Some(receipt) => receipt,
+            None => {
+                return Err(Web3Error::BadResponse(format!(
+                    "No receipt for mined transaction {tx_hash:#066x}"
+                )))
+            }
+        };
+        if !receipt.is_success() {
+            return Err(Web3Error::ContractCallError(format!(
+                "Contract deployment {tx_hash:#066x} reverted"
+            )));
+        }
+        match receipt.contract_address {
+            Some(address) => Ok(address),
+            None => Err(Web3Error::BadResponse(format!(
+                "Receipt for {tx_hash:#066x} has no contract address"
+            ))),
+        }
+    }
+}
+
+#[test]
+fn test_create_addresses() {
+    use clarity::constants::zero_address;
+
+    let deployer: Address = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        .parse()
+        .unwrap();
+    // the first two contracts deployed by the default hardhat account
+    assert_eq!(
+        create_address(deployer, 0u8.into()),
+        "0x5fbdb2315678afecb367f032d93f642f64180aa3"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(
+        create_address(deployer, 1u8.into()),
+        "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512"
+            .parse()
+            .unwrap()
+    );
+
+    // examples from EIP-1014
+    assert_eq!(
+        create2_address(zero_address(), [0u8; 32], keccak256(&[0x00])),
+        "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"
+            .parse()
+            .unwrap()
+    );
+    let mut salt = [0u8; 32];
+    salt[28..].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+    assert_eq!(
+        create2_address(
+            "0x00000000000000000000000000000000deadbeef"
+                .parse()
+                .unwrap(),
+            salt,
+            keccak256(&[0xde, 0xad, 0xbe, 0xef])
+        ),
+        "0x60f3f640a8508fc6a86d45df051962668e1e8ac7"
+            .parse()
+            .unwrap()
+    );
+}
@@ -0,0 +1,260 @@
+//! This module implements EIP-712 hashing of typed structured data, the digest produced here is
+//! what wallets sign for `eth_signTypedData_v4`. The input is the same JSON document those
+//! wallets accept so it can be handed to a remote signer unchanged.
+//!
+//! See more https://eips.ethereum.org/EIPS/eip-712
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use clarity::utils::hex_str_to_bytes;
+use clarity::{Address, Uint256};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+/// A member of an EIP-712 struct type
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+}
+
+/// Typed structured data, `types` must contain the `EIP712Domain` type along with
+/// the primary type and every type it references
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedDataField>>,
+    #[serde(rename = "primaryType")]
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+impl TypedData {
+    /// hashStruct of the domain
+    pub fn domain_separator(&self) -> Result<[u8; 32], Web3Error> {
+        self.hash_struct("EIP712Domain", &self.domain)
+    }
+
+    /// hashStruct of the message
+    pub fn struct_hash(&self) -> Result<[u8; 32], Web3Error> {
+        self.hash_struct(&self.primary_type, &self.message)
+    }
+
+    /// The digest to sign, keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))
+    pub fn signing_hash(&self) -> Result<[u8; 32], Web3Error> {
+        let mut payload = vec![0x19, 0x01];
+        payload.extend_from_slice(&self.domain_separator()?);
+        payload.extend_from_slice(&self.struct_hash()?);
+        Ok(keccak256(&payload))
+    }
+
+    /// Encodes a struct type as `Name(type name,...)` followed by every type it
+    /// references in alphabetical order
+    pub fn encode_type(&self, type_name: &str) -> Result<String, Web3Error> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(type_name, &mut dependencies);
+        dependencies.remove(type_name);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(type_name).chain(dependencies.iter().map(String::as_str)) {
+            let fields = self.fields(name)?;
+            let members: Vec<String> = fields
+                .iter()
+                .map(|f| format!("{} {}", f.field_type, f.name))
+                .collect();
+            encoded += &format!("{}({})", name, members.join(","));
+        }
+        Ok(encoded)
+    }
+
+    fn fields(&self, type_name: &str) -> Result<&Vec<TypedDataField>, Web3Error> {
+        match self.types.get(type_name) {
+            Some(fields) => Ok(fields),
+            None => Err(Web3Error::BadInput(format!(
+                "EIP712 type {type_name} is not defined"
+            ))),
+        }
+    }
+
+    fn collect_dependencies(&self, type_name: &str, found: &mut BTreeSet<String>) {
+        let fields = match self.types.get(type_name) {
+            Some(fields) => fields,
+            None => return,
+        };
+        if !found.insert(type_name.to_string()) {
+            return;
+        }
+        for field in fields {
+            self.collect_dependencies(base_type(&field.field_type), found);
+        }
+    }
+
+    fn hash_struct(&self, type_name: &str, value: &Value) -> Result<[u8; 32], Web3Error> {
+        let mut encoded = keccak256(self.encode_type(type_name)?.as_bytes()).to_vec();
+        for field in self.fields(type_name)? {
+            let member = match value.get(&field.name) {
+                Some(member) => member,
+                None => {
+                    return Err(Web3Error::BadInput(format!(
+                        "EIP712 value for {type_name} is missing {}",
+                        field.name
+                    )))
+                }
+            };
+            encoded.extend_from_slice(&self.encode_value(&field.field_type, member)?);
+        }
+        Ok(keccak256(&encoded))
+    }
+
+    /// Encodes a single member into its 32 byte slot, dynamic values are hashed
+    fn encode_value(&self, field_type: &str, value: &Value) -> Result<[u8; 32], Web3Error> {
+        let bad_value = || {
+            Web3Error::BadInput(format!(
+                "Invalid EIP712 value {value} for type {field_type}"
+            ))
+        };
+
+        if let Some(inner) = field_type.strip_suffix(']') {
+            let inner = &inner[..inner.rfind('[').ok_or_else(bad_value)?];
+            let mut encoded = Vec::new();
+            for item in value.as_array().ok_or_else(bad_value)? {
+                encoded.extend_from_slice(&self.encode_value(inner, item)?);
+            }
+            return Ok(keccak256(&encoded));
+        }
+        if self.types.contains_key(field_type) {
+            return self.hash_struct(field_type, value);
+        }
+
+        let mut word = [0u8; 32];
+        match field_type {
+            "string" => return Ok(keccak256(value.as_str().ok_or_else(bad_value)?.as_bytes())),
+            "bytes" => return Ok(keccak256(&parse_hex(value).ok_or_else(bad_value)?)),
+            "bool" => word[31] = value.as_bool().ok_or_else(bad_value)? as u8,
+            "address" => {
+                let address = Address::from_str(value.as_str().ok_or_else(bad_value)?)
+                    .map_err(|_| bad_value())?;
+                word[12..].copy_from_slice(address.as_bytes());
+            }
+            _ => {
+                if let Some(size) = field_type.strip_prefix("bytes") {
+                    let size: usize = size.parse().map_err(|_| bad_value())?;
+                    let bytes = parse_hex(value).ok_or_else(bad_value)?;
+                    if size > 32 || bytes.len() > size {
+                        return Err(bad_value());
+                    }
+                    word[..bytes.len()].copy_from_slice(&bytes);
+                } else if field_type.starts_with("uint") || field_type.starts_with("int") {
+                    word = parse_int(value).ok_or_else(bad_value)?;
+                } else {
+                    return Err(Web3Error::BadInput(format!(
+                        "Unsupported EIP712 type {field_type}"
+                    )));
+                }
+            }
+        }
+        Ok(word)
+    }
+}
+
+/// Strips any array suffixes from a type
+fn base_type(field_type: &str) -> &str {
+    match field_type.find('[') {
+        Some(index) => &field_type[..index],
+        None => field_type,
+    }
+}
+
+fn parse_hex(value: &Value) -> Option<Vec<u8>> {
+    hex_str_to_bytes(value.as_str()?).ok()
+}
+
+/// Parses a JSON number or a decimal or hex string into a 32 byte two's complement word
+fn parse_int(value: &Value) -> Option<[u8; 32]> {
+    let (negative, magnitude) = match value {
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(v), _) => (false, Uint256::from(v)),
+            (None, Some(v)) => (true, Uint256::from(v.unsigned_abs())),
+            _ => return None,
+        },
+        Value::String(s) => match s.strip_prefix('-') {
+            Some(s) => (true, Uint256::from_str(s).ok()?),
+            None => (false, Uint256::from_str(s).ok()?),
+        },
+        _ => return None,
+    };
+    let mut word = magnitude.to_be_bytes();
+    if negative {
+        // two's complement, invert and add one
+        for byte in word.iter_mut() {
+            *byte = !*byte;
+        }
+        for byte in word.iter_mut().rev() {
+            let (sum, overflow) = byte.overflowing_add(1);
+            *byte = sum;
+            if !overflow {
+                break;
+            }
+        }
+    }
+    Some(word)
+}
+
+#[test]
+fn test_eip712_mail_example() {
+    use clarity::utils::bytes_to_hex_str;
+
+    // the example from the EIP
+    let typed_data: TypedData = serde_json::from_str(
+        r#"{
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Person": [
+                    {"name": "name", "type": "string"},
+                    {"name": "wallet", "type": "address"}
+                ],
+                "Mail": [
+                    {"name": "from", "type": "Person"},
+                    {"name": "to", "type": "Person"},
+                    {"name": "contents", "type": "string"}
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+                "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+                "contents": "Hello, Bob!"
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        typed_data.encode_type("Mail").unwrap(),
+        "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&typed_data.domain_separator().unwrap()),
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&typed_data.struct_hash().unwrap()),
+        "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&typed_data.signing_hash().unwrap()),
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+    );
+}
@@ -1,7 +1,10 @@
 //! This module contains utility functions for interacting with ERC20 tokens and contracts
+use crate::abi::{decode_single, AbiTokenExt};
 use crate::jsonrpc::error::Web3Error;
+use crate::multicall::{Call3, CallResult};
+use crate::signer::Signer;
 use crate::{client::Web3, types::SendTxOption};
-use clarity::{abi::encode_call, PrivateKey as EthPrivateKey};
+use clarity::abi::encode_call;
 use clarity::{Address, Uint256};
 use num_traits::Bounded;
 use std::time::Duration;
@@ -29,14 +32,7 @@ impl Web3 {
             .simulate_transaction(erc20, payload, own_address, None)
             .await?;
 
-        let allowance = Uint256::from_be_bytes(match allowance.get(0..32) {
-            Some(val) => val,
-            None => {
-                return Err(Web3Error::ContractCallError(
-                    "erc20 allowance(address, address) failed".to_string(),
-                ))
-            }
-        });
+        let allowance = decode_single("uint256", &allowance)?.into_uint()?;
 
         // Check if the allowance remaining is greater than half of a Uint256- it's as good
         // a test as any.
@@ -55,12 +51,12 @@ impl Web3 {
     pub async fn approve_erc20_transfers(
         &self,
         erc20: Address,
-        eth_private_key: EthPrivateKey,
+        signer: impl Signer,
         target_contract: Address,
         timeout: Option<Duration>,
         options: Vec<SendTxOption>,
     ) -> Result<Uint256, Web3Error> {
-        let own_address = eth_private_key.to_address();
+        let own_address = signer.address();
 
         let txid = self
             .send_transaction(
@@ -69,7 +65,7 @@ impl Web3 {
                 &[target_contract.into(), Uint256::max_value().into()],
                 0u32.into(),
                 own_address,
-                eth_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -96,11 +92,11 @@ impl Web3 {
         amount: Uint256,
         recipient: Address,
         erc20: Address,
-        sender_private_key: EthPrivateKey,
+        signer: impl Signer,
         wait_timeout: Option<Duration>,
         options: Vec<SendTxOption>,
     ) -> Result<Uint256, Web3Error> {
-        let sender_address = sender_private_key.to_address();
+        let sender_address = signer.address();
 
         // if the user sets a gas limit we should honor it, if they don't we
         // should add the default
@@ -123,7 +119,7 @@ impl Web3 {
                 &[recipient.into(), amount.into()],
                 0u32.into(),
                 sender_address,
-                sender_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -197,14 +193,7 @@ impl Web3 {
             .simulate_transaction(erc20, payload, requester_address, height)
             .await?;
 
-        Ok(Uint256::from_be_bytes(match balance.get(0..32) {
-            Some(val) => val,
-            None => {
-                return Err(Web3Error::ContractCallError(
-                    "Bad response from ERC20 balance".to_string(),
-                ))
-            }
-        }))
+        decode_single("uint256", &balance)?.into_uint()
     }
 
     pub async fn get_erc20_name(
@@ -217,18 +206,7 @@ impl Web3 {
             .simulate_transaction(erc20, payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(name) {
-            Ok(mut val) => {
-                // the value returned is actually in Ethereum ABI encoded format
-                // stripping control characters is an easy way to strip off the encoding
-                val.retain(|v| !v.is_control());
-                let val = val.trim().to_string();
-                Ok(val)
-            }
-            Err(_e) => Err(Web3Error::ContractCallError(
-                "name is not valid utf8".to_string(),
-            )),
-        }
+        decode_erc20_string(&name)
     }
 
     pub async fn get_erc20_symbol(
@@ -241,18 +219,7 @@ impl Web3 {
             .simulate_transaction(erc20, payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(symbol) {
-            Ok(mut val) => {
-                // the value returned is actually in Ethereum ABI encoded format
-                // stripping control characters is an easy way to strip off the encoding
-                val.retain(|v| !v.is_control());
-                let val = val.trim().to_string();
-                Ok(val)
-            }
-            Err(_e) => Err(Web3Error::ContractCallError(
-                "name is not valid utf8".to_string(),
-            )),
-        }
+        decode_erc20_string(&symbol)
     }
 
     pub async fn get_erc20_decimals(
@@ -294,6 +261,96 @@ impl Web3 {
             }
         }))
     }
+
+    /// Queries many ERC20 balances with a single Multicall3 call, `queries` are pairs of
+    /// `(erc20, holder)`. Returns one balance per query in order, None if that query failed.
+    /// The default Multicall3 deployment is used if `multicall` is None.
+    pub async fn get_erc20_balances(
+        &self,
+        queries: &[(Address, Address)],
+        caller_address: Address,
+        multicall: Option<Address>,
+    ) -> Result<Vec<Option<Uint256>>, Web3Error> {
+        let mut calls = Vec::with_capacity(queries.len());
+        for (erc20, holder) in queries {
+            let payload = encode_call("balanceOf(address)", &[(*holder).into()])?;
+            calls.push(Call3::new(*erc20, payload));
+        }
+        let results = self.multicall(&calls, caller_address, multicall).await?;
+        Ok(results.iter().map(decode_erc20_uint).collect())
+    }
+
+    /// Queries the name, symbol, decimals and total supply of many ERC20 tokens with a single
+    /// Multicall3 call. Returns the metadata of each token in order, None if any of its
+    /// queries failed. The default Multicall3 deployment is used if `multicall` is None.
+    pub async fn get_erc20_metadata(
+        &self,
+        erc20s: &[Address],
+        caller_address: Address,
+        multicall: Option<Address>,
+    ) -> Result<Vec<Option<Erc20Metadata>>, Web3Error> {
+        let mut calls = Vec::with_capacity(erc20s.len() * 4);
+        for erc20 in erc20s {
+            for selector in ["name()", "symbol()", "decimals()", "totalSupply()"] {
+                calls.push(Call3::new(*erc20, encode_call(selector, &[])?));
+            }
+        }
+        let results = self.multicall(&calls, caller_address, multicall).await?;
+        Ok(results
+            .chunks(4)
+            .map(|token| {
+                Some(Erc20Metadata {
+                    name: decode_erc20_string(token[0].data()?).ok()?,
+                    symbol: decode_erc20_string(token[1].data()?).ok()?,
+                    decimals: decode_erc20_uint(&token[2])?,
+                    total_supply: decode_erc20_uint(&token[3])?,
+                })
+            })
+            .collect())
+    }
+}
+
+/// The metadata of an ERC20 token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erc20Metadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: Uint256,
+    pub total_supply: Uint256,
+}
+
+/// Decodes the name or symbol of a token, these are strings except for some early tokens
+/// such as MKR which return a zero padded bytes32
+fn decode_erc20_string(data: &[u8]) -> Result<String, Web3Error> {
+    if data.len() == 32 {
+        let bytes = decode_single("bytes32", data)?.into_bytes()?;
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        return match String::from_utf8(bytes[..end].to_vec()) {
+            Ok(val) => Ok(val),
+            Err(_e) => Err(Web3Error::ContractCallError(
+                "name is not valid utf8".to_string(),
+            )),
+        };
+    }
+    decode_single("string", data)?.into_string()
+}
+
+fn decode_erc20_uint(result: &CallResult) -> Option<Uint256> {
+    decode_single("uint256", result.data()?)
+        .ok()?
+        .into_uint()
+        .ok()
+}
+
+#[test]
+fn test_decode_erc20_string() {
+    use clarity::abi::{encode_tokens, AbiToken as Token};
+    let data = encode_tokens(&[Token::String("Dai Stablecoin".to_string())]);
+    assert_eq!(decode_erc20_string(&data).unwrap(), "Dai Stablecoin");
+    // MKR returns its symbol as a bytes32
+    let mut data = b"MKR".to_vec();
+    data.resize(32, 0);
+    assert_eq!(decode_erc20_string(&data).unwrap(), "MKR");
 }
 
 #[test]
@@ -1,9 +1,11 @@
 //! This module contains utility functions for interacting with ERC721 tokens and contracts
+use crate::abi::{decode_single, AbiTokenExt};
 use crate::jsonrpc::error::Web3Error;
+use crate::signer::Signer;
 use crate::{client::Web3, types::SendTxOption};
+use clarity::abi::encode_call;
 use clarity::constants::zero_address;
 use clarity::Address as EthAddress;
-use clarity::{abi::encode_call, PrivateKey as EthPrivateKey};
 use clarity::{abi::AbiToken as Token, Address, Uint256};
 use std::time::Duration;
 use tokio::time::timeout as future_timeout;
@@ -29,19 +31,11 @@ impl Web3 {
             .simulate_transaction(erc721, payload, own_address, None)
             .await?;
 
-        let mut data: [u8; 20] = Default::default();
-        data.copy_from_slice(&val[12..]);
-        let owner_address = EthAddress::from_slice(&data);
-
-        match owner_address {
-            Ok(address_response) => {
-                if address_response == zero_address() {
-                    Ok(None)
-                } else {
-                    Ok(Some(address_response))
-                }
-            }
-            Err(e) => Err(Web3Error::BadResponse(e.to_string())),
+        let approved = decode_single("address", &val)?.into_address()?;
+        if approved == zero_address() {
+            Ok(None)
+        } else {
+            Ok(Some(approved))
         }
     }
 
@@ -58,13 +52,13 @@ impl Web3 {
     pub async fn approve_erc721_transfers(
         &self,
         erc721: Address,
-        eth_private_key: EthPrivateKey,
+        signer: impl Signer,
         target_contract: Address,
         token_id: Uint256,
         timeout: Option<Duration>,
         options: Vec<SendTxOption>,
     ) -> Result<Uint256, Web3Error> {
-        let own_address = eth_private_key.to_address();
+        let own_address = signer.address();
 
         let txid = self
             .send_transaction(
@@ -73,7 +67,7 @@ impl Web3 {
                 &[target_contract.into(), Token::Uint(token_id)],
                 0u32.into(),
                 own_address,
-                eth_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -101,11 +95,11 @@ impl Web3 {
         recipient: Address,
         erc721: Address,
         token_id: Uint256,
-        sender_private_key: EthPrivateKey,
+        signer: impl Signer,
         wait_timeout: Option<Duration>,
         options: Vec<SendTxOption>,
     ) -> Result<Uint256, Web3Error> {
-        let sender_address = sender_private_key.to_address();
+        let sender_address = signer.address();
 
         let mut has_gas_limit = false;
         let mut options = options;
@@ -129,7 +123,7 @@ impl Web3 {
                 ],
                 0u32.into(),
                 sender_address,
-                sender_private_key,
+                signer,
                 options,
             )
             .await?;
@@ -154,18 +148,7 @@ impl Web3 {
             .simulate_transaction(erc721, payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(name) {
-            Ok(mut val) => {
-                // the value returned is actually in Ethereum ABI encoded format
-                // stripping control characters is an easy way to strip off the encoding
-                val.retain(|v| !v.is_control());
-                let val = val.trim().to_string();
-                Ok(val)
-            }
-            Err(_e) => Err(Web3Error::ContractCallError(
-                "name is not valid utf8".to_string(),
-            )),
-        }
+        decode_single("string", &name)?.into_string()
     }
 
     /// Executes EIP-721 symbol() external view returns (string _symbol)
@@ -181,18 +164,7 @@ impl Web3 {
             .simulate_transaction(erc721, payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(symbol) {
-            Ok(mut val) => {
-                // the value returned is actually in Ethereum ABI encoded format
-                // stripping control characters is an easy way to strip off the encoding
-                val.retain(|v| !v.is_control());
-                let val = val.trim().to_string();
-                Ok(val)
-            }
-            Err(_e) => Err(Web3Error::ContractCallError(
-                "name is not valid utf8".to_string(),
-            )),
-        }
+        decode_single("string", &symbol)?.into_string()
     }
 
     /// Executes EIP-721 totalSupply() external view returns (uint256)
@@ -208,14 +180,7 @@ impl Web3 {
             .simulate_transaction(erc721, payload, caller_address, None)
             .await?;
 
-        Ok(Uint256::from_be_bytes(match decimals.get(0..32) {
-            Some(val) => val,
-            None => {
-                return Err(Web3Error::ContractCallError(
-                    "Bad response from ERC721 Total Supply".to_string(),
-                ))
-            }
-        }))
+        decode_single("uint256", &decimals)?.into_uint()
     }
 
     /// Executes EIP-721 tokenURI(uint256 _tokenId) external view returns (string);
@@ -232,18 +197,7 @@ impl Web3 {
             .simulate_transaction(erc721, payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(symbol) {
-            Ok(mut val) => {
-                // the value returned is actually in Ethereum ABI encoded format
-                // stripping control characters is an easy way to strip off the encoding
-                val.retain(|v| !v.is_control());
-                let val = val.trim().to_string();
-                Ok(val)
-            }
-            Err(_e) => Err(Web3Error::ContractCallError(
-                "name is not valid utf8".to_string(),
-            )),
-        }
+        decode_single("string", &symbol)?.into_string()
     }
 
     /// Executes EIP-721 ownerOf(uint256 _tokenId) external view returns (address)
@@ -261,14 +215,7 @@ impl Web3 {
             .simulate_transaction(erc721, payload, own_address, None)
             .await?;
 
-        let mut data: [u8; 20] = Default::default();
-        data.copy_from_slice(&val[12..]);
-        let owner_address = EthAddress::from_slice(&data);
-
-        match owner_address {
-            Ok(address_response) => Ok(address_response),
-            Err(e) => Err(Web3Error::BadResponse(e.to_string())),
-        }
+        decode_single("address", &val)?.into_address()
     }
 }
 
@@ -286,7 +233,7 @@ fn test_erc721_metadata() {
         .unwrap();
     let token_id = 1039_i32;
     let token_id_uint = Uint256::from_be_bytes(&token_id.to_be_bytes());
-    let token_id_uri = ":ipfs://QmeSjSinHpPnmXmspMjwiXyN6zS4E9zccariGR3jxcaWtq/1039";
+    let token_id_uri = "ipfs://QmeSjSinHpPnmXmspMjwiXyN6zS4E9zccariGR3jxcaWtq/1039";
     runner.block_on(async move {
         let num: Uint256 = 1000u32.into();
         assert!(
@@ -1,8 +1,9 @@
 use crate::amm::WETH_CONTRACT_ADDRESS;
+use crate::signer::Signer;
 use crate::{client::Web3, jsonrpc::error::Web3Error};
 use clarity::abi::AbiToken as Token;
 use clarity::Address;
-use clarity::{PrivateKey, Uint256};
+use clarity::Uint256;
 use std::time::Duration;
 use tokio::time::timeout as future_timeout;
 
@@ -11,11 +12,11 @@ impl Web3 {
     pub async fn wrap_eth(
         &self,
         amount: Uint256,
-        secret: PrivateKey,
+        signer: impl Signer,
         weth_address: Option<Address>,
         wait_timeout: Option<Duration>,
     ) -> Result<Uint256, Web3Error> {
-        let own_address = secret.to_address();
+        let own_address = signer.address();
         let sig = "deposit()";
         let tokens = [];
         let weth_address = weth_address.unwrap_or(*WETH_CONTRACT_ADDRESS);
@@ -26,7 +27,7 @@ impl Web3 {
                 &tokens,
                 amount,
                 own_address,
-                secret,
+                signer,
                 vec![],
             )
             .await?;
@@ -40,11 +41,11 @@ impl Web3 {
     pub async fn unwrap_eth(
         &self,
         amount: Uint256,
-        secret: PrivateKey,
+        signer: impl Signer,
         weth_address: Option<Address>,
         wait_timeout: Option<Duration>,
     ) -> Result<Uint256, Web3Error> {
-        let own_address = secret.to_address();
+        let own_address = signer.address();
         let sig = "withdraw(uint256)";
         let tokens = [Token::Uint(amount)];
         let weth_address = weth_address.unwrap_or(*WETH_CONTRACT_ADDRESS);
@@ -55,7 +56,7 @@ impl Web3 {
                 &tokens,
                 0u16.into(),
                 own_address,
-                secret,
+                signer,
                 vec![],
             )
             .await?;
@@ -0,0 +1,673 @@
+//! This file contains an event indexer which follows a set of contract events from a starting
+//! block onward. Only blocks with enough confirmations are processed, progress is persisted as a
+//! checkpoint so indexing resumes where it left off after a restart, and if the checkpointed block
+//! is reorged out the indexer rolls back to a canonical ancestor and tells the handler to do the same.
+use crate::client::Web3;
+use crate::contract::{AbiEvent, DecodedEvent};
+use crate::filter::FilterBuilder;
+use crate::jsonrpc::error::Web3Error;
+use crate::log_fetcher::LogFetchOptions;
+use crate::types::{Log, NewFilter};
+use async_trait::async_trait;
+use clarity::{Address, Uint256};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::sleep as delay_for;
+
+/// The last block processed by an indexer, the hash identifies which branch it was on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub block_number: Uint256,
+    pub block_hash: Uint256,
+}
+
+/// Persistent storage for indexer checkpoints
+pub trait CheckpointStore {
+    /// Returns the saved checkpoint or None if the indexer has never made progress
+    fn load(&self) -> Result<Option<Checkpoint>, Web3Error>;
+    /// Replaces the saved checkpoint
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Web3Error>;
+}
+
+/// Stores the checkpoint as JSON in a file, a missing file means no checkpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileCheckpointStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self) -> Result<Option<Checkpoint>, Web3Error> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let file = fs::read_to_string(&self.path)
+            .map_err(|e| Web3Error::CheckpointError(format!("Failed to read checkpoint {e}")))?;
+        serde_json::from_str(&file)
+            .map(Some)
+            .map_err(|e| Web3Error::CheckpointError(format!("Failed to parse checkpoint {e}")))
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Web3Error> {
+        let json = serde_json::to_string(checkpoint)
+            .map_err(|e| Web3Error::CheckpointError(format!("Failed to encode checkpoint {e}")))?;
+        // write then rename so a crash never leaves a truncated checkpoint behind
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| Web3Error::CheckpointError(format!("Failed to write checkpoint {e}")))
+    }
+}
+
+/// Keeps the checkpoint in memory, useful for tests and indexers that always start fresh
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryCheckpointStore {
+    checkpoint: Option<Checkpoint>,
+}
+
+impl CheckpointStore for MemoryCheckpointStore {
+    fn load(&self) -> Result<Option<Checkpoint>, Web3Error> {
+        Ok(self.checkpoint)
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Web3Error> {
+        self.checkpoint = Some(*checkpoint);
+        Ok(())
+    }
+}
+
+/// Receives the events found by an EventIndexer
+#[async_trait(?Send)]
+pub trait EventHandler {
+    /// Called with the events of every block up to and including `checkpoint`, in order. The
+    /// checkpoint is only saved once this returns Ok, so on error the same events are delivered
+    /// again on the next step
+    async fn handle(
+        &mut self,
+        events: Vec<DecodedEvent>,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), Web3Error>;
+
+    /// Called when previously handled blocks were reorged out, everything handled above
+    /// `checkpoint` must be discarded
+    async fn rollback(&mut self, checkpoint: &Checkpoint) -> Result<(), Web3Error>;
+}
+
+/// The chain queries made by an EventIndexer, implemented by Web3 and by a mock chain in tests
+#[async_trait(?Send)]
+trait IndexerChain {
+    async fn block_number(&self) -> Result<Uint256, Web3Error>;
+    async fn block_hash(&self, number: Uint256) -> Result<Uint256, Web3Error>;
+    async fn logs(
+        &self,
+        filter: NewFilter,
+        from: Uint256,
+        to: Uint256,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error>;
+}
+
+#[async_trait(?Send)]
+impl IndexerChain for Web3 {
+    async fn block_number(&self) -> Result<Uint256, Web3Error> {
+        self.eth_block_number().await
+    }
+
+    async fn block_hash(&self, number: Uint256) -> Result<Uint256, Web3Error> {
+        Ok(self.eth_get_concise_block_by_number(number).await?.hash)
+    }
+
+    async fn logs(
+        &self,
+        filter: NewFilter,
+        from: Uint256,
+        to: Uint256,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error> {
+        self.get_logs_chunked(filter, from, Some(to), options).await
+    }
+}
+
+/// Follows events of one or more contracts and delivers them to an EventHandler
+pub struct EventIndexer<S: CheckpointStore> {
+    filters: Vec<(Address, AbiEvent)>,
+    start_block: Uint256,
+    confirmations: u64,
+    max_blocks_per_step: u64,
+    fetch_options: LogFetchOptions,
+    store: S,
+    checkpoint: Option<Checkpoint>,
+    /// checkpoints of recent steps, oldest first, used to find a canonical ancestor on reorg
+    history: VecDeque<Checkpoint>,
+    history_size: usize,
+}
+
+impl<S: CheckpointStore> EventIndexer<S> {
+    /// Creates an indexer which starts at `start_block` unless `store` already has a checkpoint
+    pub fn new(start_block: Uint256, store: S) -> Result<Self, Web3Error> {
+        let checkpoint = store.load()?;
+        Ok(EventIndexer {
+            filters: Vec::new(),
+            start_block,
+            confirmations: 12,
+            max_blocks_per_step: 10_000,
+            fetch_options: LogFetchOptions::default(),
+            store,
+            checkpoint,
+            history: checkpoint.into_iter().collect(),
+            history_size: 128,
+        })
+    }
+
+    /// Follows `event`, a human readable signature such as
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`, emitted by `contract`
+    pub fn with_event(mut self, contract: Address, event: &str) -> Result<Self, Web3Error> {
+        self.filters.push((contract, event.parse()?));
+        Ok(self)
+    }
+
+    /// Follows an already parsed event, for example one taken from a `Contract`
+    pub fn with_abi_event(mut self, contract: Address, event: AbiEvent) -> Self {
+        self.filters.push((contract, event));
+        self
+    }
+
+    /// Sets the number of blocks that must be built on top of a block before it is processed
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sets the largest block range processed by a single step
+    pub fn with_max_blocks_per_step(mut self, blocks: u64) -> Self {
+        self.max_blocks_per_step = blocks.max(1);
+        self
+    }
+
+    /// Sets how the logs of each step are fetched
+    pub fn with_fetch_options(mut self, options: LogFetchOptions) -> Self {
+        self.fetch_options = options;
+        self
+    }
+
+    /// The last processed block, None if nothing has been processed yet
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint
+    }
+
+    /// Processes the next range of confirmed blocks. Returns the new checkpoint, or None if
+    /// there were no new confirmed blocks or the chain changed while the range was fetched, in
+    /// which case the range is fetched again on the next step. If the checkpointed block is no
+    /// longer canonical the handler is rolled back instead and the ancestor rolled back to is
+    /// returned.
+    pub async fn step(
+        &mut self,
+        web3: &Web3,
+        handler: &mut impl EventHandler,
+    ) -> Result<Option<Checkpoint>, Web3Error> {
+        self.step_on(web3, handler).await
+    }
+
+    async fn step_on(
+        &mut self,
+        chain: &impl IndexerChain,
+        handler: &mut impl EventHandler,
+    ) -> Result<Option<Checkpoint>, Web3Error> {
+        if self.filters.is_empty() {
+            return Err(Web3Error::BadInput("No events to index".to_string()));
+        }
+
+        if let Some(checkpoint) = self.checkpoint {
+            if chain.block_hash(checkpoint.block_number).await? != checkpoint.block_hash {
+                let ancestor = self.find_ancestor(chain).await?;
+                handler.rollback(&ancestor).await?;
+                self.commit(ancestor)?;
+                return Ok(Some(ancestor));
+            }
+        }
+
+        let latest = chain.block_number().await?;
+        let confirmations = Uint256::from(self.confirmations);
+        if latest < confirmations {
+            return Ok(None);
+        }
+        let safe = latest - confirmations;
+        let from = match self.checkpoint {
+            Some(checkpoint) => checkpoint.block_number + 1u8.into(),
+            None => self.start_block,
+        };
+        if from > safe {
+            return Ok(None);
+        }
+        let to = safe.min(from + Uint256::from(self.max_blocks_per_step - 1));
+
+        // the header is fetched before the logs so that the logs can be checked against it,
+        // otherwise a reorg in between would checkpoint a block the logs did not come from
+        let checkpoint = Checkpoint {
+            block_number: to,
+            block_hash: chain.block_hash(to).await?,
+        };
+        let logs = chain
+            .logs(self.log_filter()?, from, to, self.fetch_options)
+            .await?;
+        if !logs_match_checkpoint(&logs, &checkpoint)
+            || chain.block_hash(to).await? != checkpoint.block_hash
+        {
+            warn!("Chain changed while fetching logs up to block {to}, retrying");
+            return Ok(None);
+        }
+
+        let mut events = Vec::new();
+        for log in logs {
+            if let Some((_, event)) = self
+                .filters
+                .iter()
+                .find(|(contract, event)| *contract == log.address && event.matches(&log))
+            {
+                events.push(event.decode(&log)?);
+            }
+        }
+
+        handler.handle(events, &checkpoint).await?;
+        self.commit(checkpoint)?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Calls `step` forever, waiting `poll_interval` whenever there are no new confirmed blocks.
+    /// Returns on the first error, the indexer can be run again to resume from its checkpoint
+    pub async fn run(
+        &mut self,
+        web3: &Web3,
+        handler: &mut impl EventHandler,
+        poll_interval: Duration,
+    ) -> Result<(), Web3Error> {
+        loop {
+            if self.step(web3, handler).await?.is_none() {
+                delay_for(poll_interval).await;
+            }
+        }
+    }
+
+    fn commit(&mut self, checkpoint: Checkpoint) -> Result<(), Web3Error> {
+        self.store.save(&checkpoint)?;
+        while self
+            .history
+            .back()
+            .is_some_and(|c| c.block_number >= checkpoint.block_number)
+        {
+            self.history.pop_back();
+        }
+        self.history.push_back(checkpoint);
+        while self.history.len() > self.history_size {
+            self.history.pop_front();
+        }
+        self.checkpoint = Some(checkpoint);
+        Ok(())
+    }
+
+    /// Walks back through recent checkpoints until one is still canonical, if none is the block
+    /// `max_blocks_per_step` below the oldest known checkpoint is used instead, but never one
+    /// below the start block
+    async fn find_ancestor(&mut self, chain: &impl IndexerChain) -> Result<Checkpoint, Web3Error> {
+        let mut oldest = self.start_block;
+        while let Some(checkpoint) = self.history.pop_back() {
+            if chain.block_hash(checkpoint.block_number).await? == checkpoint.block_hash {
+                return Ok(checkpoint);
+            }
+            oldest = checkpoint.block_number;
+        }
+
+        let depth: Uint256 = self.max_blocks_per_step.into();
+        let floor = if self.start_block > 0u8.into() {
+            self.start_block - 1u8.into()
+        } else {
+            self.start_block
+        };
+        let number = if oldest > depth {
+            (oldest - depth).max(floor)
+        } else {
+            floor
+        };
+        Ok(Checkpoint {
+            block_number: number,
+            block_hash: chain.block_hash(number).await?,
+        })
+    }
+
+    /// A single filter matching all followed events, logs are matched to their event locally
+    fn log_filter(&self) -> Result<NewFilter, Web3Error> {
+        // anonymous events have no signature topic so they can't be filtered by topic
+        let anonymous = self.filters.iter().any(|(_, e)| e.anonymous);
+        let mut builder = FilterBuilder::new();
+        for (contract, event) in self.filters.iter() {
+            builder = builder.address(*contract);
+            if !anonymous {
+                builder = builder.topic(0, event.topic()?);
+            }
+        }
+        builder.build()
+    }
+}
+
+/// Returns false if the logs come from more than one branch, that is if two logs of the same
+/// block disagree on its hash or a log of the checkpointed block is not from the checkpointed hash
+fn logs_match_checkpoint(logs: &[Log], checkpoint: &Checkpoint) -> bool {
+    let mut hashes = HashMap::new();
+    hashes.insert(checkpoint.block_number, checkpoint.block_hash);
+    logs.iter()
+        .all(|log| match (log.block_number, &log.block_hash) {
+            (Some(number), Some(hash)) => {
+                let hash = Uint256::from_be_bytes(hash);
+                *hashes.entry(number).or_insert(hash) == hash
+            }
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_utils::keccak256;
+    use crate::types::Data;
+    use actix::System;
+    use clarity::abi::AbiToken as Token;
+    use num_traits::ToPrimitive;
+    use std::cell::{Cell, RefCell};
+
+    const TRANSFER: &str = "Transfer(address indexed from, address indexed to, uint256 value)";
+
+    fn token() -> Address {
+        "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap()
+    }
+
+    /// A chain of `hashes.len()` blocks with a Transfer of `value` in each block of `transfers`
+    struct MockChain {
+        hashes: RefCell<Vec<u64>>,
+        transfers: Vec<(u64, u64)>,
+        /// returns logs from this block hash instead, as if the chain reorged mid fetch
+        stale_logs: Cell<Option<u64>>,
+    }
+
+    impl MockChain {
+        fn new(len: u64, transfers: Vec<(u64, u64)>) -> Self {
+            MockChain {
+                hashes: RefCell::new((0..len).map(|n| 1000 + n).collect()),
+                transfers,
+                stale_logs: Cell::new(None),
+            }
+        }
+
+        /// Replaces every block from `number` on with a block of another branch
+        fn reorg(&self, number: u64) {
+            for hash in self.hashes.borrow_mut().iter_mut().skip(number as usize) {
+                *hash += 1_000_000;
+            }
+        }
+
+        fn hash(&self, number: u64) -> Uint256 {
+            self.hashes.borrow()[number as usize].into()
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl IndexerChain for MockChain {
+        async fn block_number(&self) -> Result<Uint256, Web3Error> {
+            Ok((self.hashes.borrow().len() as u64 - 1).into())
+        }
+
+        async fn block_hash(&self, number: Uint256) -> Result<Uint256, Web3Error> {
+            Ok(self.hash(number.to_u64().unwrap()))
+        }
+
+        async fn logs(
+            &self,
+            _filter: NewFilter,
+            from: Uint256,
+            to: Uint256,
+            _options: LogFetchOptions,
+        ) -> Result<Vec<Log>, Web3Error> {
+            let (from, to) = (from.to_u64().unwrap(), to.to_u64().unwrap());
+            let topic = keccak256("Transfer(address,address,uint256)".as_bytes());
+            let word = |v: u64| Data(Uint256::from(v).to_be_bytes().to_vec());
+            Ok(self
+                .transfers
+                .iter()
+                .filter(|(block, _)| (from..=to).contains(block))
+                .map(|(block, value)| {
+                    let hash = self
+                        .stale_logs
+                        .get()
+                        .map(Uint256::from)
+                        .unwrap_or_else(|| self.hash(*block));
+                    Log {
+                        block_number: Some((*block).into()),
+                        block_hash: Some(Data(hash.to_be_bytes().to_vec())),
+                        log_index: Some(0u8.into()),
+                        address: token(),
+                        topics: vec![Data(topic.to_vec()), word(1), word(2)],
+                        data: word(*value),
+                        ..Default::default()
+                    }
+                })
+                .collect())
+        }
+    }
+
+    /// Keeps the values of handled transfers like a database would
+    #[derive(Default)]
+    struct MockHandler {
+        values: Vec<(Uint256, Uint256)>,
+        rollbacks: Vec<Checkpoint>,
+    }
+
+    #[async_trait(?Send)]
+    impl EventHandler for MockHandler {
+        async fn handle(
+            &mut self,
+            events: Vec<DecodedEvent>,
+            _checkpoint: &Checkpoint,
+        ) -> Result<(), Web3Error> {
+            for event in events {
+                let value = match event.param("value") {
+                    Some(Token::Uint(value)) => *value,
+                    other => panic!("Unexpected value {other:?}"),
+                };
+                self.values.push((event.log.block_number.unwrap(), value));
+            }
+            Ok(())
+        }
+
+        async fn rollback(&mut self, checkpoint: &Checkpoint) -> Result<(), Web3Error> {
+            self.values
+                .retain(|(block, _)| *block <= checkpoint.block_number);
+            self.rollbacks.push(*checkpoint);
+            Ok(())
+        }
+    }
+
+    fn indexer() -> EventIndexer<MemoryCheckpointStore> {
+        EventIndexer::new(1u8.into(), MemoryCheckpointStore::default())
+            .unwrap()
+            .with_event(token(), TRANSFER)
+            .unwrap()
+            .with_confirmations(2)
+            .with_max_blocks_per_step(4)
+    }
+
+    fn values(handler: &MockHandler) -> Vec<u64> {
+        handler
+            .values
+            .iter()
+            .map(|(_, v)| v.to_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_indexer_step() {
+        // latest block 11, so blocks up to 9 are confirmed
+        let chain = MockChain::new(12, vec![(2, 20), (4, 40), (7, 70), (10, 100)]);
+        let mut indexer = indexer();
+        let mut handler = MockHandler::default();
+        System::new().block_on(async move {
+            let checkpoint = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(
+                checkpoint,
+                Some(Checkpoint {
+                    block_number: 4u8.into(),
+                    block_hash: chain.hash(4),
+                })
+            );
+            assert_eq!(values(&handler), vec![20, 40]);
+
+            let checkpoint = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(checkpoint.unwrap().block_number, 8u8.into());
+            let checkpoint = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(checkpoint.unwrap().block_number, 9u8.into());
+            assert_eq!(values(&handler), vec![20, 40, 70]);
+
+            // nothing new is confirmed
+            assert_eq!(indexer.step_on(&chain, &mut handler).await.unwrap(), None);
+            assert_eq!(indexer.store.load().unwrap(), indexer.checkpoint());
+        });
+    }
+
+    #[test]
+    fn test_indexer_reorg() {
+        let chain = MockChain::new(12, vec![(2, 20), (4, 40), (7, 70)]);
+        let mut indexer = indexer();
+        let mut handler = MockHandler::default();
+        System::new().block_on(async move {
+            for _ in 0..2 {
+                indexer.step_on(&chain, &mut handler).await.unwrap();
+            }
+            assert_eq!(indexer.checkpoint().unwrap().block_number, 8u8.into());
+            assert_eq!(values(&handler), vec![20, 40, 70]);
+
+            // blocks 6 and up are replaced, the checkpoint at 4 is the newest still canonical
+            chain.reorg(6);
+            let ancestor = indexer.step_on(&chain, &mut handler).await.unwrap();
+            let expected = Checkpoint {
+                block_number: 4u8.into(),
+                block_hash: chain.hash(4),
+            };
+            assert_eq!(ancestor, Some(expected));
+            assert_eq!(handler.rollbacks, vec![expected]);
+            assert_eq!(values(&handler), vec![20, 40]);
+            assert_eq!(indexer.store.load().unwrap(), Some(expected));
+
+            // the replaced blocks are indexed again from the new branch
+            let checkpoint = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(
+                checkpoint,
+                Some(Checkpoint {
+                    block_number: 8u8.into(),
+                    block_hash: chain.hash(8),
+                })
+            );
+            assert_eq!(values(&handler), vec![20, 40, 70]);
+        });
+    }
+
+    #[test]
+    fn test_indexer_rollback_past_history() {
+        let chain = MockChain::new(12, vec![(2, 20), (4, 40)]);
+        let mut indexer = indexer();
+        let mut handler = MockHandler::default();
+        System::new().block_on(async move {
+            indexer.step_on(&chain, &mut handler).await.unwrap();
+            // every known checkpoint is gone, so roll back to just below the start block
+            chain.reorg(0);
+            let ancestor = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(
+                ancestor,
+                Some(Checkpoint {
+                    block_number: 0u8.into(),
+                    block_hash: chain.hash(0),
+                })
+            );
+            assert!(handler.values.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_indexer_rejects_logs_from_another_branch() {
+        let chain = MockChain::new(12, vec![(2, 20), (4, 40)]);
+        let mut indexer = indexer();
+        let mut handler = MockHandler::default();
+        System::new().block_on(async move {
+            chain.stale_logs.set(Some(42));
+            assert_eq!(indexer.step_on(&chain, &mut handler).await.unwrap(), None);
+            assert_eq!(indexer.checkpoint(), None);
+            assert!(handler.values.is_empty());
+
+            chain.stale_logs.set(None);
+            let checkpoint = indexer.step_on(&chain, &mut handler).await.unwrap();
+            assert_eq!(checkpoint.unwrap().block_number, 4u8.into());
+            assert_eq!(values(&handler), vec![20, 40]);
+        });
+    }
+
+    #[test]
+    fn test_file_checkpoint_store() {
+        let path = std::env::temp_dir().join("web30-indexer-checkpoint-test.json");
+        let _ = fs::remove_file(&path);
+        let mut store = FileCheckpointStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            block_number: 17_000_000u64.into(),
+            block_hash: 0xabcdefu64.into(),
+        };
+        store.save(&checkpoint).unwrap();
+        assert_eq!(
+            FileCheckpointStore::new(&path).load().unwrap(),
+            Some(checkpoint)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_indexer_log_filter() {
+        let token: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let indexer = EventIndexer::new(0u8.into(), MemoryCheckpointStore::default())
+            .unwrap()
+            .with_event(
+                token,
+                "Transfer(address indexed from, address indexed to, uint256 value)",
+            )
+            .unwrap()
+            .with_event(
+                token,
+                "Approval(address indexed owner, address indexed spender, uint256 value)",
+            )
+            .unwrap();
+        let filter = indexer.log_filter().unwrap();
+        assert_eq!(filter.address, vec![token]);
+        assert_eq!(
+            filter.topics,
+            Some(vec![Some(vec![
+                Some(
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+                        .to_string()
+                ),
+                Some(
+                    "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925"
+                        .to_string()
+                ),
+            ])])
+        );
+    }
+}
@@ -1,10 +1,8 @@
 //! This module contains functions for managing Ethereum events
-use crate::{client::Web3, types::NewFilter};
-use crate::{jsonrpc::error::Web3Error, types::Log};
-use clarity::{
-    abi::{derive_signature, AbiToken as Token, SerializedToken},
-    utils::bytes_to_hex_str,
-};
+use crate::filter::FilterBuilder;
+use crate::log_fetcher::LogFetchOptions;
+use crate::{client::Web3, jsonrpc::error::Web3Error, types::Log};
+use clarity::abi::{AbiToken as Token, SerializedToken};
 use clarity::{Address, Uint256};
 use heliosphere::core::event::EventData;
 use std::time::{Duration, Instant};
@@ -23,24 +21,80 @@ pub fn address_to_event(address: Address) -> [u8; 32] {
     }
 }
 
-fn bytes_to_data(s: &[u8]) -> String {
-    let mut val = "0x".to_string();
-    val.push_str(&bytes_to_hex_str(s));
-    val
-}
-
 pub enum Web3Event {
     Logs(Vec<Log>),
     Events(Vec<EventData>),
 }
 
+/// A contract event that can be parsed from both Ethereum logs and Tron events, usually
+/// implemented with `#[derive(ContractEvent)]`:
+///
+/// ```rust,ignore
+/// use web30::ContractEvent;
+///
+/// #[derive(ContractEvent)]
+/// #[event("Transfer(address indexed from, address indexed to, uint256 value)")]
+/// struct Transfer {
+///     from: Address,
+///     to: Address,
+///     #[event(rename = "value")]
+///     amount: Uint256,
+/// }
+/// ```
 pub trait ContractEvent
 where
     Self: Sized,
 {
     // impl for web3 log and tron event
     fn from_event(input: &EventData) -> Result<Self, Web3Error>;
-    fn from_events(input: &Web3Event) -> Result<Vec<Self>, Web3Error>;
+
+    fn from_log(_input: &Log) -> Result<Self, Web3Error> {
+        Err(Web3Error::BadInput(
+            "This event does not support Ethereum logs".to_string(),
+        ))
+    }
+
+    fn from_events(input: &Web3Event) -> Result<Vec<Self>, Web3Error> {
+        match input {
+            Web3Event::Logs(logs) => logs.iter().map(Self::from_log).collect(),
+            Web3Event::Events(events) => events.iter().map(Self::from_event).collect(),
+        }
+    }
+}
+
+/// Where wait_for_event gets new logs from
+#[derive(Debug, Clone, Copy)]
+enum EventSource {
+    /// An installed filter with this id
+    Filter(Uint256),
+    /// eth_getLogs from this block to the latest
+    Polling(Uint256),
+}
+
+/// Error fragments of nodes that don't implement filters or lost the filter, matched case insensitively
+const FILTER_UNSUPPORTED_ERRORS: [&str; 6] = [
+    "filter not found",
+    "method not found",
+    "not supported",
+    "unsupported",
+    "does not exist",
+    "not available",
+];
+
+/// Returns true if `error` means filters can't be used with this node, either because the filter
+/// methods are not implemented or because the filter was lost between calls
+fn is_filter_unsupported_error(error: &Web3Error) -> bool {
+    match error {
+        // -32601 is the JSON-RPC code for an unknown method
+        Web3Error::JsonRpcError { code: -32601, .. } => true,
+        Web3Error::JsonRpcError { message, .. } => {
+            let message = message.to_lowercase();
+            FILTER_UNSUPPORTED_ERRORS
+                .iter()
+                .any(|e| message.contains(e))
+        }
+        _ => false,
+    }
 }
 
 impl Web3 {
@@ -56,22 +110,13 @@ impl Web3 {
         topics: Vec<Vec<[u8; 32]>>,
         local_filter: F,
     ) -> Result<Log, Web3Error> {
-        let sig = derive_signature(event)?;
-        let mut final_topics = vec![Some(vec![Some(bytes_to_data(&sig))])];
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
-            }
-            final_topics.push(Some(parts));
+        let mut builder = FilterBuilder::new()
+            .addresses(&contract_address)
+            .event(event)?;
+        for (i, topic) in topics.into_iter().enumerate() {
+            builder = builder.topics(i + 1, topic);
         }
-
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block: None,
-            to_block: None,
-            topics: Some(final_topics),
-        };
+        let new_filter = builder.build()?;
 
         delay_for(wait_time).await;
         let logs = match self.eth_get_logs(new_filter.clone()).await {
@@ -90,7 +135,9 @@ impl Web3 {
     /// Sets up an event filter, waits for a single event to happen, then removes the filter. Includes a
     /// local filter. If a captured event does not pass this filter, it is ignored. This differs from
     /// wait_for_event_alt in that it will check for filter changes every second and potentially exit
-    /// earlier than the wait_for time provided by the user.
+    /// earlier than the wait_for time provided by the user. If the node does not support filters, or
+    /// loses the filter as load balanced providers do, this falls back to polling `eth_getLogs` over the
+    /// blocks produced since the wait started.
     pub async fn wait_for_event<F: Fn(Log) -> bool + 'static>(
         &self,
         wait_for: Duration,
@@ -99,46 +146,62 @@ impl Web3 {
         topics: Vec<Vec<[u8; 32]>>,
         local_filter: F,
     ) -> Result<Log, Web3Error> {
-        let sig = derive_signature(event)?;
-        let mut final_topics = vec![Some(vec![Some(bytes_to_data(&sig))])];
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
-            }
-            final_topics.push(Some(parts));
+        let mut builder = FilterBuilder::new()
+            .addresses(&contract_address)
+            .event(event)?;
+        for (i, topic) in topics.into_iter().enumerate() {
+            builder = builder.topics(i + 1, topic);
         }
+        let new_filter = builder.build()?;
 
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block: None,
-            to_block: None,
-            topics: Some(final_topics),
-        };
-
-        let filter_id = match self.eth_new_filter(new_filter).await {
-            Ok(f) => f,
+        // polling starts from here if filters turn out not to work, so nothing emitted
+        // after the wait started can be missed
+        let start_block = self.eth_block_number().await?;
+        let mut source = match self.eth_new_filter(new_filter.clone()).await {
+            Ok(filter_id) => EventSource::Filter(filter_id),
+            Err(e) if is_filter_unsupported_error(&e) => {
+                debug!("Filters unsupported, polling for {event} instead: {e}");
+                EventSource::Polling(start_block)
+            }
             Err(e) => return Err(e),
         };
 
         let start = Instant::now();
         let mut found_log = None;
-        while Instant::now() - start < wait_for {
+        while found_log.is_none() && Instant::now() - start < wait_for {
             delay_for(Duration::from_secs(1)).await;
-            let logs = match self.eth_get_filter_changes(filter_id).await {
-                Ok(changes) => changes,
-                Err(e) => return Err(e),
-            };
-            for log in logs {
-                if local_filter(log.clone()) {
-                    found_log = Some(log);
-                    break;
+            let logs = match source {
+                EventSource::Filter(filter_id) => {
+                    match self.eth_get_filter_changes(filter_id).await {
+                        Ok(changes) => changes,
+                        Err(e) if is_filter_unsupported_error(&e) => {
+                            debug!("Filter for {event} lost, polling instead: {e}");
+                            source = EventSource::Polling(start_block);
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
-            }
+                EventSource::Polling(next_block) => {
+                    let latest = self.eth_block_number().await?;
+                    if latest < next_block {
+                        continue;
+                    }
+                    let mut filter = new_filter.clone();
+                    filter.from_block = Some(format!("{next_block:#x}"));
+                    filter.to_block = Some(format!("{latest:#x}"));
+                    let logs = self.eth_get_logs(filter).await?;
+                    source = EventSource::Polling(latest + 1u8.into());
+                    logs
+                }
+            };
+            found_log = logs.into_iter().find(|log| local_filter(log.clone()));
         }
 
-        if let Err(e) = self.eth_uninstall_filter(filter_id).await {
-            return Err(Web3Error::CouldNotRemoveFilter(format!("{e}")));
+        if let EventSource::Filter(filter_id) = source {
+            if let Err(e) = self.eth_uninstall_filter(filter_id).await {
+                return Err(Web3Error::CouldNotRemoveFilter(format!("{e}")));
+            }
         }
 
         match found_log {
@@ -147,8 +210,9 @@ impl Web3 {
         }
     }
 
-    /// Checks for multiple events as defined by their signature strings over a block range. If no ending block is provided
-    /// the latest will be used. This function will not wait for events to occur.
+    /// Checks for any of multiple events as defined by their signature strings over a block range. If no ending block is provided
+    /// the latest will be used. This function will not wait for events to occur. Large ranges are fetched in chunks with the
+    /// default options, see [`Web3::check_for_events_with_options`].
     pub async fn check_for_events(
         &self,
         start_block: Uint256,
@@ -156,34 +220,39 @@ impl Web3 {
         contract_address: Vec<Address>,
         events: Vec<&str>,
     ) -> Result<Vec<Log>, Web3Error> {
-        // Build a filter with specified topics
-        let from_block = Some(format!("{start_block:#x}"));
-        let to_block;
-        if let Some(end_block) = end_block {
-            to_block = Some(format!("{end_block:#x}"));
-        } else {
-            let latest_block = self.eth_block_number().await?;
-            to_block = Some(format!("{latest_block:#x}"));
-        }
-
-        let mut final_topics = Vec::new();
-        for event in events {
-            let sig = derive_signature(event)?;
-            final_topics.push(Some(vec![Some(bytes_to_data(&sig))]));
-        }
+        self.check_for_events_with_options(
+            start_block,
+            end_block,
+            contract_address,
+            events,
+            LogFetchOptions::default(),
+        )
+        .await
+    }
 
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block,
-            to_block,
-            topics: Some(final_topics),
-        };
+    /// Same as `check_for_events` with the given chunking and bloom filter settings, see
+    /// [`Web3::get_logs_chunked`].
+    pub async fn check_for_events_with_options(
+        &self,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        contract_address: Vec<Address>,
+        events: Vec<&str>,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let new_filter = FilterBuilder::new()
+            .addresses(&contract_address)
+            .events(&events)?
+            .build()?;
 
-        self.eth_get_logs(new_filter).await
+        self.get_logs_chunked(new_filter, start_block, end_block, options)
+            .await
     }
 
-    /// Checks for multiple events as defined by arbitrary user input over a block range. If no ending block is provided
-    /// the latest will be used. This function will not wait for events to occur
+    /// Checks for multiple events as defined by arbitrary user input over a block range, `topics[i]` lists the values allowed
+    /// for topic i and an empty list matches anything. If no ending block is provided
+    /// the latest will be used. This function will not wait for events to occur. Large ranges are fetched in chunks with the
+    /// default options, see [`Web3::check_for_arbitrary_events_with_options`].
     pub async fn check_for_arbitrary_events(
         &self,
         start_block: Uint256,
@@ -191,32 +260,144 @@ impl Web3 {
         contract_address: Vec<Address>,
         topics: Vec<Vec<[u8; 32]>>,
     ) -> Result<Vec<Log>, Web3Error> {
-        // Build a filter with specified topics
-        let from_block = Some(format!("{start_block:#x}"));
-        let to_block;
-        if let Some(end_block) = end_block {
-            to_block = Some(format!("{end_block:#x}"));
-        } else {
-            let latest_block = self.eth_block_number().await?;
-            to_block = Some(format!("{latest_block:#x}"));
-        }
+        self.check_for_arbitrary_events_with_options(
+            start_block,
+            end_block,
+            contract_address,
+            topics,
+            LogFetchOptions::default(),
+        )
+        .await
+    }
 
-        let mut final_topics = Vec::new();
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
-            }
-            final_topics.push(Some(parts));
+    /// Same as `check_for_arbitrary_events` with the given chunking and bloom filter settings,
+    /// see [`Web3::get_logs_chunked`].
+    pub async fn check_for_arbitrary_events_with_options(
+        &self,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        contract_address: Vec<Address>,
+        topics: Vec<Vec<[u8; 32]>>,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let mut builder = FilterBuilder::new().addresses(&contract_address);
+        for (i, topic) in topics.into_iter().enumerate() {
+            builder = builder.topics(i, topic);
         }
+        let new_filter = builder.build()?;
+
+        self.get_logs_chunked(new_filter, start_block, end_block, options)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Data;
+    use crate::ContractEvent;
 
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block,
-            to_block,
-            topics: Some(final_topics),
+    #[derive(ContractEvent, Debug, PartialEq)]
+    #[event("Transfer(address indexed from, address indexed to, uint256 value)")]
+    struct Transfer {
+        from: Address,
+        to: Address,
+        #[event(rename = "value")]
+        amount: Uint256,
+    }
+
+    #[test]
+    fn test_derive_contract_event() {
+        let from: Address = "0x28c6c06298d514db089934071355e5743bf21d60"
+            .parse()
+            .unwrap();
+        let to: Address = "0x5041ed759dd4afc3a72b8192c143f72f4724081a"
+            .parse()
+            .unwrap();
+        let expected = Transfer {
+            from,
+            to,
+            amount: 2_500_000_000u64.into(),
         };
 
-        self.eth_get_logs(new_filter).await
+        let log: Log = serde_json::from_str(
+            r#"{
+                "address": "0xdac17f958d2ee523a2206206994597c13d831ec7",
+                "topics": [
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                    "0x00000000000000000000000028c6c06298d514db089934071355e5743bf21d60",
+                    "0x0000000000000000000000005041ed759dd4afc3a72b8192c143f72f4724081a"
+                ],
+                "data": "0x000000000000000000000000000000000000000000000000000000009502f900",
+                "blockNumber": "0x1254b8b",
+                "transactionHash": "0x4d2b1c4e1a2d0f46e8bba6a6bd48bd8e3d1f3b3d8b1f6b7a7e2b6f2d0c9e5a41",
+                "transactionIndex": "0x5a",
+                "blockHash": "0x9f1c4f6f2d0a8b3e7c5d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b",
+                "logIndex": "0x10e",
+                "removed": false
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Transfer::from_log(&log).unwrap(), expected);
+
+        // Tron nodes return the same event already decoded
+        let event: EventData = serde_json::from_value(serde_json::json!({
+            "block_number": 61_000_000u64,
+            "block_timestamp": 1_712_000_000_000u64,
+            "caller_contract_address": "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t",
+            "contract_address": "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t",
+            "event_index": 0,
+            "event_name": "Transfer",
+            "result": {
+                "0": "0x28c6c06298d514db089934071355e5743bf21d60",
+                "1": "0x5041ed759dd4afc3a72b8192c143f72f4724081a",
+                "2": "2500000000",
+                "from": "0x28c6c06298d514db089934071355e5743bf21d60",
+                "to": "0x5041ed759dd4afc3a72b8192c143f72f4724081a",
+                "value": "2500000000"
+            },
+            "result_type": {
+                "from": "address",
+                "to": "address",
+                "value": "uint256"
+            },
+            "event": "Transfer(address indexed from, address indexed to, uint256 value)",
+            "transaction_id": "4d2b1c4e1a2d0f46e8bba6a6bd48bd8e3d1f3b3d8b1f6b7a7e2b6f2d0c9e5a41"
+        }))
+        .unwrap();
+        assert_eq!(Transfer::from_event(&event).unwrap(), expected);
+
+        // a log of another event is rejected
+        let mut other = log;
+        other.topics[0] = Data(vec![0u8; 32]);
+        assert!(Transfer::from_log(&other).is_err());
+    }
+
+    #[test]
+    fn test_is_filter_unsupported_error() {
+        let error = |code: i64, message: &str| Web3Error::JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        };
+        assert!(is_filter_unsupported_error(&error(
+            -32000,
+            "filter not found"
+        )));
+        assert!(is_filter_unsupported_error(&error(
+            -32601,
+            "the method eth_newFilter does not exist/is not available"
+        )));
+        assert!(is_filter_unsupported_error(&error(
+            -32000,
+            "eth_newFilter is not supported"
+        )));
+        assert!(!is_filter_unsupported_error(&error(
+            -32000,
+            "header not found"
+        )));
+        assert!(!is_filter_unsupported_error(&Web3Error::BadResponse(
+            "filter not found".to_string()
+        )));
     }
 }
@@ -0,0 +1,258 @@
+//! This module contains a builder for `eth_getLogs` and `eth_newFilter` filters. A log matches a
+//! filter if it was emitted by one of the filter addresses and, for every topic slot, its topic is
+//! one of the values given for that slot. Slots without values are wildcards.
+use crate::event_utils::address_to_event;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::NewFilter;
+use clarity::abi::derive_signature;
+use clarity::utils::bytes_to_hex_str;
+use clarity::{Address, Uint256};
+
+/// Logs have at most four topics, the event signature and three indexed parameters
+pub const MAX_TOPICS: usize = 4;
+
+/// The 32 byte value of an indexed event parameter as it appears in a log topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Topic(pub [u8; 32]);
+
+impl From<[u8; 32]> for Topic {
+    fn from(value: [u8; 32]) -> Self {
+        Topic(value)
+    }
+}
+
+impl From<Address> for Topic {
+    fn from(value: Address) -> Self {
+        Topic(address_to_event(value))
+    }
+}
+
+impl From<Uint256> for Topic {
+    fn from(value: Uint256) -> Self {
+        Topic(value.to_be_bytes())
+    }
+}
+
+impl From<bool> for Topic {
+    fn from(value: bool) -> Self {
+        let mut topic = [0u8; 32];
+        topic[31] = value as u8;
+        Topic(topic)
+    }
+}
+
+impl Topic {
+    /// The topic of an event, the hash of its signature such as `Transfer(address,address,uint256)`
+    pub fn event(signature: &str) -> Result<Self, Web3Error> {
+        Ok(Topic(derive_signature(signature)?))
+    }
+
+    fn to_data(self) -> String {
+        format!("0x{}", bytes_to_hex_str(&self.0))
+    }
+}
+
+/// Builds a `NewFilter`, for example all transfers from one of two senders to anyone:
+///
+/// ```rust,ignore
+/// let filter = FilterBuilder::new()
+///     .address(token)
+///     .event("Transfer(address,address,uint256)")?
+///     .topic(1, alice)
+///     .topic(1, bob)
+///     .from_block(start)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterBuilder {
+    addresses: Vec<Address>,
+    from_block: Option<Uint256>,
+    to_block: Option<Uint256>,
+    block_hash: Option<Uint256>,
+    topics: [Vec<Topic>; MAX_TOPICS],
+    /// set if a slot index above MAX_TOPICS was used, reported by build
+    invalid_slot: Option<usize>,
+}
+
+impl FilterBuilder {
+    pub fn new() -> Self {
+        FilterBuilder::default()
+    }
+
+    /// Matches logs emitted by `address`, may be called more than once to match any of several
+    pub fn address(mut self, address: Address) -> Self {
+        if !self.addresses.contains(&address) {
+            self.addresses.push(address);
+        }
+        self
+    }
+
+    /// Matches logs emitted by any of `addresses`
+    pub fn addresses(self, addresses: &[Address]) -> Self {
+        addresses.iter().fold(self, |b, a| b.address(*a))
+    }
+
+    /// Matches logs from this block onward, can't be combined with `block_hash`
+    pub fn from_block(mut self, block: Uint256) -> Self {
+        self.from_block = Some(block);
+        self
+    }
+
+    /// Matches logs up to and including this block, can't be combined with `block_hash`
+    pub fn to_block(mut self, block: Uint256) -> Self {
+        self.to_block = Some(block);
+        self
+    }
+
+    /// Matches only logs of the block with this hash
+    pub fn block_hash(mut self, hash: Uint256) -> Self {
+        self.block_hash = Some(hash);
+        self
+    }
+
+    /// Matches logs of the event with this signature, may be called more than once to match any
+    /// of several events
+    pub fn event(self, signature: &str) -> Result<Self, Web3Error> {
+        Ok(self.topic(0, Topic::event(signature)?))
+    }
+
+    /// Matches logs of any of the events with these signatures
+    pub fn events(self, signatures: &[&str]) -> Result<Self, Web3Error> {
+        signatures.iter().try_fold(self, |b, s| b.event(s))
+    }
+
+    /// Adds `value` to the values allowed in topic `slot`, slot 0 is the event signature and
+    /// slots 1 to 3 the indexed parameters in declaration order
+    pub fn topic(mut self, slot: usize, value: impl Into<Topic>) -> Self {
+        match self.topics.get_mut(slot) {
+            Some(values) => {
+                let value = value.into();
+                if !values.contains(&value) {
+                    values.push(value)
+                }
+            }
+            None => self.invalid_slot = Some(slot),
+        }
+        self
+    }
+
+    /// Adds all of `values` to the values allowed in topic `slot`
+    pub fn topics<T: Into<Topic>>(self, slot: usize, values: impl IntoIterator<Item = T>) -> Self {
+        values.into_iter().fold(self, |b, v| b.topic(slot, v))
+    }
+
+    /// Makes topic `slot` match anything again, discarding any values given for it
+    pub fn any(mut self, slot: usize) -> Self {
+        match self.topics.get_mut(slot) {
+            Some(values) => values.clear(),
+            None => self.invalid_slot = Some(slot),
+        }
+        self
+    }
+
+    /// Builds the filter, fails if a topic slot above 3 was used or a block hash was combined
+    /// with a block range
+    pub fn build(self) -> Result<NewFilter, Web3Error> {
+        if let Some(slot) = self.invalid_slot {
+            return Err(Web3Error::BadInput(format!(
+                "Topic slot {slot} does not exist, logs have at most {MAX_TOPICS} topics"
+            )));
+        }
+        if self.block_hash.is_some() && (self.from_block.is_some() || self.to_block.is_some()) {
+            return Err(Web3Error::BadInput(
+                "A filter can't have both a block hash and a block range".to_string(),
+            ));
+        }
+
+        // trailing wildcards can be left out entirely
+        let used = self
+            .topics
+            .iter()
+            .rposition(|values| !values.is_empty())
+            .map_or(0, |last| last + 1);
+        let topics = if used == 0 {
+            None
+        } else {
+            Some(
+                self.topics[..used]
+                    .iter()
+                    .map(|values| {
+                        if values.is_empty() {
+                            None
+                        } else {
+                            Some(values.iter().map(|v| Some(v.to_data())).collect())
+                        }
+                    })
+                    .collect(),
+            )
+        };
+
+        Ok(NewFilter {
+            from_block: self.from_block.map(|b| format!("{b:#x}")),
+            to_block: self.to_block.map(|b| format!("{b:#x}")),
+            block_hash: self.block_hash.map(|h| format!("{h:#066x}")),
+            address: self.addresses,
+            topics,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_builder() {
+        let token: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let alice: Address = "0x00000000000000000000000000000000000a11ce"
+            .parse()
+            .unwrap();
+        let filter = FilterBuilder::new()
+            .address(token)
+            .events(&[
+                "Transfer(address,address,uint256)",
+                "Approval(address,address,uint256)",
+            ])
+            .unwrap()
+            .topic(2, alice)
+            .from_block(16u8.into())
+            .build()
+            .unwrap();
+        assert_eq!(filter.from_block, Some("0x10".to_string()));
+        assert_eq!(filter.to_block, None);
+        assert_eq!(filter.address, vec![token]);
+        assert_eq!(
+            serde_json::to_value(&filter.topics).unwrap(),
+            serde_json::json!([
+                [
+                    "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                    "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925"
+                ],
+                null,
+                ["0x00000000000000000000000000000000000000000000000000000000000a11ce"]
+            ])
+        );
+
+        let filter = FilterBuilder::new()
+            .topic(3, true)
+            .topic(3, Uint256::from(0u8))
+            .any(3)
+            .block_hash(1u8.into())
+            .build()
+            .unwrap();
+        assert_eq!(filter.topics, None);
+        assert_eq!(
+            serde_json::to_value(&filter).unwrap()["blockHash"],
+            "0x0000000000000000000000000000000000000000000000000000000000000001"
+        );
+
+        assert!(FilterBuilder::new().topic(4, true).build().is_err());
+        assert!(FilterBuilder::new()
+            .block_hash(1u8.into())
+            .from_block(1u8.into())
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Hashing helpers shared across the crate
+use sha3::{Digest, Keccak256};
+
+/// Computes the Ethereum Keccak256 hash of the input, note that this is not
+/// the finalized NIST SHA3-256 function
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+#[test]
+fn test_keccak256() {
+    use clarity::utils::bytes_to_hex_str;
+    assert_eq!(
+        bytes_to_hex_str(&keccak256(&[])),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+}
@@ -0,0 +1,343 @@
+//! This module implements BIP-39 mnemonic seeds and BIP-32 hierarchical key derivation, so that
+//! any number of Ethereum (BIP-44 coin type 60) and Tron (coin type 195) keys can be derived from
+//! a single backed up phrase. Derived keys are plain `PrivateKey`s usable with every sending helper.
+//!
+//! See more https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+use crate::jsonrpc::error::Web3Error;
+use bip39::Mnemonic;
+use clarity::utils::bytes_to_hex_str;
+use clarity::PrivateKey;
+use heliosphere::core::Address as TronAddress;
+use heliosphere::signer::keypair::Keypair;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+
+/// Child indexes at or above this are hardened
+pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+/// BIP-44 account path for Ethereum, the address index is appended
+pub const ETHEREUM_DERIVATION_PATH: &str = "m/44'/60'/0'/0";
+/// BIP-44 account path for Tron, the address index is appended
+pub const TRON_DERIVATION_PATH: &str = "m/44'/195'/0'/0";
+
+/// A parsed derivation path such as `m/44'/60'/0'/0/0`, hardened indexes may be
+/// written with `'`, `h` or `H`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(pub Vec<u32>);
+
+impl DerivationPath {
+    /// Returns a new path with `index` appended
+    pub fn child(&self, index: u32) -> DerivationPath {
+        let mut path = self.0.clone();
+        path.push(index);
+        DerivationPath(path)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Web3Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_path = || Web3Error::BadInput(format!("Invalid derivation path {s}"));
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(bad_path());
+        }
+        let mut path = Vec::new();
+        for part in parts {
+            let (number, hardened) = match part.strip_suffix(['\'', 'h', 'H']) {
+                Some(number) => (number, true),
+                None => (part, false),
+            };
+            let index: u32 = number.parse().map_err(|_| bad_path())?;
+            if index >= HARDENED_OFFSET {
+                return Err(bad_path());
+            }
+            path.push(if hardened {
+                index + HARDENED_OFFSET
+            } else {
+                index
+            });
+        }
+        Ok(DerivationPath(path))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in self.0.iter() {
+            if *index >= HARDENED_OFFSET {
+                write!(f, "/{}'", index - HARDENED_OFFSET)?;
+            } else {
+                write!(f, "/{index}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A BIP-32 extended private key
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedPrivateKey {
+    key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+// never print key material by accident
+impl fmt::Debug for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExtendedPrivateKey {{ .. }}")
+    }
+}
+
+impl ExtendedPrivateKey {
+    /// Derives the master key from a seed, usually the 64 byte BIP-39 seed
+    pub fn from_seed(seed: &[u8]) -> Result<ExtendedPrivateKey, Web3Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Web3Error::BadInput(format!(
+                "Seed length {} is not between 16 and 64 bytes",
+                seed.len()
+            )));
+        }
+        ExtendedPrivateKey::from_hmac(b"Bitcoin seed", seed)
+    }
+
+    fn from_hmac(key: &[u8], data: &[u8]) -> Result<ExtendedPrivateKey, Web3Error> {
+        let output = hmac_sha512(key, data);
+        let key = SecretKey::from_slice(&output[..32])
+            .map_err(|_| Web3Error::BadInput("Seed produces an invalid key".to_string()))?;
+        Ok(ExtendedPrivateKey {
+            key,
+            chain_code: output[32..].try_into().unwrap(),
+        })
+    }
+
+    /// Derives the child at `index`, hardened if `index` >= `HARDENED_OFFSET`
+    pub fn derive_child(&self, index: u32) -> Result<ExtendedPrivateKey, Web3Error> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED_OFFSET {
+            data.push(0);
+            data.extend_from_slice(&self.key.secret_bytes());
+        } else {
+            let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.key);
+            data.extend_from_slice(&public_key.serialize());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+
+        // the spec says to move on to the next index when this fails, the odds are
+        // below 1 in 2^127 so it is simply reported
+        let invalid = || Web3Error::BadInput(format!("Index {index} produces an invalid key"));
+        let output = hmac_sha512(&self.chain_code, &data);
+        let tweak =
+            Scalar::from_be_bytes(output[..32].try_into().unwrap()).map_err(|_| invalid())?;
+        let key = self.key.add_tweak(&tweak).map_err(|_| invalid())?;
+        Ok(ExtendedPrivateKey {
+            key,
+            chain_code: output[32..].try_into().unwrap(),
+        })
+    }
+
+    /// Derives every index of `path` in turn, the path must start at this key
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<ExtendedPrivateKey, Web3Error> {
+        let mut key = self.clone();
+        for index in path.0.iter() {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn private_key(&self) -> PrivateKey {
+        bytes_to_hex_str(&self.key.secret_bytes())
+            .parse()
+            .expect("secp256k1 keys are valid private keys")
+    }
+}
+
+/// A wallet deriving keys from a BIP-39 mnemonic
+#[derive(Clone)]
+pub struct HdWallet {
+    master: ExtendedPrivateKey,
+}
+
+impl fmt::Debug for HdWallet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HdWallet {{ .. }}")
+    }
+}
+
+impl HdWallet {
+    /// Creates a wallet from an English mnemonic and optional BIP-39 passphrase, the
+    /// mnemonic checksum is verified
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<HdWallet, Web3Error> {
+        let mnemonic = Mnemonic::parse(phrase)
+            .map_err(|e| Web3Error::BadInput(format!("Invalid mnemonic {e}")))?;
+        HdWallet::from_seed(&mnemonic.to_seed(passphrase))
+    }
+
+    pub fn from_seed(seed: &[u8]) -> Result<HdWallet, Web3Error> {
+        Ok(HdWallet {
+            master: ExtendedPrivateKey::from_seed(seed)?,
+        })
+    }
+
+    /// Generates a new random English mnemonic of 12, 15, 18, 21 or 24 words
+    pub fn generate_mnemonic(word_count: usize) -> Result<String, Web3Error> {
+        if word_count % 3 != 0 || !(12..=24).contains(&word_count) {
+            return Err(Web3Error::BadInput(format!(
+                "Invalid mnemonic length {word_count}"
+            )));
+        }
+        // every 3 words carry 32 bits of entropy and 1 bit of checksum
+        let mut entropy = vec![0u8; word_count / 3 * 4];
+        rand::thread_rng().fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy)
+            .map_err(|e| Web3Error::BadInput(format!("Invalid mnemonic entropy {e}")))?;
+        Ok(mnemonic.to_string())
+    }
+
+    /// Derives the key at an arbitrary path such as `m/44'/60'/0'/0/0`
+    pub fn derive(&self, path: &str) -> Result<PrivateKey, Web3Error> {
+        let path: DerivationPath = path.parse()?;
+        Ok(self.master.derive_path(&path)?.private_key())
+    }
+
+    /// Derives the Ethereum key at `m/44'/60'/0'/0/index`, the same keys MetaMask
+    /// and most other wallets produce from a mnemonic
+    pub fn ethereum_key(&self, index: u32) -> Result<PrivateKey, Web3Error> {
+        self.derive(&format!("{ETHEREUM_DERIVATION_PATH}/{index}"))
+    }
+
+    /// Derives the Tron key at `m/44'/195'/0'/0/index`, it can be used to send with a
+    /// `Web3` instance connected to a Tron node
+    pub fn tron_key(&self, index: u32) -> Result<PrivateKey, Web3Error> {
+        self.derive(&format!("{TRON_DERIVATION_PATH}/{index}"))
+    }
+
+    /// The Tron address of the key at `m/44'/195'/0'/0/index`
+    pub fn tron_address(&self, index: u32) -> Result<TronAddress, Web3Error> {
+        let key = self.tron_key(index)?;
+        let keypair = Keypair::from_bytes(&key.to_bytes())
+            .map_err(|_| Web3Error::BadInput("Invalid Tron key".to_string()))?;
+        Ok(keypair.address())
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    let mut output = [0u8; 64];
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clarity::utils::hex_str_to_bytes;
+    use clarity::Address;
+
+    #[test]
+    fn test_bip32_vector() {
+        // test vector 1 from BIP-32
+        let seed = hex_str_to_bytes("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivateKey::from_seed(&seed).unwrap();
+        assert_eq!(
+            bytes_to_hex_str(&master.chain_code()),
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508"
+        );
+
+        let expected = [
+            (
+                "m",
+                "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            ),
+            (
+                "m/0'",
+                "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            ),
+            (
+                "m/0'/1",
+                "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+            ),
+            (
+                "m/0H/1/2h",
+                "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+            ),
+        ];
+        for (path, key) in expected {
+            let path: DerivationPath = path.parse().unwrap();
+            let derived = master.derive_path(&path).unwrap();
+            assert_eq!(bytes_to_hex_str(&derived.private_key().to_bytes()), key);
+        }
+
+        assert_eq!(
+            "m/44h/60'/0'/0/1"
+                .parse::<DerivationPath>()
+                .unwrap()
+                .to_string(),
+            "m/44'/60'/0'/0/1"
+        );
+        assert!("44'/60'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+
+    #[test]
+    fn test_mnemonic_derivation() {
+        // the default hardhat and anvil development mnemonic
+        let wallet = HdWallet::from_mnemonic(
+            "test test test test test test test test test test test junk",
+            "",
+        )
+        .unwrap();
+        let expected: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap();
+        assert_eq!(wallet.ethereum_key(0).unwrap().to_address(), expected);
+        assert_eq!(
+            bytes_to_hex_str(&wallet.ethereum_key(1).unwrap().to_bytes()),
+            "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+        );
+
+        // same secp256k1 key and address bytes as Ethereum, only the path differs
+        let tron_key = wallet.tron_key(0).unwrap();
+        assert_eq!(
+            bytes_to_hex_str(&tron_key.to_bytes()),
+            "15f0bbb1774be40b7a8d7965d637f324bda2f711fc5726a3dcc19585c6950954"
+        );
+        assert_eq!(
+            wallet.tron_address(0).unwrap().to_string(),
+            "TWer2Ygk5TEheHp3TPuYeqxmB6SsGZmaL6"
+        );
+
+        // the passphrase changes the seed and so every derived key
+        let other = HdWallet::from_mnemonic(
+            "test test test test test test test test test test test junk",
+            "TREZOR",
+        )
+        .unwrap();
+        assert_ne!(
+            other.ethereum_key(0).unwrap(),
+            wallet.ethereum_key(0).unwrap()
+        );
+
+        // bad checksum
+        assert!(HdWallet::from_mnemonic(
+            "test test test test test test test test test test test test",
+            ""
+        )
+        .is_err());
+
+        let phrase = HdWallet::generate_mnemonic(24).unwrap();
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert!(HdWallet::from_mnemonic(&phrase, "").is_ok());
+    }
+}
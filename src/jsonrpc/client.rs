@@ -81,7 +81,7 @@ impl HttpClient {
             Err(e) => Err(Web3Error::JsonRpcError {
                 code: e.code,
                 message: e.message,
-                data: format!("{:?}", e.data),
+                data: e.data,
             }),
         }
     }
@@ -1,7 +1,9 @@
+use crate::revert::RevertReason;
 use awc::error::SendRequestError as ActixError;
 use clarity::Error as ClarityError;
 use clarity::Uint256;
 use heliosphere::Error as TronError;
+use serde_json::Value;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
@@ -16,7 +18,7 @@ pub enum Web3Error {
     JsonRpcError {
         code: i64,
         message: String,
-        data: String,
+        data: Option<Value>,
     },
     TronError(TronError),
     InsufficientGas {
@@ -36,6 +38,29 @@ pub enum Web3Error {
     },
     SyncingNode(String),
     PreLondon,
+    InvalidBlockHash {
+        expected: Uint256,
+        computed: Uint256,
+    },
+    InvalidTransactionsRoot {
+        expected: Uint256,
+        computed: Uint256,
+    },
+    ChainIdMismatch {
+        expected: u64,
+        reported: u64,
+    },
+    InvalidKeystore(String),
+    /// The keystore MAC does not match, almost always a wrong password
+    KeystoreMacMismatch,
+    /// A simulated transaction reverted, `data` is the raw revert payload
+    TransactionReverted {
+        reason: RevertReason,
+        data: Vec<u8>,
+    },
+    AbiDecodingError(String),
+    /// An event indexer checkpoint could not be loaded or saved
+    CheckpointError(String),
 }
 
 impl From<ParseIntError> for Web3Error {
@@ -108,6 +133,33 @@ impl Display for Web3Error {
             Web3Error::PreLondon => {
                 write!(f, "Web3, this function sends EIP1559 tx but the connected chain does not support them!")
             }
+            Web3Error::InvalidBlockHash { expected, computed } => {
+                write!(
+                    f,
+                    "Web3 block header hashes to {computed:#x} but the node reported {expected:#x}"
+                )
+            }
+            Web3Error::ChainIdMismatch { expected, reported } => {
+                write!(
+                    f,
+                    "Web3 refusing to sign, expected chain id {expected} but the node reports {reported}"
+                )
+            }
+            Web3Error::InvalidTransactionsRoot { expected, computed } => {
+                write!(
+                    f,
+                    "Web3 block transactions produce root {computed:#x} but the header contains {expected:#x}"
+                )
+            }
+            Web3Error::InvalidKeystore(val) => write!(f, "Web3 invalid keystore {val}"),
+            Web3Error::KeystoreMacMismatch => {
+                write!(f, "Web3 keystore MAC mismatch, the password is incorrect")
+            }
+            Web3Error::TransactionReverted { reason, .. } => {
+                write!(f, "Web3 transaction would revert: {reason}")
+            }
+            Web3Error::AbiDecodingError(val) => write!(f, "Web3 ABI decoding error {val}"),
+            Web3Error::CheckpointError(val) => write!(f, "Web3 checkpoint error {val}"),
         }
     }
 }
@@ -0,0 +1,507 @@
+//! This module implements the Web3 Secret Storage (v3 keystore) format used by geth and most
+//! wallets for encrypted key files. An unlocked keystore (`Keystore::unlock`) can be passed to
+//! any sending helper as a `Signer`. Scrypt keystores are only supported with parameters
+//! allowed by RFC 7914 (`n < 2^(16 r)`), which includes everything geth writes.
+//!
+//! See more https://ethereum.org/en/developers/docs/data-structures-and-encoding/web3-secret-storage/
+use crate::eip712::TypedData;
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::signer::Signer;
+use aes::Aes128;
+use async_trait::async_trait;
+use clarity::utils::bytes_to_hex_str;
+use clarity::{Address, PrivateKey, Signature, Transaction};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use sha2::Sha256;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use subtle::ConstantTimeEq;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+/// The only cipher defined by the v3 format
+const KEYSTORE_CIPHER: &str = "aes-128-ctr";
+
+/// Scrypt parameters geth uses for new keys, these take around a second to derive
+pub const DEFAULT_SCRYPT_N: u64 = 1 << 18;
+pub const DEFAULT_SCRYPT_R: u32 = 8;
+pub const DEFAULT_SCRYPT_P: u32 = 1;
+
+/// A v3 keystore file, only the encrypted key is kept so it is safe to write to disk
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Keystore {
+    pub crypto: KeystoreCrypto,
+    pub id: String,
+    pub version: u32,
+    /// Lower case hex without 0x, optional in the format but written by geth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeystoreCrypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    #[serde(with = "hex_bytes")]
+    pub ciphertext: Vec<u8>,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    #[serde(with = "hex_bytes")]
+    pub mac: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CipherParams {
+    #[serde(with = "hex_bytes")]
+    pub iv: Vec<u8>,
+}
+
+/// Key derivation parameters, which variant is expected is named by `KeystoreCrypto::kdf`.
+/// Scrypt keystores with `n >= 2^(16 r)` are outside RFC 7914 and can not be decrypted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u64,
+        r: u32,
+        p: u32,
+        #[serde(with = "hex_bytes")]
+        salt: Vec<u8>,
+    },
+    Pbkdf2 {
+        c: u32,
+        dklen: usize,
+        prf: String,
+        #[serde(with = "hex_bytes")]
+        salt: Vec<u8>,
+    },
+}
+
+impl KdfParams {
+    /// Scrypt with geth's default cost and a random salt
+    pub fn scrypt() -> Self {
+        KdfParams::Scrypt {
+            dklen: 32,
+            n: DEFAULT_SCRYPT_N,
+            r: DEFAULT_SCRYPT_R,
+            p: DEFAULT_SCRYPT_P,
+            salt: random_bytes(32),
+        }
+    }
+
+    /// PBKDF2-HMAC-SHA256 with `rounds` iterations and a random salt
+    pub fn pbkdf2(rounds: u32) -> Self {
+        KdfParams::Pbkdf2 {
+            c: rounds,
+            dklen: 32,
+            prf: "hmac-sha256".to_string(),
+            salt: random_bytes(32),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            KdfParams::Scrypt { .. } => "scrypt",
+            KdfParams::Pbkdf2 { .. } => "pbkdf2",
+        }
+    }
+
+    /// Derives the key, the first 16 bytes are the AES key and the next 16 the MAC key
+    fn derive(&self, password: &str) -> Result<Vec<u8>, Web3Error> {
+        match self {
+            KdfParams::Scrypt {
+                dklen,
+                n,
+                r,
+                p,
+                salt,
+            } => {
+                if *dklen < 32 || *n < 2 || !n.is_power_of_two() {
+                    return Err(Web3Error::InvalidKeystore(format!(
+                        "Invalid scrypt parameters n {n} dklen {dklen}"
+                    )));
+                }
+                // RFC 7914 requires n < 2^(16 r), geth never writes such keystores but some
+                // tools (and the r = 1 example in the specification) do
+                let log_n = n.trailing_zeros();
+                if u64::from(log_n) >= 16 * u64::from(*r) {
+                    return Err(Web3Error::InvalidKeystore(format!(
+                        "Unsupported scrypt parameters n {n} r {r}, n must be below 2^(16 r)"
+                    )));
+                }
+                let params = scrypt::Params::new(log_n as u8, *r, *p, *dklen).map_err(|e| {
+                    Web3Error::InvalidKeystore(format!("Invalid scrypt parameters {e}"))
+                })?;
+                let mut key = vec![0u8; *dklen];
+                scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+                    .map_err(|e| Web3Error::InvalidKeystore(format!("Scrypt failed {e}")))?;
+                Ok(key)
+            }
+            KdfParams::Pbkdf2 {
+                c,
+                dklen,
+                prf,
+                salt,
+            } => {
+                if prf != "hmac-sha256" {
+                    return Err(Web3Error::InvalidKeystore(format!(
+                        "Unsupported pbkdf2 prf {prf}"
+                    )));
+                }
+                if *dklen < 32 {
+                    return Err(Web3Error::InvalidKeystore(format!(
+                        "Invalid pbkdf2 dklen {dklen}"
+                    )));
+                }
+                let mut key = vec![0u8; *dklen];
+                pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, *c, &mut key);
+                Ok(key)
+            }
+        }
+    }
+}
+
+impl Keystore {
+    /// Encrypts a key with the given password using scrypt at geth's default cost
+    pub fn encrypt(key: &PrivateKey, password: &str) -> Result<Keystore, Web3Error> {
+        Keystore::encrypt_with_params(key, password, KdfParams::scrypt())
+    }
+
+    /// Encrypts a key with the given password and key derivation parameters
+    pub fn encrypt_with_params(
+        key: &PrivateKey,
+        password: &str,
+        kdfparams: KdfParams,
+    ) -> Result<Keystore, Web3Error> {
+        let derived = kdfparams.derive(password)?;
+        let iv = random_bytes(16);
+        let mut ciphertext = key.to_bytes().to_vec();
+        apply_cipher(&derived, &iv, &mut ciphertext)?;
+        let mac = compute_mac(&derived, &ciphertext).to_vec();
+
+        Ok(Keystore {
+            crypto: KeystoreCrypto {
+                cipher: KEYSTORE_CIPHER.to_string(),
+                cipherparams: CipherParams { iv },
+                ciphertext,
+                kdf: kdfparams.name().to_string(),
+                kdfparams,
+                mac,
+            },
+            id: random_uuid(),
+            version: 3,
+            address: Some(bytes_to_hex_str(key.to_address().as_bytes())),
+        })
+    }
+
+    /// Decrypts the key, fails with `KeystoreMacMismatch` if the password is wrong
+    pub fn decrypt(&self, password: &str) -> Result<PrivateKey, Web3Error> {
+        if self.version != 3 {
+            return Err(Web3Error::InvalidKeystore(format!(
+                "Unsupported version {}",
+                self.version
+            )));
+        }
+        let crypto = &self.crypto;
+        if crypto.cipher != KEYSTORE_CIPHER {
+            return Err(Web3Error::InvalidKeystore(format!(
+                "Unsupported cipher {}",
+                crypto.cipher
+            )));
+        }
+        if crypto.kdf != crypto.kdfparams.name() {
+            return Err(Web3Error::InvalidKeystore(format!(
+                "Parameters do not match kdf {}",
+                crypto.kdf
+            )));
+        }
+
+        let derived = crypto.kdfparams.derive(password)?;
+        // compared in constant time so the comparison leaks nothing about the expected mac
+        let mac = compute_mac(&derived, &crypto.ciphertext);
+        if !bool::from(mac[..].ct_eq(&crypto.mac[..])) {
+            return Err(Web3Error::KeystoreMacMismatch);
+        }
+
+        let mut plaintext = crypto.ciphertext.clone();
+        apply_cipher(&derived, &crypto.cipherparams.iv, &mut plaintext)?;
+        let key: PrivateKey = bytes_to_hex_str(&plaintext)
+            .parse()
+            .map_err(|e| Web3Error::InvalidKeystore(format!("Invalid private key {e}")))?;
+
+        if let Some(address) = &self.address {
+            let expected: Address = format!("0x{}", address.trim_start_matches("0x"))
+                .parse()
+                .map_err(|e| Web3Error::InvalidKeystore(format!("Invalid address {e}")))?;
+            if expected != key.to_address() {
+                return Err(Web3Error::InvalidKeystore(format!(
+                    "Key does not belong to address {expected}"
+                )));
+            }
+        }
+        Ok(key)
+    }
+
+    /// Decrypts the key and returns a signer for it which keeps the keystore alongside
+    pub fn unlock(self, password: &str) -> Result<UnlockedKeystore, Web3Error> {
+        let key = self.decrypt(password)?;
+        Ok(UnlockedKeystore {
+            keystore: self,
+            key,
+        })
+    }
+
+    /// Reads a keystore file
+    pub fn load(path: impl AsRef<Path>) -> Result<Keystore, Web3Error> {
+        let file = fs::read_to_string(path)
+            .map_err(|e| Web3Error::InvalidKeystore(format!("Failed to read keystore {e}")))?;
+        serde_json::from_str(&file)
+            .map_err(|e| Web3Error::InvalidKeystore(format!("Failed to parse keystore {e}")))
+    }
+
+    /// Writes the keystore as JSON to a file readable only by its owner. The file is written
+    /// next to `path` first and then renamed over it, so an existing keystore is never left
+    /// truncated
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Web3Error> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Web3Error::InvalidKeystore(format!("Failed to encode keystore {e}")))?;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let _ = fs::remove_file(&tmp);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| {
+                file.write_all(json.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                Web3Error::InvalidKeystore(format!("Failed to write keystore {e}"))
+            })
+    }
+}
+
+/// A keystore decrypted with `Keystore::unlock`, signs with the decrypted key. Not `Debug`
+/// so the key does not end up in logs
+#[derive(Clone)]
+pub struct UnlockedKeystore {
+    keystore: Keystore,
+    key: PrivateKey,
+}
+
+impl UnlockedKeystore {
+    /// Loads and unlocks a keystore file in one step
+    pub fn open(path: impl AsRef<Path>, password: &str) -> Result<UnlockedKeystore, Web3Error> {
+        Keystore::load(path)?.unlock(password)
+    }
+
+    /// The encrypted keystore this signer was unlocked from
+    pub fn keystore(&self) -> &Keystore {
+        &self.keystore
+    }
+}
+
+#[async_trait(?Send)]
+impl Signer for UnlockedKeystore {
+    fn address(&self) -> Address {
+        self.key.to_address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: Transaction,
+        network_id: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error> {
+        self.key.sign_transaction(transaction, network_id).await
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, Web3Error> {
+        self.key.sign_message(message).await
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature, Web3Error> {
+        self.key.sign_typed_data(typed_data).await
+    }
+
+    fn private_key(&self) -> Option<PrivateKey> {
+        Some(self.key)
+    }
+}
+
+/// Loads and decrypts a keystore file in one step
+pub fn decrypt_keystore_file(
+    path: impl AsRef<Path>,
+    password: &str,
+) -> Result<PrivateKey, Web3Error> {
+    Keystore::load(path)?.decrypt(password)
+}
+
+/// keccak256(derived_key[16..32] ‖ ciphertext)
+fn compute_mac(derived: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut payload = derived[16..32].to_vec();
+    payload.extend_from_slice(ciphertext);
+    keccak256(&payload)
+}
+
+/// AES-128-CTR with the first 16 bytes of the derived key, encrypts and decrypts
+fn apply_cipher(derived: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Web3Error> {
+    let mut cipher = Aes128Ctr::new_from_slices(&derived[..16], iv)
+        .map_err(|_| Web3Error::InvalidKeystore(format!("Invalid iv length {}", iv.len())))?;
+    cipher.apply_keystream(data);
+    Ok(())
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// A random (version 4) UUID
+fn random_uuid() -> String {
+    let mut bytes = random_bytes(16);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes_to_hex_str(&bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Keystores encode binary values as hex without a 0x prefix
+mod hex_bytes {
+    use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&bytes_to_hex_str(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex_str_to_bytes(&s).map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The key and password behind the specification's test vectors, its scrypt vector uses
+    /// parameters that are unsupported so only the pbkdf2 one decrypts
+    const VECTOR_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+    const VECTOR_PASSWORD: &str = "testpassword";
+
+    #[test]
+    fn test_keystore_pbkdf2_vector() {
+        let keystore: Keystore = serde_json::from_str(
+            r#"{
+                "crypto" : {
+                    "cipher" : "aes-128-ctr",
+                    "cipherparams" : {"iv" : "6087dab2f9fdbbfaddc31a909735c1e6"},
+                    "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                    "kdf" : "pbkdf2",
+                    "kdfparams" : {
+                        "c" : 262144,
+                        "dklen" : 32,
+                        "prf" : "hmac-sha256",
+                        "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                    },
+                    "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+                },
+                "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                "version" : 3
+            }"#,
+        )
+        .unwrap();
+        let key = keystore.decrypt(VECTOR_PASSWORD).unwrap();
+        assert_eq!(bytes_to_hex_str(&key.to_bytes()), VECTOR_KEY);
+        assert!(matches!(
+            keystore.decrypt("wrongpassword"),
+            Err(Web3Error::KeystoreMacMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_keystore_scrypt_vector() {
+        // the specification's scrypt vector uses r = 1 with n = 2^18, outside RFC 7914
+        let keystore: Keystore = serde_json::from_str(
+            r#"{
+                "crypto" : {
+                    "cipher" : "aes-128-ctr",
+                    "cipherparams" : {"iv" : "83dbcc02d8ccb40e466191a123791e0e"},
+                    "ciphertext" : "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+                    "kdf" : "scrypt",
+                    "kdfparams" : {
+                        "dklen" : 32,
+                        "n" : 262144,
+                        "r" : 1,
+                        "p" : 8,
+                        "salt" : "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                    },
+                    "mac" : "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+                },
+                "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                "version" : 3
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            keystore.decrypt(VECTOR_PASSWORD),
+            Err(Web3Error::InvalidKeystore(_))
+        ));
+    }
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let key: PrivateKey = VECTOR_KEY.parse().unwrap();
+        // cheap parameters, the default cost is far too slow for a debug build
+        let params = KdfParams::Scrypt {
+            dklen: 32,
+            n: 1024,
+            r: 8,
+            p: 1,
+            salt: random_bytes(32),
+        };
+        for params in [params, KdfParams::pbkdf2(1000)] {
+            let keystore = Keystore::encrypt_with_params(&key, "hunter2", params).unwrap();
+            let path = std::env::temp_dir().join(format!("web30-keystore-{}.json", keystore.id));
+            keystore.save(&path).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = fs::metadata(&path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+            // saving again replaces the file
+            keystore.save(&path).unwrap();
+            let decrypted = decrypt_keystore_file(&path, "hunter2").unwrap();
+            let unlocked = UnlockedKeystore::open(&path, "hunter2").unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(decrypted, key);
+            assert_eq!(unlocked.address(), key.to_address());
+            assert_eq!(unlocked.private_key(), Some(key));
+            assert_eq!(unlocked.keystore(), &keystore);
+            assert!(keystore.decrypt("hunter3").is_err());
+        }
+    }
+}
@@ -8,20 +8,48 @@ extern crate serde_derive;
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
+// lets the ContractEvent derive refer to ::web30 from within this crate
+extern crate self as web30;
 
+pub mod abi;
 pub mod amm;
+pub mod block_tracker;
+mod block_verification;
+pub mod bloom;
 pub mod client;
+pub mod contract;
+mod contract_deployment;
+pub mod eip712;
 mod erc20_utils;
 mod erc721_utils;
 pub mod eth_wrapping;
+pub mod event_indexer;
 mod event_utils;
+pub mod filter;
 pub mod gas_estimator;
+mod hash_utils;
+pub mod hd_wallet;
 pub mod jsonrpc;
+pub mod keystore;
+pub mod log_fetcher;
 mod mem;
+pub mod multicall;
+mod native_transfer;
+pub mod nonce_manager;
+pub mod revert;
+mod rlp;
+pub mod signer;
 mod tron_utils;
+mod tx_replacement;
 pub mod types;
 
 pub use clarity::Address as EthAddress;
+pub use contract_deployment::{create2_address, create_address};
+pub use erc20_utils::Erc20Metadata;
 pub use event_utils::address_to_event;
 pub use event_utils::{ContractEvent, Web3Event};
 pub use heliosphere::core::{event::EventData, Address as TronAddress};
+pub use tx_replacement::{
+    bump_fee, BroadcastAttempt, RebroadcastConfig, RebroadcastResult, MIN_REPLACEMENT_BUMP_PERCENT,
+};
+pub use web30_derive::ContractEvent;
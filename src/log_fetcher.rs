@@ -0,0 +1,447 @@
+//! This module contains chunked `eth_getLogs` fetching for large block ranges. Most providers
+//! reject log queries that span too many blocks or return too many results, so ranges are split
+//! into chunks which shrink when the provider complains and grow again once queries succeed.
+use crate::bloom::Bloom;
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{Log, NewFilter};
+use clarity::Uint256;
+use futures::future::join_all;
+use num_traits::ToPrimitive;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+
+/// Fragments of the error messages providers return when a log query covers too many blocks or
+/// too many results, matched case insensitively
+const RANGE_LIMIT_ERRORS: [&str; 8] = [
+    "query returned more than",
+    "block range",
+    "range is too large",
+    "range too large",
+    "is limited to a",
+    "query timeout",
+    "response size",
+    "response is too big",
+];
+
+/// Fragments of rate limit errors, these may also mention limits or ranges but retrying over a
+/// smaller range only makes them worse
+const RATE_LIMIT_ERRORS: [&str; 4] = [
+    "rate limit",
+    "daily request count exceeded",
+    "429",
+    "too many requests",
+];
+
+/// Settings for chunked log fetching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogFetchOptions {
+    /// Number of blocks queried by the first chunks
+    pub initial_chunk_size: u64,
+    /// Chunks are never split below this size, a range limit error on a chunk of this size is
+    /// returned to the caller
+    pub min_chunk_size: u64,
+    /// Chunks never grow above this size
+    pub max_chunk_size: u64,
+    /// Maximum number of `eth_getLogs` requests in flight at once
+    pub concurrency: usize,
+    /// Fetch every block header in the range and only query the logs of blocks whose logs
+    /// bloom matches the filter. Worth it for sparse events on providers where header calls are
+    /// cheaper than log queries
+    pub bloom_filter: bool,
+    /// Maximum number of block headers requested at once when `bloom_filter` is set
+    pub header_batch_size: usize,
+}
+
+impl Default for LogFetchOptions {
+    fn default() -> Self {
+        LogFetchOptions {
+            initial_chunk_size: 2_000,
+            min_chunk_size: 1,
+            max_chunk_size: 100_000,
+            concurrency: 4,
+            bloom_filter: false,
+            header_batch_size: 16,
+        }
+    }
+}
+
+/// Returns true if `error` is a provider rejecting a log query for covering too many blocks or
+/// returning too many results, these queries succeed if retried over a smaller range
+pub fn is_range_limit_error(error: &Web3Error) -> bool {
+    let message = match error {
+        Web3Error::JsonRpcError { message, .. } => message,
+        Web3Error::BadResponse(message) => message,
+        _ => return false,
+    };
+    let message = message.to_lowercase();
+    !RATE_LIMIT_ERRORS.iter().any(|e| message.contains(e))
+        && RANGE_LIMIT_ERRORS.iter().any(|e| message.contains(e))
+}
+
+impl Web3 {
+    /// Fetches the logs matching `filter` between `start_block` and `end_block` inclusive, or the
+    /// latest block if no end is provided. The block range and hash of `filter` are ignored.
+    /// The range is queried in chunks of at most `options.concurrency` concurrent requests, a chunk
+    /// rejected for covering too much is split in half and retried and the chunk size is halved,
+    /// while a batch of successful chunks doubles it. Logs are returned ordered by block and log
+    /// index with duplicates removed. If `options.bloom_filter` is set headers are checked instead,
+    /// see [`LogFetchOptions::bloom_filter`].
+    pub async fn get_logs_chunked(
+        &self,
+        filter: NewFilter,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let start = match start_block.to_u64() {
+            Some(v) => v,
+            None => {
+                return Err(Web3Error::BadInput(format!(
+                    "Invalid start block {start_block}"
+                )))
+            }
+        };
+        let end = match end_block {
+            Some(end_block) => match end_block.to_u64() {
+                Some(v) => v,
+                None => {
+                    return Err(Web3Error::BadInput(format!(
+                        "Invalid end block {end_block}"
+                    )))
+                }
+            },
+            None => self.eth_block_number().await?.to_u64().unwrap_or(u64::MAX),
+        };
+        if start > end {
+            return Ok(Vec::new());
+        }
+        if options.bloom_filter {
+            return self
+                .get_logs_bloom_filtered(filter, start, end, options)
+                .await;
+        }
+
+        fetch_logs_chunked(&filter, start, end, options, |filter| {
+            self.eth_get_logs(filter)
+        })
+        .await
+    }
+
+    /// Fetches the header of every block from `start` to `end` and the logs of the blocks whose
+    /// bloom may contain a match, querying by block hash so the logs belong to the header checked
+    async fn get_logs_bloom_filtered(
+        &self,
+        filter: NewFilter,
+        start: u64,
+        end: u64,
+        options: LogFetchOptions,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let batch_size = options.header_batch_size.max(1) as u64;
+        let mut logs = Vec::new();
+        let mut from = start;
+        while from <= end {
+            let to = from.saturating_add(batch_size - 1).min(end);
+            let blocks = join_all(
+                (from..=to).map(|number| self.eth_get_concise_block_by_number(number.into())),
+            )
+            .await;
+
+            let mut matching = Vec::new();
+            for block in blocks {
+                let block = block?;
+                if Bloom::try_from(&block.logs_bloom)?.matches_filter(&filter)? {
+                    matching.push(block.hash);
+                }
+            }
+            // log queries are still limited to `concurrency` in flight
+            for hashes in matching.chunks(options.concurrency.max(1)) {
+                let results = join_all(hashes.iter().map(|hash| {
+                    let mut filter = filter.clone();
+                    filter.from_block = None;
+                    filter.to_block = None;
+                    filter.block_hash = Some(format!("{hash:#066x}"));
+                    self.eth_get_logs(filter)
+                }))
+                .await;
+                for result in results {
+                    logs.extend(result?);
+                }
+            }
+
+            if to == end {
+                break;
+            }
+            from = to + 1;
+        }
+        Ok(sort_and_dedup_logs(logs))
+    }
+}
+
+/// The chunking loop of `get_logs_chunked`, `get_logs` makes a single `eth_getLogs` query
+async fn fetch_logs_chunked<F, Fut>(
+    filter: &NewFilter,
+    start: u64,
+    end: u64,
+    options: LogFetchOptions,
+    get_logs: F,
+) -> Result<Vec<Log>, Web3Error>
+where
+    F: Fn(NewFilter) -> Fut,
+    Fut: Future<Output = Result<Vec<Log>, Web3Error>>,
+{
+    let min_chunk = options.min_chunk_size.max(1);
+    let max_chunk = options.max_chunk_size.max(min_chunk);
+    let mut chunk = options.initial_chunk_size.clamp(min_chunk, max_chunk);
+    let concurrency = options.concurrency.max(1);
+
+    // ranges which failed and were split, these are retried before any new range
+    let mut retry: VecDeque<(u64, u64)> = VecDeque::new();
+    // the first block not yet covered by any range
+    let mut next = Some(start);
+    // logs by the first block of the chunk which returned them
+    let mut fetched: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
+
+    while !retry.is_empty() || next.is_some() {
+        let mut batch = Vec::new();
+        while batch.len() < concurrency {
+            if let Some(range) = retry.pop_front() {
+                batch.push(range);
+            } else if let Some(from) = next {
+                let to = from.saturating_add(chunk - 1).min(end);
+                batch.push((from, to));
+                next = if to == end { None } else { Some(to + 1) };
+            } else {
+                break;
+            }
+        }
+
+        let results = join_all(batch.iter().map(|(from, to)| {
+            let mut filter = filter.clone();
+            filter.from_block = Some(format!("{:#x}", Uint256::from(*from)));
+            filter.to_block = Some(format!("{:#x}", Uint256::from(*to)));
+            filter.block_hash = None;
+            get_logs(filter)
+        }))
+        .await;
+
+        let mut split = Vec::new();
+        for ((from, to), result) in batch.into_iter().zip(results) {
+            match result {
+                Ok(logs) => {
+                    fetched.insert(from, logs);
+                }
+                Err(e) if is_range_limit_error(&e) && to - from + 1 > min_chunk => {
+                    let mid = from + (to - from) / 2;
+                    split.push((from, mid));
+                    split.push((mid + 1, to));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if split.is_empty() {
+            chunk = chunk.saturating_mul(2).min(max_chunk);
+        } else {
+            let largest = split.iter().map(|(from, to)| to - from + 1).max();
+            chunk = (chunk / 2).min(largest.unwrap_or(chunk)).max(min_chunk);
+            for range in split.into_iter().rev() {
+                retry.push_front(range);
+            }
+        }
+    }
+
+    Ok(sort_and_dedup_logs(
+        fetched.into_values().flatten().collect(),
+    ))
+}
+
+/// Sorts logs by block and log index and removes logs returned more than once
+fn sort_and_dedup_logs(mut logs: Vec<Log>) -> Vec<Log> {
+    logs.sort_by(|a, b| (&a.block_number, &a.log_index).cmp(&(&b.block_number, &b.log_index)));
+    logs.dedup_by(|a, b| {
+        a.block_number == b.block_number
+            && a.log_index == b.log_index
+            && a.transaction_hash == b.transaction_hash
+    });
+    logs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Data;
+    use actix::System;
+    use std::cell::RefCell;
+
+    fn log(block: u64, index: u64) -> Log {
+        Log {
+            block_number: Some(block.into()),
+            log_index: Some(index.into()),
+            transaction_hash: Some(Data(vec![block as u8])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_and_dedup_logs() {
+        let logs = vec![log(5, 1), log(3, 0), log(5, 0), log(3, 0), log(5, 1)];
+        let expected = vec![log(3, 0), log(5, 0), log(5, 1)];
+        assert_eq!(sort_and_dedup_logs(logs), expected);
+    }
+
+    #[test]
+    fn test_is_range_limit_error() {
+        let error = |message: &str| Web3Error::JsonRpcError {
+            code: -32005,
+            message: message.to_string(),
+            data: None,
+        };
+        assert!(is_range_limit_error(&error(
+            "query returned more than 10000 results"
+        )));
+        assert!(is_range_limit_error(&error(
+            "eth_getLogs is limited to a 10,000 block range"
+        )));
+        assert!(is_range_limit_error(&error("Log response size exceeded.")));
+        assert!(is_range_limit_error(&error(
+            "block range is too wide, max 5000"
+        )));
+        assert!(!is_range_limit_error(&error("execution reverted")));
+        assert!(!is_range_limit_error(&error("limit exceeded")));
+        assert!(!is_range_limit_error(&error(
+            "daily request count exceeded, request rate limited"
+        )));
+        assert!(!is_range_limit_error(&error(
+            "429 Too Many Requests: block range is too large"
+        )));
+        assert!(!is_range_limit_error(&Web3Error::BadInput(
+            "more than".to_string()
+        )));
+    }
+
+    /// Parses the block range of a chunk filter
+    fn range(filter: &NewFilter) -> (u64, u64) {
+        let parse = |v: &Option<String>| {
+            u64::from_str_radix(v.as_ref().unwrap().trim_start_matches("0x"), 16).unwrap()
+        };
+        (parse(&filter.from_block), parse(&filter.to_block))
+    }
+
+    /// The block ranges queried from a mock provider, in order
+    type Queries = Vec<(u64, u64)>;
+
+    /// Fetches `start..=end` from a provider which rejects ranges of more than `limit` blocks
+    /// with `error` and has a log in every tenth block, returns the result and the queried ranges
+    fn fetch(
+        start: u64,
+        end: u64,
+        options: LogFetchOptions,
+        limit: u64,
+        error: &str,
+    ) -> (Result<Vec<Log>, Web3Error>, Queries) {
+        let queries = RefCell::new(Vec::new());
+        let get_logs = |filter: NewFilter| {
+            let (from, to) = range(&filter);
+            queries.borrow_mut().push((from, to));
+            let result = if to - from + 1 > limit {
+                Err(Web3Error::JsonRpcError {
+                    code: -32005,
+                    message: error.to_string(),
+                    data: None,
+                })
+            } else {
+                Ok((from..=to)
+                    .filter(|b| b % 10 == 0)
+                    .map(|b| log(b, 0))
+                    .collect())
+            };
+            async move { result }
+        };
+        let result = System::new().block_on(fetch_logs_chunked(
+            &NewFilter::default(),
+            start,
+            end,
+            options,
+            get_logs,
+        ));
+        (result, queries.into_inner())
+    }
+
+    #[test]
+    fn test_chunk_growth() {
+        let options = LogFetchOptions {
+            initial_chunk_size: 100,
+            concurrency: 1,
+            ..Default::default()
+        };
+        let (logs, queries) = fetch(0, 699, options, u64::MAX, "");
+        assert_eq!(logs.unwrap().len(), 70);
+        assert_eq!(queries, vec![(0, 99), (100, 299), (300, 699)]);
+
+        // never above the maximum
+        let options = LogFetchOptions {
+            max_chunk_size: 150,
+            ..options
+        };
+        let (_, queries) = fetch(0, 399, options, u64::MAX, "");
+        assert_eq!(queries, vec![(0, 99), (100, 249), (250, 399)]);
+    }
+
+    #[test]
+    fn test_chunk_split() {
+        let options = LogFetchOptions {
+            initial_chunk_size: 400,
+            concurrency: 2,
+            ..Default::default()
+        };
+        let error = "query returned more than 10000 results";
+        let (logs, queries) = fetch(0, 999, options, 150, error);
+        let expected: Vec<Log> = (0..100).map(|b| log(b * 10, 0)).collect();
+        assert_eq!(logs.unwrap(), expected);
+
+        // the first batch is split in half twice before queries fit
+        assert_eq!(
+            queries[..6],
+            [
+                (0, 399),
+                (400, 799),
+                (0, 199),
+                (200, 399),
+                (0, 99),
+                (100, 199)
+            ]
+        );
+        // and every block is covered by exactly one successful query
+        let mut covered: Vec<(u64, u64)> = queries
+            .into_iter()
+            .filter(|(from, to)| to - from < 150)
+            .collect();
+        covered.sort();
+        assert_eq!(covered.first().unwrap().0, 0);
+        assert_eq!(covered.last().unwrap().1, 999);
+        assert!(covered.windows(2).all(|w| w[0].1 + 1 == w[1].0));
+    }
+
+    #[test]
+    fn test_chunk_errors() {
+        let options = LogFetchOptions {
+            initial_chunk_size: 400,
+            concurrency: 1,
+            ..Default::default()
+        };
+        // rate limits are returned instead of retried over smaller ranges
+        let (logs, queries) = fetch(0, 999, options, 150, "429 Too Many Requests");
+        assert!(logs.is_err());
+        assert_eq!(queries, vec![(0, 399)]);
+
+        // as is a range limit on a chunk that can't be split further
+        let options = LogFetchOptions {
+            min_chunk_size: 200,
+            ..options
+        };
+        let (logs, queries) = fetch(0, 999, options, 150, "block range too large");
+        assert!(logs.is_err());
+        assert_eq!(queries, vec![(0, 399), (0, 199)]);
+    }
+}
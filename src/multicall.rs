@@ -0,0 +1,205 @@
+//! This module contains a client for Multicall3, which executes many contract reads in a single
+//! `eth_call` so that batches of queries cost one round trip instead of one per call.
+use crate::abi::{decode_single, AbiTokenExt};
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use clarity::Address;
+
+/// Selector of `aggregate3((address,bool,bytes)[])`
+const AGGREGATE3_SELECTOR: [u8; 4] = [0x82, 0xad, 0x56, 0xcb];
+
+lazy_static! {
+    /// Multicall3 is deployed at the same address on Ethereum and most EVM chains
+    pub static ref MULTICALL3_ADDRESS: Address =
+        Address::parse_and_validate("0xcA11bde05977b3631167028862bE2a173976CA11").unwrap();
+}
+
+/// A single call in a Multicall3 batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call3 {
+    pub target: Address,
+    /// If false a revert of this call reverts the whole batch
+    pub allow_failure: bool,
+    pub call_data: Vec<u8>,
+}
+
+impl Call3 {
+    /// A call whose failure is reported in its result instead of failing the batch
+    pub fn new(target: Address, call_data: Vec<u8>) -> Self {
+        Call3 {
+            target,
+            allow_failure: true,
+            call_data,
+        }
+    }
+}
+
+/// The outcome of a single call in a Multicall3 batch, `return_data` is the revert data
+/// if the call failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallResult {
+    pub success: bool,
+    pub return_data: Vec<u8>,
+}
+
+impl CallResult {
+    /// The return data of a successful call
+    pub fn data(&self) -> Option<&[u8]> {
+        if self.success {
+            Some(&self.return_data)
+        } else {
+            None
+        }
+    }
+}
+
+impl Web3 {
+    /// Executes `calls` with a single `aggregate3` call to Multicall3 and returns one result per
+    /// call, in order. Calls with `allow_failure` set report failure in their result, any other
+    /// failing call makes the whole batch fail. The default Multicall3 deployment is used if
+    /// `multicall` is None.
+    pub async fn multicall(
+        &self,
+        calls: &[Call3],
+        caller_address: Address,
+        multicall: Option<Address>,
+    ) -> Result<Vec<CallResult>, Web3Error> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let multicall = multicall.unwrap_or(*MULTICALL3_ADDRESS);
+        let payload = encode_aggregate3(calls);
+        let result = self
+            .simulate_transaction(multicall, payload, caller_address, None)
+            .await?;
+        let results = decode_aggregate3(&result)?;
+        if results.len() != calls.len() {
+            return Err(Web3Error::BadResponse(format!(
+                "Multicall returned {} results for {} calls",
+                results.len(),
+                calls.len()
+            )));
+        }
+        Ok(results)
+    }
+}
+
+fn push_word(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&[0u8; 24]);
+    out.extend_from_slice(&(value as u64).to_be_bytes());
+}
+
+fn push_padded(out: &mut Vec<u8>, bytes: &[u8]) {
+    push_word(out, bytes.len());
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + (32 - bytes.len() % 32) % 32, 0);
+}
+
+/// ABI encodes `aggregate3(calls)`, each `(address,bool,bytes)` tuple is dynamic so the array
+/// holds offsets to the tuples which follow it
+fn encode_aggregate3(calls: &[Call3]) -> Vec<u8> {
+    let mut tuples = Vec::new();
+    let mut offsets = Vec::new();
+    for call in calls {
+        push_word(&mut offsets, calls.len() * 32 + tuples.len());
+        tuples.extend_from_slice(&[0u8; 12]);
+        tuples.extend_from_slice(call.target.as_bytes());
+        push_word(&mut tuples, call.allow_failure as usize);
+        // the bytes follow the three head words of the tuple
+        push_word(&mut tuples, 3 * 32);
+        push_padded(&mut tuples, &call.call_data);
+    }
+
+    let mut out = AGGREGATE3_SELECTOR.to_vec();
+    push_word(&mut out, 32);
+    push_word(&mut out, calls.len());
+    out.extend_from_slice(&offsets);
+    out.extend_from_slice(&tuples);
+    out
+}
+
+/// ABI decodes the `(bool,bytes)[]` returned by `aggregate3`
+fn decode_aggregate3(data: &[u8]) -> Result<Vec<CallResult>, Web3Error> {
+    let mut results = Vec::new();
+    for result in decode_single("(bool,bytes)[]", data)?.into_array()? {
+        let mut result = result.into_array()?.into_iter();
+        match (result.next(), result.next()) {
+            (Some(success), Some(return_data)) => results.push(CallResult {
+                success: success.into_bool()?,
+                return_data: return_data.into_bytes()?,
+            }),
+            _ => unreachable!("a (bool,bytes) tuple decodes to two tokens"),
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clarity::utils::hex_str_to_bytes;
+    use clarity::Uint256;
+
+    #[test]
+    fn test_encode_aggregate3() {
+        let target: Address = "0x6b175474e89094c44da98b954eedeac495271d0f"
+            .parse()
+            .unwrap();
+        let calls = vec![
+            Call3::new(target, vec![0x18, 0x16, 0x0d, 0xdd]),
+            Call3 {
+                target,
+                allow_failure: false,
+                call_data: vec![0x31, 0x3c, 0xe5, 0x67],
+            },
+        ];
+        let expected = hex_str_to_bytes(
+            "0x82ad56cb\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000002\
+            0000000000000000000000000000000000000000000000000000000000000040\
+            00000000000000000000000000000000000000000000000000000000000000e0\
+            0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000060\
+            0000000000000000000000000000000000000000000000000000000000000004\
+            18160ddd00000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000006b175474e89094c44da98b954eedeac495271d0f\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000000000000000000000000000000000060\
+            0000000000000000000000000000000000000000000000000000000000000004\
+            313ce56700000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        assert_eq!(encode_aggregate3(&calls), expected);
+    }
+
+    #[test]
+    fn test_decode_aggregate3() {
+        // a successful totalSupply() followed by a call that reverted without data
+        let response = hex_str_to_bytes(
+            "0x0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000002\
+            0000000000000000000000000000000000000000000000000000000000000040\
+            00000000000000000000000000000000000000000000000000000000000000c0\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000040\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            00000000000000000000000000000000000000000000000000000000000003e8\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000000000000000000000000000000000040\
+            0000000000000000000000000000000000000000000000000000000000000000",
+        )
+        .unwrap();
+        let results = decode_aggregate3(&response).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            Uint256::from_be_bytes(results[0].data().unwrap()),
+            1000u16.into()
+        );
+        assert!(!results[1].success);
+        assert_eq!(results[1].data(), None);
+
+        assert!(decode_aggregate3(&response[..200]).is_err());
+    }
+}
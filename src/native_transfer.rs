@@ -0,0 +1,185 @@
+//! This module contains helpers for plain transfers of the chain's native token (ETH or TRX),
+//! without any calldata.
+use crate::client::{Web3, ETHEREUM_INTRINSIC_GAS};
+use crate::jsonrpc::error::Web3Error;
+use crate::signer::Signer;
+use crate::tron_utils;
+use crate::types::SendTxOption;
+use clarity::{Address, Uint256};
+use futures::future::join;
+
+/// Priority fee of a sweep unless `SendTxOption::GasPriorityFee` is passed, 1 gwei
+pub const SWEEP_PRIORITY_FEE: u64 = 1_000_000_000;
+
+impl Web3 {
+    /// Sends `amount` of the native token to `to`, on Ethereum this is a 21000 gas transfer with
+    /// no calldata unless `SendTxOption::GasLimit` is passed (needed if `to` is a contract with
+    /// a receive function), on Tron a native TRX transfer. `options` are the same as for
+    /// `send_transaction` and are ignored on Tron.
+    pub async fn send_native(
+        &self,
+        to: Address,
+        amount: Uint256,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<Uint256, Web3Error> {
+        if let Some(tron) = self.tron_client() {
+            let secret = match signer.private_key() {
+                Some(secret) => secret,
+                None => {
+                    return Err(Web3Error::BadInput(
+                        "Tron transactions require an in memory private key".to_string(),
+                    ))
+                }
+            };
+            return tron_utils::send_native(tron, to, amount, secret).await;
+        }
+
+        let mut options = options;
+        if !options
+            .iter()
+            .any(|option| matches!(option, SendTxOption::GasLimit(_)))
+        {
+            options.push(SendTxOption::GasLimit(ETHEREUM_INTRINSIC_GAS.into()));
+        }
+        let own_address = signer.address();
+        self.send_transaction_data(Some(to), Vec::new(), amount, own_address, signer, options)
+            .await
+    }
+
+    /// Sends the entire native balance of the signer to `to` minus the exact transaction fee,
+    /// leaving the account empty. Returns the transaction hash and the amount sent.
+    ///
+    /// The sweep is a legacy transaction since only a fixed gas price makes the fee known up
+    /// front, an EIP-1559 transaction pays less than its max fee whenever the base fee drops.
+    /// On EIP-1559 chains the gas price is the current base fee with one block of headroom
+    /// plus a priority fee of `SendTxOption::GasPriorityFee` or `SWEEP_PRIORITY_FEE`, on other
+    /// chains it is the node's `eth_gasPrice`. A `SendTxOption::GasPrice` or `GasMaxFee` sets it
+    /// directly. Gas limit options are honored, multipliers are not. Not supported on Tron where
+    /// the fee depends on the bandwidth left to the account.
+    pub async fn sweep_native(
+        &self,
+        to: Address,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+    ) -> Result<(Uint256, Uint256), Web3Error> {
+        if self.is_tron() {
+            return Err(Web3Error::BadInput(
+                "Sweeping is not supported on Tron".to_string(),
+            ));
+        }
+
+        let own_address = signer.address();
+        let (balance, base_fee_per_gas) = join(
+            self.eth_get_balance(own_address),
+            self.get_base_fee_per_gas(),
+        )
+        .await;
+        let (balance, base_fee_per_gas) = (balance?, base_fee_per_gas?);
+
+        let (gas_limit, gas_price) = sweep_fee(&options, base_fee_per_gas);
+        let gas_price = match gas_price {
+            Some(gas_price) => gas_price,
+            None => self.eth_gas_price().await?,
+        };
+
+        let fee = gas_price * gas_limit;
+        if balance <= fee {
+            return Err(Web3Error::InsufficientGas {
+                balance,
+                base_gas: gas_price,
+                gas_required: gas_limit,
+            });
+        }
+        let amount = balance - fee;
+
+        // replace everything that influences the fee with the exact values computed above
+        let mut options: Vec<SendTxOption> = options
+            .into_iter()
+            .filter(|option| {
+                matches!(
+                    option,
+                    SendTxOption::Nonce(_)
+                        | SendTxOption::AccessList(_)
+                        | SendTxOption::NetworkId(_)
+                        | SendTxOption::Legacy
+                        | SendTxOption::Preflight
+                )
+            })
+            .collect();
+        if !options.contains(&SendTxOption::Legacy) {
+            options.push(SendTxOption::Legacy);
+        }
+        options.push(SendTxOption::GasLimit(gas_limit));
+        options.push(SendTxOption::GasPrice(gas_price));
+
+        let tx_hash = self
+            .send_transaction_data(Some(to), Vec::new(), amount, own_address, signer, options)
+            .await?;
+        Ok((tx_hash, amount))
+    }
+}
+
+/// The gas limit and gas price of a sweep, the gas price is `None` if the chain has no base fee
+/// and none was passed, it is then the node's `eth_gasPrice`
+fn sweep_fee(
+    options: &[SendTxOption],
+    base_fee_per_gas: Option<Uint256>,
+) -> (Uint256, Option<Uint256>) {
+    let mut gas_limit: Uint256 = ETHEREUM_INTRINSIC_GAS.into();
+    let mut gas_price = None;
+    let mut priority_fee: Uint256 = SWEEP_PRIORITY_FEE.into();
+    for option in options.iter() {
+        match option {
+            SendTxOption::GasLimit(limit) => gas_limit = *limit,
+            SendTxOption::GasPrice(price) | SendTxOption::GasMaxFee(price) => {
+                gas_price = Some(*price)
+            }
+            SendTxOption::GasPriorityFee(fee) => priority_fee = *fee,
+            _ => {}
+        }
+    }
+
+    // the base fee rises by at most an eighth per block
+    let gas_price = gas_price.or_else(|| {
+        base_fee_per_gas.map(|base_fee| base_fee + base_fee / 8u8.into() + priority_fee)
+    });
+    (gas_limit, gas_price)
+}
+
+#[test]
+fn test_sweep_fee() {
+    let gwei: Uint256 = 1_000_000_000u64.into();
+    let base_fee = Some(gwei * 80u8.into());
+    let intrinsic: Uint256 = ETHEREUM_INTRINSIC_GAS.into();
+
+    assert_eq!(
+        sweep_fee(&[], base_fee),
+        (intrinsic, Some(gwei * 91u8.into()))
+    );
+    assert_eq!(
+        sweep_fee(&[SendTxOption::GasPriorityFee(gwei * 2u8.into())], base_fee),
+        (intrinsic, Some(gwei * 92u8.into()))
+    );
+    // multipliers are ignored and a legacy chain leaves the price to the node
+    let options = [
+        SendTxOption::Legacy,
+        SendTxOption::GasPriceMultiplier(2.0),
+        SendTxOption::GasMaxFeeMultiplier(2.0),
+        SendTxOption::GasPriorityFee(gwei),
+    ];
+    assert_eq!(
+        sweep_fee(&options, base_fee),
+        (intrinsic, Some(gwei * 91u8.into()))
+    );
+    assert_eq!(sweep_fee(&options, None), (intrinsic, None));
+
+    let options = [
+        SendTxOption::GasPrice(gwei * 50u8.into()),
+        SendTxOption::GasLimit(30_000u32.into()),
+    ];
+    assert_eq!(
+        sweep_fee(&options, None),
+        (30_000u32.into(), Some(gwei * 50u8.into()))
+    );
+}
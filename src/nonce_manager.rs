@@ -0,0 +1,180 @@
+//! This file contains a nonce manager which hands out sequential nonces per address so that
+//! concurrent sends from the same key do not race each other for the same nonce. It is shared
+//! between all clones of a `Web3` instance.
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use clarity::{Address, Uint256};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long local nonce state is trusted before it is checked against the node again
+const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Nonce bookkeeping for a single address
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NonceState {
+    /// the next never handed out nonce
+    next: Uint256,
+    /// nonces below `next` that were handed out but released before broadcast,
+    /// these are gaps that have to be filled before the following nonces can be mined
+    released: BTreeSet<Uint256>,
+    /// when the state was last checked against the node's pending transaction count
+    synced: Instant,
+}
+
+impl NonceState {
+    fn new(next: Uint256) -> Self {
+        NonceState {
+            next,
+            released: BTreeSet::new(),
+            synced: Instant::now(),
+        }
+    }
+
+    /// Catches up with the node's pending transaction count. Transactions sent by another
+    /// party move it past `next` and use up any released nonce below it, a lower count only
+    /// means our own transactions have not reached this node yet so it is ignored.
+    fn sync(&mut self, pending: Uint256) {
+        if pending > self.next {
+            self.next = pending;
+        }
+        self.released = self.released.split_off(&pending);
+        self.synced = Instant::now();
+    }
+
+    /// Hands out the lowest available nonce, filling gaps first
+    fn take(&mut self) -> Uint256 {
+        match self.released.pop_first() {
+            Some(nonce) => nonce,
+            None => {
+                let nonce = self.next;
+                self.next += Uint256::from(1u8);
+                nonce
+            }
+        }
+    }
+
+    /// Returns a nonce that was never broadcast
+    fn give_back(&mut self, nonce: Uint256) {
+        if nonce >= self.next {
+            return;
+        }
+        self.released.insert(nonce);
+        // shrink back down instead of keeping gaps at the end of the sequence
+        while let Some(last) = self.released.last().copied() {
+            if last + 1u8.into() != self.next {
+                break;
+            }
+            self.released.remove(&last);
+            self.next = last;
+        }
+    }
+}
+
+/// A struct for handing out nonces to concurrent senders, the first nonce for each
+/// address is taken from the node's pending transaction count
+#[derive(Debug)]
+pub struct NonceManager {
+    nonces: Mutex<HashMap<Address, NonceState>>,
+    sync_interval: Duration,
+}
+
+impl Default for NonceManager {
+    fn default() -> Self {
+        NonceManager::with_sync_interval(DEFAULT_SYNC_INTERVAL)
+    }
+}
+
+impl NonceManager {
+    pub fn new() -> Self {
+        NonceManager::default()
+    }
+
+    /// Creates a nonce manager which checks its state against the node's pending transaction
+    /// count whenever it was last checked more than `sync_interval` ago
+    pub fn with_sync_interval(sync_interval: Duration) -> Self {
+        NonceManager {
+            nonces: Mutex::new(HashMap::new()),
+            sync_interval,
+        }
+    }
+
+    /// Reserves the next nonce for an address, the caller must either broadcast a
+    /// transaction with it or hand it back with `release`
+    pub async fn reserve(&self, web30: &Web3, address: Address) -> Result<Uint256, Web3Error> {
+        // the lock is held while syncing so that concurrent callers wait for the
+        // pending count instead of each fetching the same value
+        let mut nonces = self.nonces.lock().await;
+        match nonces.get_mut(&address) {
+            Some(state) if state.synced.elapsed() < self.sync_interval => {}
+            Some(state) => {
+                let pending = web30.eth_get_pending_transaction_count(address).await?;
+                state.sync(pending);
+            }
+            None => {
+                let pending = web30.eth_get_pending_transaction_count(address).await?;
+                nonces.insert(address, NonceState::new(pending));
+            }
+        }
+        Ok(nonces.get_mut(&address).unwrap().take())
+    }
+
+    /// Releases a reserved nonce whose transaction was never broadcast, it will be
+    /// handed out again before any new nonce
+    pub async fn release(&self, address: Address, nonce: Uint256) {
+        if let Some(state) = self.nonces.lock().await.get_mut(&address) {
+            state.give_back(nonce);
+        }
+    }
+
+    /// Discards all local state for an address, the next reservation resyncs from the
+    /// node's pending transaction count. Use this when transactions were sent by
+    /// another party or a broadcast failed in a way that leaves the nonce unknown.
+    /// Nonces reserved but not yet broadcast at that point may be handed out twice.
+    pub async fn resync(&self, address: Address) {
+        self.nonces.lock().await.remove(&address);
+    }
+}
+
+#[test]
+fn test_nonce_gaps() {
+    let mut state = NonceState::new(5u8.into());
+    let nonces: Vec<Uint256> = (0..4).map(|_| state.take()).collect();
+    assert_eq!(nonces, vec![5u8.into(), 6u8.into(), 7u8.into(), 8u8.into()]);
+
+    // a gap in the middle is refilled before new nonces are handed out
+    state.give_back(6u8.into());
+    assert_eq!(state.take(), 6u8.into());
+    assert_eq!(state.take(), 9u8.into());
+
+    // releasing the tail of the sequence shrinks it instead
+    state.give_back(8u8.into());
+    state.give_back(9u8.into());
+    assert_eq!(state.next, 8u8.into());
+    assert!(state.released.is_empty());
+
+    // unknown nonces are ignored
+    state.give_back(20u8.into());
+    assert_eq!(state.take(), 8u8.into());
+}
+
+#[test]
+fn test_nonce_sync() {
+    let mut state = NonceState::new(5u8.into());
+    for _ in 0..4 {
+        state.take();
+    }
+    state.give_back(6u8.into());
+
+    // a node that has not seen our latest transactions yet does not rewind the sequence
+    state.sync(7u8.into());
+    assert_eq!(state.take(), 9u8.into());
+
+    // another party used nonces up to 11, including the released gap
+    state.give_back(9u8.into());
+    state.give_back(7u8.into());
+    state.sync(12u8.into());
+    assert!(state.released.is_empty());
+    assert_eq!(state.take(), 12u8.into());
+}
@@ -0,0 +1,466 @@
+//! This module extracts and decodes the data returned by reverted calls, so a failed
+//! simulation or call can be reported with the reason the contract gave.
+//!
+//! Solidity reverts with one of three payloads, `Error(string)` for `require` and `revert`
+//! with a message, `Panic(uint256)` for failed asserts and checked arithmetic, or a custom
+//! error declared by the contract. Custom errors can only be recognized if their signature
+//! has been registered in an `ErrorRegistry`.
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use clarity::Uint256;
+use num_traits::ToPrimitive;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Selector of `Error(string)`, the payload of `require(cond, "reason")` and `revert("reason")`
+pub const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, the payload of failed asserts and compiler inserted checks
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The panic codes defined by Solidity 0.8
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PanicCode {
+    /// 0x00, generic compiler inserted panic
+    Generic,
+    /// 0x01, `assert` with a false condition
+    AssertionFailed,
+    /// 0x11, arithmetic overflow or underflow outside of an `unchecked` block
+    ArithmeticOverflow,
+    /// 0x12, division or modulo by zero
+    DivisionByZero,
+    /// 0x21, conversion of an out of range value to an enum
+    InvalidEnumValue,
+    /// 0x22, access to an incorrectly encoded storage byte array
+    InvalidStorageByteArray,
+    /// 0x31, `pop()` on an empty array
+    EmptyArrayPop,
+    /// 0x32, array or slice index out of bounds
+    IndexOutOfBounds,
+    /// 0x41, too much memory allocated or an array that is too large
+    OutOfMemory,
+    /// 0x51, call to a zero initialized internal function pointer
+    InvalidFunctionPointer,
+    /// A code not defined by Solidity
+    Unknown(Uint256),
+}
+
+impl PanicCode {
+    pub fn from_code(code: Uint256) -> PanicCode {
+        match code.to_u8() {
+            Some(0x00) => PanicCode::Generic,
+            Some(0x01) => PanicCode::AssertionFailed,
+            Some(0x11) => PanicCode::ArithmeticOverflow,
+            Some(0x12) => PanicCode::DivisionByZero,
+            Some(0x21) => PanicCode::InvalidEnumValue,
+            Some(0x22) => PanicCode::InvalidStorageByteArray,
+            Some(0x31) => PanicCode::EmptyArrayPop,
+            Some(0x32) => PanicCode::IndexOutOfBounds,
+            Some(0x41) => PanicCode::OutOfMemory,
+            Some(0x51) => PanicCode::InvalidFunctionPointer,
+            _ => PanicCode::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> Uint256 {
+        let code: u8 = match self {
+            PanicCode::Generic => 0x00,
+            PanicCode::AssertionFailed => 0x01,
+            PanicCode::ArithmeticOverflow => 0x11,
+            PanicCode::DivisionByZero => 0x12,
+            PanicCode::InvalidEnumValue => 0x21,
+            PanicCode::InvalidStorageByteArray => 0x22,
+            PanicCode::EmptyArrayPop => 0x31,
+            PanicCode::IndexOutOfBounds => 0x32,
+            PanicCode::OutOfMemory => 0x41,
+            PanicCode::InvalidFunctionPointer => 0x51,
+            PanicCode::Unknown(code) => return *code,
+        };
+        code.into()
+    }
+}
+
+impl Display for PanicCode {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let description = match self {
+            PanicCode::Generic => "generic panic",
+            PanicCode::AssertionFailed => "assertion failed",
+            PanicCode::ArithmeticOverflow => "arithmetic overflow or underflow",
+            PanicCode::DivisionByZero => "division or modulo by zero",
+            PanicCode::InvalidEnumValue => "invalid enum value",
+            PanicCode::InvalidStorageByteArray => "invalid storage byte array",
+            PanicCode::EmptyArrayPop => "pop on empty array",
+            PanicCode::IndexOutOfBounds => "index out of bounds",
+            PanicCode::OutOfMemory => "out of memory",
+            PanicCode::InvalidFunctionPointer => "invalid internal function pointer",
+            PanicCode::Unknown(_) => "unknown panic",
+        };
+        write!(f, "panic {:#04x}: {description}", self.code())
+    }
+}
+
+/// The decoded reason for a revert
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertReason {
+    /// `Error(string)`, a `require` or `revert` with a message
+    Error(String),
+    /// `Panic(uint256)`
+    Panic(PanicCode),
+    /// A custom error registered in the `ErrorRegistry`, `args` are the ABI encoded
+    /// parameters following the selector
+    Custom { signature: String, args: Vec<u8> },
+    /// The revert carried no data, `require(cond)` or `revert()` without a message
+    Empty,
+    /// Data that matches no known or registered error
+    Unknown(Vec<u8>),
+}
+
+impl RevertReason {
+    /// Decodes a revert payload, custom errors are looked up in `registry`
+    pub fn decode(data: &[u8], registry: &ErrorRegistry) -> RevertReason {
+        if data.is_empty() {
+            return RevertReason::Empty;
+        }
+        if let Some(reason) = decode_revert_reason(data) {
+            return RevertReason::Error(reason);
+        }
+        if let Some(code) = decode_panic_code(data) {
+            return RevertReason::Panic(PanicCode::from_code(code));
+        }
+        if let Some(signature) = data.get(..4).and_then(|selector| registry.get(selector)) {
+            return RevertReason::Custom {
+                signature: signature.to_string(),
+                args: data[4..].to_vec(),
+            };
+        }
+        RevertReason::Unknown(data.to_vec())
+    }
+
+    /// Reads the reason from the message of nodes that don't return the revert data,
+    /// geth style `execution reverted: <reason>` and hardhat style
+    /// `reverted with reason string '<reason>'` or `reverted with panic code 0x11`
+    fn from_message(message: &str) -> Option<RevertReason> {
+        lazy_static! {
+            static ref HARDHAT_REASON: Regex =
+                Regex::new(r"reverted with reason string '(.*)'").unwrap();
+            static ref HARDHAT_PANIC: Regex =
+                Regex::new(r"reverted with panic code 0x([0-9a-fA-F]+)").unwrap();
+        }
+        if let Some(captures) = HARDHAT_REASON.captures(message) {
+            return Some(RevertReason::Error(captures[1].to_string()));
+        }
+        if let Some(captures) = HARDHAT_PANIC.captures(message) {
+            let code = u64::from_str_radix(&captures[1], 16).ok()?;
+            return Some(RevertReason::Panic(PanicCode::from_code(code.into())));
+        }
+        message
+            .split_once("execution reverted: ")
+            .map(|(_, reason)| RevertReason::Error(reason.to_string()))
+    }
+}
+
+impl Display for RevertReason {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RevertReason::Error(reason) => write!(f, "{reason}"),
+            RevertReason::Panic(code) => write!(f, "{code}"),
+            RevertReason::Custom { signature, args } => {
+                write!(f, "{signature} with arguments 0x{}", bytes_to_hex_str(args))
+            }
+            RevertReason::Empty => write!(f, "no reason given"),
+            RevertReason::Unknown(data) => {
+                write!(f, "unrecognized data 0x{}", bytes_to_hex_str(data))
+            }
+        }
+    }
+}
+
+/// A set of custom error signatures, such as `InsufficientBalance(uint256,uint256)`, used to
+/// recognize custom errors in revert data
+#[derive(Debug, Clone, Default)]
+pub struct ErrorRegistry {
+    errors: HashMap<[u8; 4], String>,
+}
+
+impl ErrorRegistry {
+    pub fn new() -> Self {
+        ErrorRegistry::default()
+    }
+
+    /// Registers a custom error by its canonical signature, returns the error selector
+    pub fn register(&mut self, signature: &str) -> Result<[u8; 4], Web3Error> {
+        lazy_static! {
+            static ref SIGNATURE: Regex =
+                Regex::new(r"^[A-Za-z_$][A-Za-z0-9_$]*\([A-Za-z0-9\[\](),]*\)$").unwrap();
+        }
+        let signature: String = signature.split_whitespace().collect();
+        if !SIGNATURE.is_match(&signature) {
+            return Err(Web3Error::BadInput(format!(
+                "Invalid error signature {signature}"
+            )));
+        }
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&keccak256(signature.as_bytes())[..4]);
+        self.errors.insert(selector, signature);
+        Ok(selector)
+    }
+
+    /// Returns the signature registered for `selector`
+    pub fn get(&self, selector: &[u8]) -> Option<&str> {
+        let selector: [u8; 4] = selector.try_into().ok()?;
+        self.errors.get(&selector).map(|s| s.as_str())
+    }
+}
+
+/// Decodes the reason string of an `Error(string)` revert payload
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    let payload = data.strip_prefix(&ERROR_STRING_SELECTOR[..])?;
+    let offset = read_usize(payload, 0)?;
+    let length = read_usize(payload, offset)?;
+    let start = offset.checked_add(32)?;
+    let bytes = payload.get(start..start.checked_add(length)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Decodes the code of a `Panic(uint256)` revert payload
+pub fn decode_panic_code(data: &[u8]) -> Option<Uint256> {
+    let payload = data.strip_prefix(&PANIC_SELECTOR[..])?;
+    if payload.len() != 32 {
+        return None;
+    }
+    Some(Uint256::from_be_bytes(payload))
+}
+
+/// Reads a 32 byte big endian word at `offset` as a usize
+fn read_usize(payload: &[u8], offset: usize) -> Option<usize> {
+    let word = payload.get(offset..offset.checked_add(32)?)?;
+    if word[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(word[24..].try_into().unwrap()) as usize)
+}
+
+/// Finds the revert payload in the `data` of a JSON-RPC error. geth, erigon and the Tron
+/// JSON-RPC endpoint return it as a hex string, hardhat nests it in an object under `data`,
+/// ganache keys it by transaction hash under `return` and the Tron HTTP API returns it
+/// without a prefix in the `constant_result` array.
+pub fn find_revert_data(data: &Value) -> Option<Vec<u8>> {
+    match data {
+        Value::String(s) => parse_hex(s),
+        Value::Array(values) => values.iter().find_map(find_revert_data),
+        Value::Object(map) => ["data", "return", "constant_result"]
+            .iter()
+            .find_map(|key| map.get(*key).and_then(find_revert_data))
+            .or_else(|| {
+                map.values()
+                    .filter(|value| value.is_object())
+                    .find_map(find_revert_data)
+            }),
+        _ => None,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    hex_str_to_bytes(&format!("0x{digits}")).ok()
+}
+
+/// Returns true if a node error reports a reverted execution
+fn is_revert(code: i64, message: &str) -> bool {
+    // geth and erigon use code 3 for reverts that carry data, Tron reports
+    // "REVERT opcode executed"
+    code == 3 || message.to_lowercase().contains("revert")
+}
+
+impl Web3Error {
+    /// Returns the raw revert payload if this error is a reverted call or transaction
+    pub fn revert_data(&self) -> Option<Vec<u8>> {
+        match self {
+            Web3Error::TransactionReverted { data, .. } => Some(data.clone()),
+            Web3Error::JsonRpcError {
+                code,
+                message,
+                data,
+            } if is_revert(*code, message) => {
+                Some(data.as_ref().and_then(find_revert_data).unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
+    /// Decodes the revert reason if this error is a reverted call or transaction, custom
+    /// errors are looked up in `registry`
+    pub fn revert_reason(&self, registry: &ErrorRegistry) -> Option<RevertReason> {
+        let data = self.revert_data()?;
+        if data.is_empty() {
+            let message_reason = match self {
+                Web3Error::TransactionReverted { reason, .. } => Some(reason.clone()),
+                Web3Error::JsonRpcError { message, .. } => RevertReason::from_message(message),
+                _ => None,
+            };
+            return Some(message_reason.unwrap_or(RevertReason::Empty));
+        }
+        Some(RevertReason::decode(&data, registry))
+    }
+}
+
+/// Converts a node error caused by a reverted execution into `Web3Error::TransactionReverted`,
+/// any other error is returned unchanged
+pub(crate) fn revert_error(error: Web3Error, registry: &ErrorRegistry) -> Web3Error {
+    match (error.revert_reason(registry), error.revert_data()) {
+        (Some(reason), Some(data)) => Web3Error::TransactionReverted { reason, data },
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The `Error(string)` payload of `revert("Not enough Ether provided.")`
+    const REVERT_DATA: &str = "0x08c379a0\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        000000000000000000000000000000000000000000000000000000000000001a\
+        4e6f7420656e6f7567682045746865722070726f76696465642e000000000000";
+    /// The `Panic(uint256)` payload of an arithmetic overflow
+    const PANIC_DATA: &str = "0x4e487b71\
+        0000000000000000000000000000000000000000000000000000000000000011";
+    /// `InsufficientBalance(1, 2)`
+    const CUSTOM_DATA: &str = "0xcf479181\
+        0000000000000000000000000000000000000000000000000000000000000001\
+        0000000000000000000000000000000000000000000000000000000000000002";
+
+    fn rpc_error(code: i64, message: &str, data: Option<Value>) -> Web3Error {
+        Web3Error::JsonRpcError {
+            code,
+            message: message.to_string(),
+            data,
+        }
+    }
+
+    fn expected_reason() -> RevertReason {
+        RevertReason::Error("Not enough Ether provided.".to_string())
+    }
+
+    #[test]
+    fn test_decode_revert_data() {
+        let mut registry = ErrorRegistry::new();
+        let selector = registry
+            .register("InsufficientBalance(uint256, uint256)")
+            .unwrap();
+        assert_eq!(selector, [0xcf, 0x47, 0x91, 0x81]);
+        assert!(registry.register("not a signature").is_err());
+
+        let data = hex_str_to_bytes(REVERT_DATA).unwrap();
+        assert_eq!(RevertReason::decode(&data, &registry), expected_reason());
+        assert_eq!(decode_revert_reason(&data[..40]), None);
+
+        let data = hex_str_to_bytes(PANIC_DATA).unwrap();
+        assert_eq!(
+            RevertReason::decode(&data, &registry),
+            RevertReason::Panic(PanicCode::ArithmeticOverflow)
+        );
+        assert_eq!(PanicCode::ArithmeticOverflow.code(), 0x11u8.into());
+        assert_eq!(
+            PanicCode::from_code(0x99u8.into()),
+            PanicCode::Unknown(0x99u8.into())
+        );
+
+        let data = hex_str_to_bytes(CUSTOM_DATA).unwrap();
+        assert_eq!(
+            RevertReason::decode(&data, &registry),
+            RevertReason::Custom {
+                signature: "InsufficientBalance(uint256,uint256)".to_string(),
+                args: data[4..].to_vec(),
+            }
+        );
+        assert_eq!(
+            RevertReason::decode(&data, &ErrorRegistry::new()),
+            RevertReason::Unknown(data)
+        );
+        assert_eq!(RevertReason::decode(&[], &registry), RevertReason::Empty);
+    }
+
+    #[test]
+    fn test_node_revert_errors() {
+        let registry = ErrorRegistry::new();
+        let payload = hex_str_to_bytes(REVERT_DATA).unwrap();
+
+        // geth and erigon, data is the payload
+        let geth = rpc_error(
+            3,
+            "execution reverted: Not enough Ether provided.",
+            Some(json!(REVERT_DATA)),
+        );
+        assert_eq!(geth.revert_data(), Some(payload.clone()));
+        assert_eq!(geth.revert_reason(&registry), Some(expected_reason()));
+
+        // hardhat, payload nested in an object
+        let hardhat = rpc_error(
+            -32603,
+            "Error: VM Exception while processing transaction: reverted with reason string 'Not enough Ether provided.'",
+            Some(json!({"message": "revert", "data": REVERT_DATA})),
+        );
+        assert_eq!(hardhat.revert_reason(&registry), Some(expected_reason()));
+        let hardhat_panic = rpc_error(
+            -32603,
+            "Error: VM Exception while processing transaction: reverted with panic code 0x12 (Division or modulo division by zero)",
+            None,
+        );
+        assert_eq!(
+            hardhat_panic.revert_reason(&registry),
+            Some(RevertReason::Panic(PanicCode::DivisionByZero))
+        );
+
+        // ganache, keyed by transaction hash
+        let ganache = rpc_error(
+            -32000,
+            "VM Exception while processing transaction: revert",
+            Some(json!({
+                "0x9b4e2d1f0ff8f2fdd06bc8a2f71b4e8d7bb8c8f1e7c0b1f3b1e0b2c36d9e3a11": {
+                    "error": "revert",
+                    "program_counter": 130,
+                    "return": PANIC_DATA,
+                },
+                "stack": "RuntimeError: VM Exception",
+            })),
+        );
+        assert_eq!(
+            ganache.revert_reason(&registry),
+            Some(RevertReason::Panic(PanicCode::ArithmeticOverflow))
+        );
+
+        // Tron JSON-RPC and HTTP API
+        let tron = rpc_error(-32000, "REVERT opcode executed", Some(json!(REVERT_DATA)));
+        assert_eq!(tron.revert_reason(&registry), Some(expected_reason()));
+        assert_eq!(
+            find_revert_data(&json!({
+                "result": {"code": "CONTRACT_EXE_ERROR", "message": "524556455254"},
+                "constant_result": [REVERT_DATA.trim_start_matches("0x")],
+            })),
+            Some(payload)
+        );
+
+        // no payload, require without a message
+        let empty = rpc_error(-32000, "execution reverted", None);
+        assert_eq!(empty.revert_reason(&registry), Some(RevertReason::Empty));
+
+        // anything else is not a revert
+        let other = rpc_error(-32000, "nonce too low", None);
+        assert_eq!(other.revert_data(), None);
+        assert!(matches!(
+            revert_error(other, &registry),
+            Web3Error::JsonRpcError { .. }
+        ));
+        match revert_error(geth, &registry) {
+            Web3Error::TransactionReverted { reason, .. } => assert_eq!(reason, expected_reason()),
+            e => panic!("Unexpected error {e}"),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! A minimal RLP encoder, just enough to reproduce the consensus encodings of
+//! block headers and transactions returned by the JSONRPC API.
+//!
+//! See more https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp/
+use clarity::{Address, Uint256};
+
+/// Encodes the length prefix for a string (offset 0x80) or list (offset 0xc0)
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let first = len_bytes.iter().position(|b| *b != 0).unwrap_or(7);
+        let mut out = vec![offset + 55 + (len_bytes.len() - first) as u8];
+        out.extend_from_slice(&len_bytes[first..]);
+        out
+    }
+}
+
+/// Encodes a byte string
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = encode_length(bytes.len(), 0x80);
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Encodes a list from items that have already been RLP encoded
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut out = encode_length(payload.len(), 0xc0);
+    out.extend(payload);
+    out
+}
+
+/// Encodes an integer as a big endian byte string without leading zeroes,
+/// zero is encoded as the empty string
+pub fn encode_uint(value: Uint256) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    encode_bytes(&bytes[first..])
+}
+
+/// Encodes a 32 byte hash, unlike integers hashes keep their leading zeroes
+pub fn encode_hash(value: Uint256) -> Vec<u8> {
+    encode_bytes(&value.to_be_bytes())
+}
+
+pub fn encode_address(address: &Address) -> Vec<u8> {
+    encode_bytes(address.as_bytes())
+}
+
+#[test]
+fn test_rlp_encoding() {
+    assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+    assert_eq!(encode_bytes(&[]), vec![0x80]);
+    assert_eq!(encode_bytes(&[0x0f]), vec![0x0f]);
+    assert_eq!(encode_uint(0u8.into()), vec![0x80]);
+    assert_eq!(encode_uint(1024u16.into()), vec![0x82, 0x04, 0x00]);
+    assert_eq!(encode_list(&[]), vec![0xc0]);
+    assert_eq!(
+        encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]),
+        vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+    );
+    let long = [b'a'; 56];
+    let encoded = encode_bytes(&long);
+    assert_eq!(&encoded[..2], &[0xb8, 56]);
+    assert_eq!(encoded.len(), 58);
+}
@@ -0,0 +1,691 @@
+//! This module contains the `Signer` trait accepted by every sending helper in this crate. Keys
+//! can live in memory (`clarity::PrivateKey`), or in a separate process or machine reached over
+//! JSON-RPC (`RemoteSigner`) so they never have to be loaded into the relayer itself.
+use crate::eip712::TypedData;
+use crate::jsonrpc::client::HttpClient;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{clarity_destination, Data, SignedTransaction, UnpaddedHex};
+use async_trait::async_trait;
+use clarity::constants::zero_address;
+use clarity::utils::bytes_to_hex_str;
+use clarity::{Address, PrivateKey, Signature, Transaction, Uint256};
+use num_traits::ToPrimitive;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Something that can sign on behalf of an Ethereum address
+#[async_trait(?Send)]
+pub trait Signer {
+    /// The address signatures are produced for
+    fn address(&self) -> Address;
+
+    /// Signs a transaction and returns its network encoding, ready for
+    /// `eth_sendRawTransaction`. `network_id` is the EIP-155 chain id legacy
+    /// transactions are signed with, typed transactions carry their own chain id
+    async fn sign_transaction(
+        &self,
+        transaction: Transaction,
+        network_id: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error>;
+
+    /// Signs a message with the EIP-191 "\x19Ethereum Signed Message:\n" prefix, as `eth_sign` does
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, Web3Error>;
+
+    /// Signs EIP-712 typed structured data, as `eth_signTypedData_v4` does
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature, Web3Error>;
+
+    /// The raw key for signers that hold one in memory, Tron transactions are signed
+    /// by heliosphere and can only be sent with such a signer
+    fn private_key(&self) -> Option<PrivateKey> {
+        None
+    }
+}
+
+#[async_trait(?Send)]
+impl Signer for PrivateKey {
+    fn address(&self) -> Address {
+        self.to_address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: Transaction,
+        network_id: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error> {
+        Ok(transaction.sign(self, network_id).to_bytes())
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, Web3Error> {
+        Ok(self.sign_ethereum_msg(message))
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature, Web3Error> {
+        Ok(self.sign_hash(&typed_data.signing_hash()?))
+    }
+
+    fn private_key(&self) -> Option<PrivateKey> {
+        Some(*self)
+    }
+}
+
+// allows helpers that take a signer by value to be called with a reference, so
+// non Copy signers can be reused across calls
+#[async_trait(?Send)]
+impl<S: Signer + ?Sized> Signer for &S {
+    fn address(&self) -> Address {
+        (**self).address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: Transaction,
+        network_id: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error> {
+        (**self).sign_transaction(transaction, network_id).await
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, Web3Error> {
+        (**self).sign_message(message).await
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature, Web3Error> {
+        (**self).sign_typed_data(typed_data).await
+    }
+
+    fn private_key(&self) -> Option<PrivateKey> {
+        (**self).private_key()
+    }
+}
+
+/// Carries JSON-RPC requests from a `RemoteSigner` to whatever holds the key
+#[async_trait(?Send)]
+pub trait SignerTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Web3Error>;
+}
+
+/// Sends requests to a JSON-RPC signing service over HTTP
+pub struct HttpSignerTransport {
+    client: HttpClient,
+    timeout: Duration,
+    headers: HashMap<String, String>,
+}
+
+impl HttpSignerTransport {
+    pub fn new(url: &str, timeout: Duration) -> Self {
+        HttpSignerTransport {
+            client: HttpClient::new(url),
+            timeout,
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Sets a header sent with every request, such as an authorization token
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        self.headers.insert(key.to_string(), value.to_string());
+    }
+}
+
+#[async_trait(?Send)]
+impl SignerTransport for HttpSignerTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Web3Error> {
+        self.client
+            .request_method(method, params, self.timeout, &self.headers)
+            .await
+    }
+}
+
+/// Answers signing requests in process with a local key, this stands in for a remote
+/// signing service in tests and development setups
+pub struct LocalSignerTransport {
+    key: PrivateKey,
+}
+
+impl LocalSignerTransport {
+    pub fn new(key: PrivateKey) -> Self {
+        LocalSignerTransport { key }
+    }
+
+    fn check_address(&self, address: &Value) -> Result<(), Web3Error> {
+        let address: Address = serde_json::from_value(address.clone())
+            .map_err(|e| Web3Error::BadInput(format!("Invalid signer address {e}")))?;
+        if address != self.key.to_address() {
+            return Err(Web3Error::BadInput(format!("Unknown account {address}")));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl SignerTransport for LocalSignerTransport {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Web3Error> {
+        let signature = match method {
+            "eth_signTransaction" => {
+                let request: RemoteTransaction = serde_json::from_value(params[0].clone())
+                    .map_err(|e| Web3Error::BadInput(format!("Invalid transaction {e}")))?;
+                self.check_address(&json!(request.from))?;
+                let (transaction, network_id) = request.into_transaction()?;
+                let raw = self.key.sign_transaction(transaction, network_id).await?;
+                return Ok(json!(Data(raw)));
+            }
+            "eth_sign" => {
+                self.check_address(&params[0])?;
+                let message: Data = serde_json::from_value(params[1].clone())
+                    .map_err(|e| Web3Error::BadInput(format!("Invalid message {e}")))?;
+                self.key.sign_message(&message).await?
+            }
+            "eth_signTypedData_v4" => {
+                self.check_address(&params[0])?;
+                // some clients send the typed data as a JSON string
+                let typed_data = match &params[1] {
+                    Value::String(s) => serde_json::from_str(s),
+                    other => serde_json::from_value(other.clone()),
+                }
+                .map_err(|e| Web3Error::BadInput(format!("Invalid typed data {e}")))?;
+                self.key.sign_typed_data(&typed_data).await?
+            }
+            _ => {
+                return Err(Web3Error::BadInput(format!(
+                    "Unsupported signer method {method}"
+                )))
+            }
+        };
+        Ok(json!(Data(signature.to_bytes().to_vec())))
+    }
+}
+
+/// A signer holding its key in another process, spoken to with the `eth_signTransaction`,
+/// `eth_sign` and `eth_signTypedData_v4` JSON-RPC methods supported by signing services
+/// such as Web3Signer
+#[derive(Clone)]
+pub struct RemoteSigner {
+    address: Address,
+    transport: Rc<dyn SignerTransport>,
+}
+
+impl RemoteSigner {
+    /// Creates a signer for `address` using the signing service at `url`
+    pub fn new(url: &str, address: Address, timeout: Duration) -> Self {
+        RemoteSigner::with_transport(address, HttpSignerTransport::new(url, timeout))
+    }
+
+    pub fn with_transport(address: Address, transport: impl SignerTransport + 'static) -> Self {
+        RemoteSigner {
+            address,
+            transport: Rc::new(transport),
+        }
+    }
+
+    async fn request_signature(&self, method: &str, params: Value) -> Result<Signature, Web3Error> {
+        let signature = parse_data(self.transport.request(method, params).await?)?;
+        Signature::from_bytes(&signature).map_err(|e| Web3Error::BadResponse(e.to_string()))
+    }
+}
+
+#[async_trait(?Send)]
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: Transaction,
+        network_id: Option<u64>,
+    ) -> Result<Vec<u8>, Web3Error> {
+        let request = RemoteTransaction::new(&transaction, self.address, network_id);
+        let response = self
+            .transport
+            .request("eth_signTransaction", json!([request]))
+            .await?;
+        // geth style signers return the raw transaction along with its decoded form
+        match response.get("raw") {
+            Some(raw) => parse_data(raw.clone()),
+            None => parse_data(response),
+        }
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, Web3Error> {
+        self.request_signature(
+            "eth_sign",
+            json!([self.address, format!("0x{}", bytes_to_hex_str(message))]),
+        )
+        .await
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature, Web3Error> {
+        self.request_signature("eth_signTypedData_v4", json!([self.address, typed_data]))
+            .await
+    }
+}
+
+fn parse_data(value: Value) -> Result<Vec<u8>, Web3Error> {
+    match serde_json::from_value::<Data>(value) {
+        Ok(data) => Ok(data.0),
+        Err(e) => Err(Web3Error::BadResponse(format!(
+            "Signer returned invalid data {e}"
+        ))),
+    }
+}
+
+/// An access list entry in the format used by the JSON-RPC API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct RemoteAccessListEntry {
+    address: Address,
+    #[serde(rename = "storageKeys")]
+    storage_keys: Vec<Data>,
+}
+
+/// An unsigned transaction in the format `eth_signTransaction` accepts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct RemoteTransaction {
+    #[serde(rename = "type")]
+    tx_type: UnpaddedHex,
+    from: Address,
+    /// None for contract creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to: Option<Address>,
+    gas: UnpaddedHex,
+    value: UnpaddedHex,
+    data: Data,
+    nonce: UnpaddedHex,
+    #[serde(rename = "chainId", default, skip_serializing_if = "Option::is_none")]
+    chain_id: Option<UnpaddedHex>,
+    #[serde(rename = "gasPrice", default, skip_serializing_if = "Option::is_none")]
+    gas_price: Option<UnpaddedHex>,
+    #[serde(
+        rename = "maxFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    max_fee_per_gas: Option<UnpaddedHex>,
+    #[serde(
+        rename = "maxPriorityFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    max_priority_fee_per_gas: Option<UnpaddedHex>,
+    #[serde(rename = "accessList", default, skip_serializing_if = "Vec::is_empty")]
+    access_list: Vec<RemoteAccessListEntry>,
+}
+
+impl RemoteTransaction {
+    fn new(transaction: &Transaction, from: Address, network_id: Option<u64>) -> Self {
+        let encode_access_list = |list: &Vec<(Address, Vec<Uint256>)>| {
+            list.iter()
+                .map(|(address, keys)| RemoteAccessListEntry {
+                    address: *address,
+                    storage_keys: keys
+                        .iter()
+                        .map(|k| Data(k.to_be_bytes().to_vec()))
+                        .collect(),
+                })
+                .collect()
+        };
+        match transaction {
+            Transaction::Legacy {
+                nonce,
+                gas_price,
+                gas_limit,
+                to,
+                value,
+                data,
+                ..
+            } => RemoteTransaction {
+                tx_type: 0u64.into(),
+                from,
+                to: request_destination(*to),
+                gas: (*gas_limit).into(),
+                value: (*value).into(),
+                data: data.clone().into(),
+                nonce: (*nonce).into(),
+                chain_id: network_id.map(|id| id.into()),
+                gas_price: Some((*gas_price).into()),
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                access_list: Vec::new(),
+            },
+            Transaction::Eip2930 {
+                access_list,
+                chain_id,
+                nonce,
+                gas_price,
+                gas_limit,
+                to,
+                value,
+                data,
+                ..
+            } => RemoteTransaction {
+                tx_type: 1u64.into(),
+                from,
+                to: request_destination(*to),
+                gas: (*gas_limit).into(),
+                value: (*value).into(),
+                data: data.clone().into(),
+                nonce: (*nonce).into(),
+                chain_id: Some((*chain_id).into()),
+                gas_price: Some((*gas_price).into()),
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+                access_list: encode_access_list(access_list),
+            },
+            Transaction::Eip1559 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas_limit,
+                to,
+                value,
+                data,
+                access_list,
+                ..
+            } => RemoteTransaction {
+                tx_type: 2u64.into(),
+                from,
+                to: request_destination(*to),
+                gas: (*gas_limit).into(),
+                value: (*value).into(),
+                data: data.clone().into(),
+                nonce: (*nonce).into(),
+                chain_id: Some((*chain_id).into()),
+                gas_price: None,
+                max_fee_per_gas: Some((*max_fee_per_gas).into()),
+                max_priority_fee_per_gas: Some((*max_priority_fee_per_gas).into()),
+                access_list: encode_access_list(access_list),
+            },
+        }
+    }
+
+    /// Converts back into an unsigned transaction and the EIP-155 network id
+    fn into_transaction(self) -> Result<(Transaction, Option<u64>), Web3Error> {
+        let missing = |field: &str| Web3Error::BadInput(format!("Transaction is missing {field}"));
+        let chain_id = self.chain_id.map(|id| id.0);
+        let access_list = self
+            .access_list
+            .into_iter()
+            .map(|entry| {
+                let keys = entry
+                    .storage_keys
+                    .iter()
+                    .map(|k| Uint256::from_be_bytes(k))
+                    .collect();
+                (entry.address, keys)
+            })
+            .collect();
+
+        match self.tx_type.0.to_u8() {
+            Some(0) => Ok((
+                Transaction::Legacy {
+                    nonce: self.nonce.0,
+                    gas_price: self.gas_price.ok_or_else(|| missing("gasPrice"))?.0,
+                    gas_limit: self.gas.0,
+                    to: clarity_destination(self.to)?,
+                    value: self.value.0,
+                    data: self.data.0,
+                    signature: None,
+                },
+                chain_id.and_then(|id| id.to_u64()),
+            )),
+            Some(1) => Ok((
+                Transaction::Eip2930 {
+                    access_list,
+                    chain_id: chain_id.ok_or_else(|| missing("chainId"))?,
+                    signature: None,
+                    nonce: self.nonce.0,
+                    gas_price: self.gas_price.ok_or_else(|| missing("gasPrice"))?.0,
+                    gas_limit: self.gas.0,
+                    to: clarity_destination(self.to)?,
+                    value: self.value.0,
+                    data: self.data.0,
+                },
+                None,
+            )),
+            Some(2) => Ok((
+                Transaction::Eip1559 {
+                    chain_id: chain_id.ok_or_else(|| missing("chainId"))?,
+                    nonce: self.nonce.0,
+                    max_priority_fee_per_gas: self
+                        .max_priority_fee_per_gas
+                        .ok_or_else(|| missing("maxPriorityFeePerGas"))?
+                        .0,
+                    max_fee_per_gas: self
+                        .max_fee_per_gas
+                        .ok_or_else(|| missing("maxFeePerGas"))?
+                        .0,
+                    gas_limit: self.gas.0,
+                    to: clarity_destination(self.to)?,
+                    value: self.value.0,
+                    data: self.data.0,
+                    signature: None,
+                    access_list,
+                },
+                None,
+            )),
+            _ => Err(Web3Error::BadInput(format!(
+                "Unsupported transaction type {:#x}",
+                self.tx_type.0
+            ))),
+        }
+    }
+}
+
+/// The `to` of a signing request for a clarity transaction, clarity signs the zero address as
+/// an empty destination so such a transaction creates a contract
+fn request_destination(to: Address) -> Option<Address> {
+    if to == zero_address() {
+        None
+    } else {
+        Some(to)
+    }
+}
+
+/// The fields a signer must not change, nonce, destination, value, data and chain id. For
+/// legacy transactions the chain id is the EIP-155 network id of the signature
+fn signed_fields(
+    transaction: &Transaction,
+    network_id: Option<u64>,
+) -> (Uint256, Address, Uint256, &[u8], Option<Uint256>) {
+    match transaction {
+        Transaction::Legacy {
+            nonce,
+            to,
+            value,
+            data,
+            ..
+        } => (*nonce, *to, *value, data, network_id.map(|id| id.into())),
+        Transaction::Eip2930 {
+            chain_id,
+            nonce,
+            to,
+            value,
+            data,
+            ..
+        }
+        | Transaction::Eip1559 {
+            chain_id,
+            nonce,
+            to,
+            value,
+            data,
+            ..
+        } => (*nonce, *to, *value, data, Some(*chain_id)),
+    }
+}
+
+/// Decodes the raw transaction returned by `signer` for `expected` and checks that it is a
+/// valid transaction sent by the signer's address with the nonce, destination, value, data and
+/// chain id that were asked for. A remote signer that is buggy or compromised could otherwise
+/// get anything broadcast in our name.
+pub(crate) fn verify_signed_transaction(
+    raw: Vec<u8>,
+    expected: &Transaction,
+    network_id: Option<u64>,
+    signer: Address,
+) -> Result<SignedTransaction, Web3Error> {
+    let signed = SignedTransaction::from_raw(raw)?;
+    if !signed.transaction.is_valid() {
+        return Err(Web3Error::BadResponse(
+            "Signer returned an invalid transaction".to_string(),
+        ));
+    }
+    let sender = signed.transaction.sender()?;
+    if sender != signer {
+        return Err(Web3Error::BadResponse(format!(
+            "Signer returned a transaction from {sender}, expected {signer}"
+        )));
+    }
+    let signed_network_id = match &signed.transaction {
+        Transaction::Legacy { .. } => signed
+            .transaction
+            .get_signature()
+            .and_then(|signature| signature.legacy_network_id())
+            .and_then(|id| id.to_u64()),
+        _ => None,
+    };
+    if signed_fields(&signed.transaction, signed_network_id) != signed_fields(expected, network_id)
+    {
+        return Err(Web3Error::BadResponse(
+            "Signer returned a transaction which differs from the one requested".to_string(),
+        ));
+    }
+    Ok(signed)
+}
+
+#[test]
+fn test_remote_signer() {
+    use actix::System;
+
+    let key: PrivateKey = "0xfe1bfd3aa9a6b18ce38cbe07e1a8e9e84e6a0cb98e6e2b56d70b4ea5d3b9b7c4"
+        .parse()
+        .unwrap();
+    let remote = RemoteSigner::with_transport(key.to_address(), LocalSignerTransport::new(key));
+
+    let transactions = vec![
+        (
+            Transaction::Legacy {
+                nonce: 1u8.into(),
+                gas_price: 2u8.into(),
+                gas_limit: 21000u16.into(),
+                to: key.to_address(),
+                value: 3u8.into(),
+                data: vec![1, 2, 3],
+                signature: None,
+            },
+            Some(1),
+        ),
+        (
+            Transaction::Eip1559 {
+                chain_id: 1u8.into(),
+                nonce: 1u8.into(),
+                max_priority_fee_per_gas: 1u8.into(),
+                max_fee_per_gas: 2u8.into(),
+                gas_limit: 21000u16.into(),
+                to: zero_address(),
+                value: 3u8.into(),
+                data: Vec::new(),
+                signature: None,
+                access_list: vec![(zero_address(), vec![7u8.into()])],
+            },
+            None,
+        ),
+    ];
+
+    let runner = System::new();
+    runner.block_on(async move {
+        // a round trip through the JSON-RPC format must produce the same signature
+        for (transaction, network_id) in transactions {
+            assert_eq!(
+                remote
+                    .sign_transaction(transaction.clone(), network_id)
+                    .await
+                    .unwrap(),
+                key.sign_transaction(transaction, network_id).await.unwrap()
+            );
+        }
+        assert_eq!(
+            remote.sign_message(b"hello").await.unwrap(),
+            key.sign_message(b"hello").await.unwrap()
+        );
+
+        let other = RemoteSigner::with_transport(zero_address(), LocalSignerTransport::new(key));
+        assert!(other.sign_message(b"hello").await.is_err());
+    });
+}
+
+#[test]
+fn test_remote_contract_creation() {
+    let key: PrivateKey = "0xfe1bfd3aa9a6b18ce38cbe07e1a8e9e84e6a0cb98e6e2b56d70b4ea5d3b9b7c4"
+        .parse()
+        .unwrap();
+    // clarity signs a zero destination as a contract creation, which is requested without `to`
+    let creation = Transaction::Legacy {
+        nonce: 1u8.into(),
+        gas_price: 2u8.into(),
+        gas_limit: 100_000u32.into(),
+        to: zero_address(),
+        value: 0u8.into(),
+        data: vec![0x60, 0x00],
+        signature: None,
+    };
+    let request = RemoteTransaction::new(&creation, key.to_address(), Some(1));
+    assert!(json!(request).get("to").is_none());
+    let (transaction, network_id) = request.clone().into_transaction().unwrap();
+    assert_eq!(transaction, creation);
+    assert_eq!(network_id, Some(1));
+
+    // a transfer to the zero address would be signed as a creation and is refused
+    let transfer = RemoteTransaction {
+        to: Some(zero_address()),
+        ..request
+    };
+    assert!(transfer.into_transaction().is_err());
+}
+
+#[test]
+fn test_verify_signed_transaction() {
+    use actix::System;
+
+    let key: PrivateKey = "0xfe1bfd3aa9a6b18ce38cbe07e1a8e9e84e6a0cb98e6e2b56d70b4ea5d3b9b7c4"
+        .parse()
+        .unwrap();
+    let other: PrivateKey = "0x2c5cdb9e3fbd2b0a4f2a5ec6e4ac1c4ab8dbee1b2ed1f5b89ff4cb95bf2f1ad9"
+        .parse()
+        .unwrap();
+    let transaction = Transaction::Legacy {
+        nonce: 1u8.into(),
+        gas_price: 2u8.into(),
+        gas_limit: 21000u16.into(),
+        to: key.to_address(),
+        value: 3u8.into(),
+        data: Vec::new(),
+        signature: None,
+    };
+    let mut changed = transaction.clone();
+    if let Transaction::Legacy { value, .. } = &mut changed {
+        *value = 4u8.into();
+    }
+
+    let runner = System::new();
+    runner.block_on(async move {
+        let raw = key
+            .sign_transaction(transaction.clone(), Some(1))
+            .await
+            .unwrap();
+        let signed =
+            verify_signed_transaction(raw.clone(), &transaction, Some(1), key.to_address())
+                .unwrap();
+        assert_eq!(signed.raw, raw);
+        // signed for another chain
+        assert!(
+            verify_signed_transaction(raw.clone(), &transaction, Some(56), key.to_address())
+                .is_err()
+        );
+        // signed by another key
+        assert!(verify_signed_transaction(raw, &transaction, Some(1), other.to_address()).is_err());
+        // not the transaction that was asked for
+        let raw = key.sign_transaction(changed, Some(1)).await.unwrap();
+        assert!(verify_signed_transaction(raw, &transaction, Some(1), key.to_address()).is_err());
+    });
+}
@@ -1,5 +1,6 @@
 use clarity::{
     abi::{encode_tokens, AbiToken as Token},
+    utils::hex_str_to_bytes,
     Address as EthAddress, PrivateKey, Uint256,
 };
 use heliosphere::{
@@ -7,6 +8,7 @@ use heliosphere::{
     MethodCall, RpcClient,
 };
 use num_traits::ToPrimitive;
+use sha2::{Digest, Sha256};
 
 use crate::{jsonrpc::error::Web3Error, types::SendTxOption};
 
@@ -59,3 +61,99 @@ pub async fn send_transaction(
 
     Ok(Uint256::from_be_bytes(&tx_id.0))
 }
+
+/// Sends a native TRX transfer of `amount` sun
+pub async fn send_native(
+    client: &RpcClient,
+    to: EthAddress,
+    amount: Uint256,
+    sender_secret: PrivateKey,
+) -> Result<Uint256, Web3Error> {
+    let keypair = Keypair::from_bytes(&sender_secret.to_bytes()).expect("Wrong secret key");
+    let amount = match amount.to_u64() {
+        Some(amount) => amount,
+        None => {
+            return Err(Web3Error::BadInput(format!(
+                "TRX amount {amount} does not fit in 64 bits"
+            )))
+        }
+    };
+
+    let mut tx = client
+        .trx_transfer(&keypair.address(), &to.into(), amount)
+        .await?;
+    keypair.sign_transaction(&mut tx).unwrap();
+    let tx_id = client.broadcast_transaction(&tx).await?;
+
+    Ok(Uint256::from_be_bytes(&tx_id.0))
+}
+
+/// Parses a Tron address as found in event results, either base58check (`T...`), hex with
+/// the 0x41 prefix or 20 bytes of hex with an optional 0x prefix
+pub(crate) fn parse_tron_address(address: &str) -> Result<EthAddress, Web3Error> {
+    let invalid = || Web3Error::BadInput(format!("Invalid Tron address {address}"));
+    let digits = address.strip_prefix("0x").unwrap_or(address);
+    let bytes = if digits.len() == 40 || (digits.len() == 42 && digits.starts_with("41")) {
+        hex_str_to_bytes(&format!("0x{digits}")).map_err(|_| invalid())?
+    } else {
+        let decoded = decode_base58(address).ok_or_else(invalid)?;
+        if decoded.len() != 25 {
+            return Err(invalid());
+        }
+        let (payload, checksum) = decoded.split_at(21);
+        if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+            return Err(invalid());
+        }
+        payload.to_vec()
+    };
+    let bytes = match bytes.len() {
+        21 if bytes[0] == 0x41 => &bytes[1..],
+        20 => &bytes[..],
+        _ => return Err(invalid()),
+    };
+    EthAddress::from_slice(bytes).map_err(|_| invalid())
+}
+
+fn decode_base58(input: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    // big endian base 256 digits of the value decoded so far
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input.bytes() {
+        let mut carry = ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    // each leading '1' encodes a leading zero byte
+    let zeros = input.bytes().take_while(|c| *c == b'1').count();
+    let mut output = vec![0u8; zeros];
+    output.extend(bytes);
+    Some(output)
+}
+
+#[test]
+fn test_parse_tron_address() {
+    let expected: EthAddress = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c"
+        .parse()
+        .unwrap();
+    // the USDT contract
+    assert_eq!(
+        parse_tron_address("TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t").unwrap(),
+        expected
+    );
+    assert_eq!(
+        parse_tron_address("41a614f803b6fd780986a42c78ec9c7f77e6ded13c").unwrap(),
+        expected
+    );
+    assert_eq!(
+        parse_tron_address("0xa614f803b6fd780986a42c78ec9c7f77e6ded13c").unwrap(),
+        expected
+    );
+    assert!(parse_tron_address("TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6u").is_err());
+}
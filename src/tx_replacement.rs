@@ -0,0 +1,655 @@
+//! This module contains functions for replacing transactions that are stuck in the mempool,
+//! either by sending the same transaction again with higher fees or by cancelling it with a
+//! zero value transfer to ourselves. Both reuse the nonce of the original transaction so only
+//! one of them can ever be included.
+use crate::client::{multiply_fee, Web3, ETHEREUM_INTRINSIC_GAS};
+use crate::jsonrpc::error::Web3Error;
+use crate::signer::{verify_signed_transaction, Signer};
+use crate::types::{clarity_destination, SendTxOption, TransactionResponse};
+use clarity::abi::AbiToken as Token;
+use clarity::{Address, Transaction, Uint256};
+use futures::future::select_ok;
+use num_traits::ToPrimitive;
+use std::cmp::max;
+use std::time::{Duration, Instant};
+use tokio::time::sleep as delay_for;
+
+/// Nodes only accept a replacement at the same nonce if every fee is raised by at least
+/// this percentage, anything less is rejected as underpriced
+pub const MIN_REPLACEMENT_BUMP_PERCENT: u8 = 10;
+
+/// Configures how `send_transaction_until_mined` escalates fees
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebroadcastConfig {
+    /// How many blocks to wait for inclusion before replacing the transaction
+    pub blocks_per_attempt: u64,
+    /// Fee multiplier applied on every replacement, the minimum replacement bump
+    /// is always applied
+    pub bump: f32,
+    /// The highest max fee per gas (gas price for legacy transactions) a replacement may
+    /// pay, once reached we stop replacing and keep waiting
+    pub fee_cap: Uint256,
+    /// The total time to wait for any of the broadcast transactions to be mined
+    pub timeout: Duration,
+}
+
+/// A transaction broadcast by `send_transaction_until_mined`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastAttempt {
+    pub tx_hash: Uint256,
+    /// The max fee per gas, or gas price for legacy transactions, `None` if the
+    /// node could not return the original transaction
+    pub fee: Option<Uint256>,
+}
+
+/// The outcome of `send_transaction_until_mined`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebroadcastResult {
+    /// The hash of the transaction that was mined
+    pub tx_hash: Uint256,
+    /// Every transaction broadcast at this nonce, starting with the original
+    pub history: Vec<BroadcastAttempt>,
+}
+
+/// Multiplies a fee by `bump`, never returning less than the minimum fee a node will
+/// accept as a replacement
+pub fn bump_fee(fee: Uint256, bump: f32) -> Uint256 {
+    let minimum = fee + fee * MIN_REPLACEMENT_BUMP_PERCENT.into() / 100u8.into() + 1u8.into();
+    max(minimum, multiply_fee(fee, bump))
+}
+
+impl Web3 {
+    /// Replaces a pending transaction with an identical one (same nonce, destination, value
+    /// and payload) paying fees multiplied by `bump`, at least the 10% replacement minimum
+    /// is always applied. Returns the hash of the replacement transaction
+    pub async fn speed_up_transaction(
+        &self,
+        tx_hash: Uint256,
+        signer: impl Signer,
+        bump: f32,
+    ) -> Result<Uint256, Web3Error> {
+        self.replace_transaction(tx_hash, signer, bump, false, false)
+            .await
+    }
+
+    /// Cancels a pending transaction by replacing it with a zero value transfer to our own
+    /// address at the same nonce, fees are bumped the same way as `speed_up_transaction`.
+    /// Returns the hash of the cancelling transaction, the original is only cancelled once
+    /// this is included
+    pub async fn cancel_transaction(
+        &self,
+        tx_hash: Uint256,
+        signer: impl Signer,
+        bump: f32,
+    ) -> Result<Uint256, Web3Error> {
+        self.replace_transaction(tx_hash, signer, bump, true, false)
+            .await
+    }
+
+    /// Like `speed_up_transaction` or `cancel_transaction` depending on `cancel`, but also
+    /// replaces pre EIP-155 legacy transactions. Those carry no chain id, so there is no way to
+    /// check that the original was meant for the chain the node is on. The replacement is
+    /// signed with EIP-155 replay protection for the chain id the node reports.
+    pub async fn replace_unprotected_transaction(
+        &self,
+        tx_hash: Uint256,
+        signer: impl Signer,
+        bump: f32,
+        cancel: bool,
+    ) -> Result<Uint256, Web3Error> {
+        self.replace_transaction(tx_hash, signer, bump, cancel, true)
+            .await
+    }
+
+    /// Sends a transaction with `send_transaction` and makes sure it lands. If it is not mined
+    /// within `config.blocks_per_attempt` blocks it is replaced by the same transaction with
+    /// escalated fees, up to `config.fee_cap`. Since any of the broadcast transactions may
+    /// end up mined all of them are watched, the hash that was mined is returned along with
+    /// the full replacement history. Not supported on Tron.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_transaction_until_mined(
+        &self,
+        to_address: Address,
+        selector: &str,
+        tokens: &[Token],
+        value: Uint256,
+        own_address: Address,
+        signer: impl Signer,
+        options: Vec<SendTxOption>,
+        config: RebroadcastConfig,
+    ) -> Result<RebroadcastResult, Web3Error> {
+        let start = Instant::now();
+        let tx_hash = self
+            .send_transaction(
+                to_address,
+                selector,
+                tokens,
+                value,
+                own_address,
+                &signer,
+                options,
+            )
+            .await?;
+
+        // the unsigned copy of the latest broadcast, replacements are bumped from it
+        let mut current = match self.eth_get_transaction_by_hash(tx_hash).await? {
+            Some(original) => Some(unsigned_transaction(&original, own_address, false)?),
+            None => None,
+        };
+        let mut history = vec![BroadcastAttempt {
+            tx_hash,
+            fee: current.as_ref().map(|(tx, _)| transaction_fee(tx)),
+        }];
+
+        loop {
+            let remaining = match config.timeout.checked_sub(start.elapsed()) {
+                Some(remaining) => remaining,
+                None => return Err(Web3Error::TransactionTimeout),
+            };
+            let target_block = self.eth_block_number().await? + config.blocks_per_attempt.into();
+
+            let waits = history.iter().map(|attempt| {
+                Box::pin(self.wait_for_transaction(attempt.tx_hash, remaining, None))
+            });
+            tokio::select! {
+                mined = select_ok(waits) => {
+                    let (tx_hash, _) = mined?;
+                    return Ok(RebroadcastResult { tx_hash, history });
+                }
+                reached = self.wait_for_block_number(target_block, remaining) => reached?,
+            }
+
+            if current.is_none() {
+                // the node accepted the original so it should eventually return it
+                if let Some(original) = self.eth_get_transaction_by_hash(tx_hash).await? {
+                    current = Some(unsigned_transaction(&original, own_address, false)?);
+                }
+            }
+            let (transaction, network_id) = match &current {
+                Some(current) => current.clone(),
+                None => continue,
+            };
+            let replacement =
+                match bump_transaction_fees(transaction, config.bump, Some(config.fee_cap)) {
+                    Some(replacement) => replacement,
+                    None => {
+                        debug!("Fee cap reached for {tx_hash:#066x}, waiting without replacing");
+                        continue;
+                    }
+                };
+
+            let fee = transaction_fee(&replacement);
+            let network_id = self
+                .replacement_network_id(&replacement, network_id, false)
+                .await?;
+            let raw = signer
+                .sign_transaction(replacement.clone(), network_id)
+                .await?;
+            let signed =
+                verify_signed_transaction(raw, &replacement, network_id, signer.address())?;
+            match self.eth_send_raw_transaction(signed.raw).await {
+                Ok(replacement_hash) => {
+                    info!("Replaced {tx_hash:#066x} with {replacement_hash:#066x} paying {fee}");
+                    history.push(BroadcastAttempt {
+                        tx_hash: replacement_hash,
+                        fee: Some(fee),
+                    });
+                    current = Some((replacement, network_id));
+                }
+                // most likely an earlier transaction was mined in the meantime,
+                // which the next wait will pick up
+                Err(e) => warn!("Failed to broadcast replacement for {tx_hash:#066x} {e}"),
+            }
+        }
+    }
+
+    /// Checks a replacement against `signing_chain_id`, see `replacement_network_id`
+    async fn replacement_network_id(
+        &self,
+        transaction: &Transaction,
+        network_id: Option<u64>,
+        allow_unprotected: bool,
+    ) -> Result<Option<u64>, Web3Error> {
+        let chain_id = self.signing_chain_id().await?;
+        replacement_network_id(chain_id, transaction, network_id, allow_unprotected)
+    }
+
+    /// Waits until the chain reaches `target` height
+    async fn wait_for_block_number(
+        &self,
+        target: Uint256,
+        timeout: Duration,
+    ) -> Result<(), Web3Error> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if self.eth_block_number().await? >= target {
+                return Ok(());
+            }
+            delay_for(Duration::from_secs(1)).await;
+        }
+        Err(Web3Error::TransactionTimeout)
+    }
+
+    async fn replace_transaction(
+        &self,
+        tx_hash: Uint256,
+        signer: impl Signer,
+        bump: f32,
+        cancel: bool,
+        allow_unprotected: bool,
+    ) -> Result<Uint256, Web3Error> {
+        let original = match self.eth_get_transaction_by_hash(tx_hash).await? {
+            Some(tx) => tx,
+            None => {
+                return Err(Web3Error::BadInput(format!(
+                    "Transaction {tx_hash:#066x} not found, it may have been dropped"
+                )))
+            }
+        };
+        let own_address = signer.address();
+        if original.get_from() != own_address {
+            return Err(Web3Error::BadInput(
+                "Can not replace a transaction sent by another address".to_string(),
+            ));
+        }
+        if original.get_block_number().is_some() {
+            return Err(Web3Error::BadInput(format!(
+                "Transaction {tx_hash:#066x} is already in a block"
+            )));
+        }
+
+        let (transaction, network_id) =
+            replacement_transaction(&original, own_address, bump, cancel)?;
+
+        let network_id = self
+            .replacement_network_id(&transaction, network_id, allow_unprotected)
+            .await?;
+        let raw = signer
+            .sign_transaction(transaction.clone(), network_id)
+            .await?;
+        let signed = verify_signed_transaction(raw, &transaction, network_id, own_address)?;
+
+        self.eth_send_raw_transaction(signed.raw).await
+    }
+}
+
+/// Checks the chain id of a replacement against the chain id we sign for, so that a
+/// transaction is never re-signed for a chain other than the one we expect, and returns the
+/// EIP-155 network id to sign it with. Pre EIP-155 legacy transactions have no chain id to
+/// check, they are refused unless `allow_unprotected` is set, in which case the replacement
+/// is signed for `chain_id`
+fn replacement_network_id(
+    chain_id: u64,
+    transaction: &Transaction,
+    network_id: Option<u64>,
+    allow_unprotected: bool,
+) -> Result<Option<u64>, Web3Error> {
+    let original_chain_id = match transaction {
+        Transaction::Legacy { .. } => network_id,
+        Transaction::Eip2930 { chain_id, .. } | Transaction::Eip1559 { chain_id, .. } => {
+            Some(chain_id.to_u64().unwrap_or(u64::MAX))
+        }
+    };
+    match original_chain_id {
+        Some(original_chain_id) if original_chain_id != chain_id => {
+            Err(Web3Error::ChainIdMismatch {
+                expected: chain_id,
+                reported: original_chain_id,
+            })
+        }
+        Some(_) => Ok(network_id),
+        None if allow_unprotected => Ok(Some(chain_id)),
+        None => Err(Web3Error::BadInput(
+            "Refusing to replace a transaction without a chain id, use \
+             replace_unprotected_transaction to replace it anyway"
+                .to_string(),
+        )),
+    }
+}
+
+/// Builds the unsigned replacement for `original` along with the EIP-155 network id
+/// legacy transactions have to be signed with
+fn replacement_transaction(
+    original: &TransactionResponse,
+    own_address: Address,
+    bump: f32,
+    cancel: bool,
+) -> Result<(Transaction, Option<u64>), Web3Error> {
+    let (transaction, network_id) = unsigned_transaction(original, own_address, cancel)?;
+    // without a cap a bump is always possible
+    let transaction = bump_transaction_fees(transaction, bump, None).unwrap();
+    Ok((transaction, network_id))
+}
+
+/// Raises every fee of an unsigned transaction by `bump`, at least the minimum replacement
+/// bump is applied. Fees are limited to `fee_cap`, returns `None` if the cap is too low for
+/// a valid replacement
+fn bump_transaction_fees(
+    mut transaction: Transaction,
+    bump: f32,
+    fee_cap: Option<Uint256>,
+) -> Option<Transaction> {
+    match &mut transaction {
+        Transaction::Legacy { gas_price, .. } | Transaction::Eip2930 { gas_price, .. } => {
+            *gas_price = bump_fee_capped(*gas_price, bump, fee_cap)?;
+        }
+        Transaction::Eip1559 {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            ..
+        } => {
+            *max_fee_per_gas = bump_fee_capped(*max_fee_per_gas, bump, fee_cap)?;
+            // the priority fee can never exceed the max fee
+            *max_priority_fee_per_gas =
+                bump_fee_capped(*max_priority_fee_per_gas, bump, Some(*max_fee_per_gas))?;
+        }
+    }
+    Some(transaction)
+}
+
+fn bump_fee_capped(fee: Uint256, bump: f32, fee_cap: Option<Uint256>) -> Option<Uint256> {
+    let bumped = bump_fee(fee, bump);
+    match fee_cap {
+        // a bump of zero produces the minimum accepted replacement fee
+        Some(cap) if bumped > cap => (bump_fee(fee, 0.0) <= cap).then_some(cap),
+        _ => Some(bumped),
+    }
+}
+
+/// The max fee per gas of a transaction, or the gas price for transactions without one
+fn transaction_fee(transaction: &Transaction) -> Uint256 {
+    match transaction {
+        Transaction::Legacy { gas_price, .. } | Transaction::Eip2930 { gas_price, .. } => {
+            *gas_price
+        }
+        Transaction::Eip1559 {
+            max_fee_per_gas, ..
+        } => *max_fee_per_gas,
+    }
+}
+
+/// Copies `original` into an unsigned transaction with the same fees, when cancelling the
+/// payload is replaced by a zero value transfer to `own_address`
+fn unsigned_transaction(
+    original: &TransactionResponse,
+    own_address: Address,
+    cancel: bool,
+) -> Result<(Transaction, Option<u64>), Web3Error> {
+    match original {
+        TransactionResponse::Legacy {
+            nonce,
+            gas_price,
+            gas,
+            to,
+            value,
+            input,
+            v,
+            ..
+        } => {
+            let (to, value, data, gas_limit) =
+                replacement_payload(own_address, cancel, to, *value, input, *gas)?;
+            // pre EIP-155 signatures have v of 27 or 28 and no chain id
+            let network_id = if *v >= 35u8.into() {
+                ((*v - 35u8.into()) / 2u8.into()).to_u64()
+            } else {
+                None
+            };
+            Ok((
+                Transaction::Legacy {
+                    nonce: *nonce,
+                    gas_price: *gas_price,
+                    gas_limit,
+                    to,
+                    value,
+                    data,
+                    signature: None,
+                },
+                network_id,
+            ))
+        }
+        TransactionResponse::Eip2930 {
+            chain_id,
+            nonce,
+            gas_price,
+            gas,
+            to,
+            value,
+            input,
+            access_list,
+            ..
+        } => {
+            let (to, value, data, gas_limit) =
+                replacement_payload(own_address, cancel, to, *value, input, *gas)?;
+            Ok((
+                Transaction::Eip2930 {
+                    access_list: if cancel {
+                        Vec::new()
+                    } else {
+                        to_clarity_access_list(access_list)?
+                    },
+                    chain_id: *chain_id,
+                    signature: None,
+                    nonce: *nonce,
+                    gas_price: *gas_price,
+                    gas_limit,
+                    to,
+                    value,
+                    data,
+                },
+                None,
+            ))
+        }
+        TransactionResponse::Eip1559 {
+            chain_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas,
+            to,
+            value,
+            input,
+            access_list,
+            ..
+        } => {
+            let (to, value, data, gas_limit) =
+                replacement_payload(own_address, cancel, to, *value, input, *gas)?;
+            Ok((
+                Transaction::Eip1559 {
+                    chain_id: *chain_id,
+                    nonce: *nonce,
+                    max_priority_fee_per_gas: *max_priority_fee_per_gas,
+                    max_fee_per_gas: *max_fee_per_gas,
+                    gas_limit,
+                    to,
+                    value,
+                    data,
+                    signature: None,
+                    access_list: if cancel {
+                        Vec::new()
+                    } else {
+                        to_clarity_access_list(access_list)?
+                    },
+                },
+                None,
+            ))
+        }
+        TransactionResponse::Eip4844 { .. }
+        | TransactionResponse::Eip7702 { .. }
+        | TransactionResponse::Unknown { .. } => Err(Web3Error::BadInput(format!(
+            "Replacing type {} transactions is not supported",
+            original.get_type()
+        ))),
+    }
+}
+
+/// Returns the destination, value, data and gas limit of a replacement
+fn replacement_payload(
+    own_address: Address,
+    cancel: bool,
+    to: &Option<Address>,
+    value: Uint256,
+    input: &[u8],
+    gas: Uint256,
+) -> Result<(Address, Uint256, Vec<u8>, Uint256), Web3Error> {
+    if cancel {
+        return Ok((
+            own_address,
+            0u8.into(),
+            Vec::new(),
+            ETHEREUM_INTRINSIC_GAS.into(),
+        ));
+    }
+    Ok((clarity_destination(*to)?, value, input.to_vec(), gas))
+}
+
+/// Access list addresses are returned left padded to 32 bytes
+fn to_clarity_access_list(
+    access_list: &[(Uint256, Vec<Uint256>)],
+) -> Result<Vec<(Address, Vec<Uint256>)>, Web3Error> {
+    access_list
+        .iter()
+        .map(
+            |(address, keys)| match Address::from_slice(&address.to_be_bytes()[12..]) {
+                Ok(address) => Ok((address, keys.clone())),
+                Err(e) => Err(Web3Error::BadResponse(e.to_string())),
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn test_bump_fee() {
+    // the minimum replacement bump is always applied
+    assert_eq!(bump_fee(100u8.into(), 1.0), 111u8.into());
+    assert_eq!(bump_fee(0u8.into(), 1.0), 1u8.into());
+    assert_eq!(bump_fee(100u8.into(), 2.0), 200u8.into());
+    // fees are scaled without a lossy float conversion and rounded up
+    assert_eq!(
+        bump_fee(1_000_000_001u32.into(), 1.5),
+        1_500_000_002u32.into()
+    );
+}
+
+#[test]
+fn test_replacement_transaction() {
+    let legacy = r#"{
+        "blockHash": null,
+        "blockNumber": null,
+        "from": "0x802275979b020f0ec871c5ec1db6e412b72ff20b",
+        "gas": "0x186a0",
+        "gasPrice": "0x3b9aca00",
+        "hash": "0xceb484eb92fd7ad626bc5aced6d669a693baf3d776b515a08d65fafca633a6a6",
+        "input": "0xdeadbeef",
+        "nonce": "0x5",
+        "to": "0xaf38668f4719ecf9452dc0300be3f6c83cbf3721",
+        "transactionIndex": null,
+        "value": "0x10",
+        "v": "0x26",
+        "r": "0x3f25525c83f8260820b5bbfcb3849d83d3740c35ee23ee0d3edb110c845b4abd",
+        "s": "0x7f9eddf929ae5afc7325ee9377c776ddeebebe0701097336340aca8c32668579"
+    }"#;
+    let original: TransactionResponse = serde_json::from_str(legacy).unwrap();
+    let own_address = original.get_from();
+
+    let (sped_up, network_id) =
+        replacement_transaction(&original, own_address, 1.5, false).unwrap();
+    assert_eq!(network_id, Some(1));
+    match sped_up {
+        Transaction::Legacy {
+            nonce,
+            gas_price,
+            to,
+            value,
+            data,
+            ..
+        } => {
+            assert_eq!(nonce, 5u8.into());
+            assert_eq!(gas_price, 1_500_000_000u32.into());
+            assert_ne!(to, own_address);
+            assert_eq!(value, 16u8.into());
+            assert_eq!(data, vec![0xde, 0xad, 0xbe, 0xef]);
+        }
+        _ => panic!("Expected a legacy replacement"),
+    }
+
+    let (cancel, _) = replacement_transaction(&original, own_address, 1.0, true).unwrap();
+    match cancel {
+        Transaction::Legacy {
+            nonce,
+            gas_price,
+            gas_limit,
+            to,
+            value,
+            data,
+            ..
+        } => {
+            assert_eq!(nonce, 5u8.into());
+            assert_eq!(gas_price, 1_100_000_001u32.into());
+            assert_eq!(gas_limit, ETHEREUM_INTRINSIC_GAS.into());
+            assert_eq!(to, own_address);
+            assert_eq!(value, 0u8.into());
+            assert!(data.is_empty());
+        }
+        _ => panic!("Expected a legacy replacement"),
+    }
+}
+
+#[test]
+fn test_fee_cap() {
+    let transaction = Transaction::Eip1559 {
+        chain_id: 1u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 10u8.into(),
+        max_fee_per_gas: 100u8.into(),
+        gas_limit: ETHEREUM_INTRINSIC_GAS.into(),
+        to: clarity::constants::zero_address(),
+        value: 0u8.into(),
+        data: Vec::new(),
+        signature: None,
+        access_list: Vec::new(),
+    };
+
+    // the bump is limited to the cap as long as the cap is a valid replacement
+    let bumped = bump_transaction_fees(transaction.clone(), 2.0, Some(150u8.into())).unwrap();
+    assert_eq!(transaction_fee(&bumped), 150u8.into());
+    match bumped {
+        Transaction::Eip1559 {
+            max_priority_fee_per_gas,
+            ..
+        } => assert_eq!(max_priority_fee_per_gas, 20u8.into()),
+        _ => panic!("Expected an EIP1559 transaction"),
+    }
+
+    // a cap below the minimum replacement fee makes replacement impossible
+    assert!(bump_transaction_fees(transaction, 2.0, Some(105u8.into())).is_none());
+}
+
+#[test]
+fn test_replacement_network_id() {
+    let legacy = Transaction::Legacy {
+        nonce: 0u8.into(),
+        gas_price: 1u8.into(),
+        gas_limit: ETHEREUM_INTRINSIC_GAS.into(),
+        to: clarity::constants::zero_address(),
+        value: 0u8.into(),
+        data: Vec::new(),
+        signature: None,
+    };
+    assert_eq!(
+        replacement_network_id(1, &legacy, Some(1), false).unwrap(),
+        Some(1)
+    );
+    assert!(matches!(
+        replacement_network_id(56, &legacy, Some(1), true),
+        Err(Web3Error::ChainIdMismatch {
+            expected: 56,
+            reported: 1
+        })
+    ));
+    // pre EIP-155 originals need an explicit opt in and are then signed for our chain
+    assert!(replacement_network_id(1, &legacy, None, false).is_err());
+    assert_eq!(
+        replacement_network_id(1, &legacy, None, true).unwrap(),
+        Some(1)
+    );
+}
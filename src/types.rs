@@ -1,7 +1,13 @@
+use crate::hash_utils::keccak256;
+use crate::jsonrpc::error::Web3Error;
+use crate::rlp::{encode_bytes, encode_list};
+use clarity::constants::zero_address;
+use clarity::rlp::{unpack_rlp, RlpToken};
 use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
 use clarity::{Address, Transaction};
 use num256::Uint256;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use std::str::FromStr;
 use std::{cmp::Ordering, ops::Deref};
 
@@ -84,10 +90,126 @@ impl From<Vec<u8>> for Data {
 
 /// As received by getTransactionByHash
 ///
+/// Variants are selected by the EIP-2718 `type` field of the response, transactions
+/// without a `type` field (pre-Berlin nodes) are decoded as `Legacy`
+///
 /// See more: https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_gettransactionbyhash
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
-#[serde(untagged)]
+#[serde(remote = "Self")]
+#[serde(tag = "type")]
 pub enum TransactionResponse {
+    #[serde(rename = "0x4")]
+    Eip7702 {
+        /// hash of the block where this transaction was in. null when its pending.
+        #[serde(rename = "blockHash")]
+        block_hash: Option<Data>,
+        /// block number where this transaction was in. null when its pending.
+        #[serde(rename = "blockNumber")]
+        block_number: Option<Uint256>,
+        /// The chain id field of this transaction.
+        #[serde(rename = "chainId")]
+        chain_id: Uint256,
+        /// address of the sender.
+        from: Address,
+        /// gas provided by the sender.
+        gas: Uint256,
+        /// gas price actually paid in Wei.
+        #[serde(rename = "gasPrice")]
+        gas_price: Uint256,
+        /// gas price provided by the sender in Wei
+        #[serde(rename = "maxFeePerGas")]
+        max_fee_per_gas: Uint256,
+        /// gas price provided by the sender in Wei
+        #[serde(rename = "maxPriorityFeePerGas")]
+        max_priority_fee_per_gas: Uint256,
+        /// hash of the transaction
+        hash: Data,
+        /// the data send along with the transaction.
+        input: Data,
+        /// the number of transactions made by the sender prior to this one.
+        nonce: Uint256,
+        /// address of the receiver, set code transactions can not create contracts
+        to: Address,
+        /// integer of the transaction's index position in the block. null when its pending.
+        #[serde(rename = "transactionIndex")]
+        transaction_index: Option<Uint256>,
+        /// value transferred in Wei.
+        value: Uint256,
+        /// ECDSA recovery id
+        v: Uint256,
+        /// ECDSA signature r
+        r: Uint256,
+        /// ECDSA signature s
+        s: Uint256,
+        /// The storage access list for this transaction
+        #[serde(
+            rename = "accessList",
+            serialize_with = "access_list_serialize",
+            deserialize_with = "access_list_deserialize"
+        )]
+        access_list: Vec<(Uint256, Vec<Uint256>)>,
+        /// The signed delegations this transaction installs as code on the authorizing accounts
+        #[serde(rename = "authorizationList")]
+        authorization_list: Vec<SignedAuthorization>,
+    },
+    #[serde(rename = "0x3")]
+    Eip4844 {
+        /// hash of the block where this transaction was in. null when its pending.
+        #[serde(rename = "blockHash")]
+        block_hash: Option<Data>,
+        /// block number where this transaction was in. null when its pending.
+        #[serde(rename = "blockNumber")]
+        block_number: Option<Uint256>,
+        /// The chain id field of this transaction.
+        #[serde(rename = "chainId")]
+        chain_id: Uint256,
+        /// address of the sender.
+        from: Address,
+        /// gas provided by the sender.
+        gas: Uint256,
+        /// gas price actually paid in Wei.
+        #[serde(rename = "gasPrice")]
+        gas_price: Uint256,
+        /// gas price provided by the sender in Wei
+        #[serde(rename = "maxFeePerGas")]
+        max_fee_per_gas: Uint256,
+        /// gas price provided by the sender in Wei
+        #[serde(rename = "maxPriorityFeePerGas")]
+        max_priority_fee_per_gas: Uint256,
+        /// blob gas price provided by the sender in Wei
+        #[serde(rename = "maxFeePerBlobGas")]
+        max_fee_per_blob_gas: Uint256,
+        /// hash of the transaction
+        hash: Data,
+        /// the data send along with the transaction.
+        input: Data,
+        /// the number of transactions made by the sender prior to this one.
+        nonce: Uint256,
+        /// address of the receiver, blob transactions can not create contracts
+        to: Address,
+        /// integer of the transaction's index position in the block. null when its pending.
+        #[serde(rename = "transactionIndex")]
+        transaction_index: Option<Uint256>,
+        /// value transferred in Wei.
+        value: Uint256,
+        /// ECDSA recovery id
+        v: Uint256,
+        /// ECDSA signature r
+        r: Uint256,
+        /// ECDSA signature s
+        s: Uint256,
+        /// The storage access list for this transaction
+        #[serde(
+            rename = "accessList",
+            serialize_with = "access_list_serialize",
+            deserialize_with = "access_list_deserialize"
+        )]
+        access_list: Vec<(Uint256, Vec<Uint256>)>,
+        /// The versioned hashes of the blobs carried by this transaction
+        #[serde(rename = "blobVersionedHashes")]
+        blob_versioned_hashes: Vec<Data>,
+    },
+    #[serde(rename = "0x2")]
     Eip1559 {
         /// hash of the block where this transaction was in. null when its pending.
         #[serde(rename = "blockHash")]
@@ -131,9 +253,14 @@ pub enum TransactionResponse {
         /// ECDSA signature s
         s: Uint256,
         /// The storage access list for this transaction
-        #[serde(rename = "accessList")]
+        #[serde(
+            rename = "accessList",
+            serialize_with = "access_list_serialize",
+            deserialize_with = "access_list_deserialize"
+        )]
         access_list: Vec<(Uint256, Vec<Uint256>)>,
     },
+    #[serde(rename = "0x1")]
     Eip2930 {
         /// hash of the block where this transaction was in. null when its pending.
         #[serde(rename = "blockHash")]
@@ -171,9 +298,14 @@ pub enum TransactionResponse {
         /// ECDSA signature s
         s: Uint256,
         /// The storage access list for this transaction
-        #[serde(rename = "accessList")]
+        #[serde(
+            rename = "accessList",
+            serialize_with = "access_list_serialize",
+            deserialize_with = "access_list_deserialize"
+        )]
         access_list: Vec<(Uint256, Vec<Uint256>)>,
     },
+    #[serde(rename = "0x0")]
     Legacy {
         /// hash of the block where this transaction was in. null when its pending.
         #[serde(rename = "blockHash")]
@@ -208,34 +340,185 @@ pub enum TransactionResponse {
         /// ECDSA signature s
         s: Uint256,
     },
+    /// A transaction of a type this library does not know, such as the deposit (0x7e)
+    /// transactions of OP stack chains or Arbitrum's system transactions, kept as returned
+    /// by the node
+    #[serde(skip)]
+    Unknown { tx_type: u8, raw: Value },
+}
+
+impl Serialize for TransactionResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            TransactionResponse::Unknown { raw, .. } => raw.serialize(serializer),
+            _ => TransactionResponse::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionResponse {
+    /// Normalizes the `type` field before handing off to the derived implementation,
+    /// nodes are inconsistent about padding ("0x02" vs "0x2") and pre-Berlin nodes
+    /// omit the field entirely for what can only be legacy transactions. Types above
+    /// 0x4 decode as `Unknown` so that blocks of L2 chains still decode
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut value = Value::deserialize(deserializer)?;
+        let object = match value.as_object_mut() {
+            Some(object) => object,
+            None => return Err(serde::de::Error::custom("transaction is not an object")),
+        };
+        let tx_type = match object.get("type") {
+            None | Some(Value::Null) => 0,
+            Some(Value::String(tx_type)) => {
+                u8::from_str_radix(tx_type.trim_start_matches("0x"), 16).map_err(|_| {
+                    serde::de::Error::custom(format!("invalid transaction type {tx_type}"))
+                })?
+            }
+            Some(other) => {
+                return Err(serde::de::Error::custom(format!(
+                    "invalid transaction type {other}"
+                )))
+            }
+        };
+        if tx_type > 4 {
+            return Ok(TransactionResponse::Unknown {
+                tx_type,
+                raw: value,
+            });
+        }
+        object.insert("type".to_string(), Value::String(format!("{tx_type:#x}")));
+
+        TransactionResponse::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A signed EIP-7702 authorization as returned in the `authorizationList`
+/// of a set code transaction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignedAuthorization {
+    /// The chain id this authorization is valid on, zero for any chain
+    #[serde(rename = "chainId")]
+    pub chain_id: Uint256,
+    /// The contract whose code is delegated to
+    pub address: Address,
+    /// The nonce of the authorizing account
+    pub nonce: Uint256,
+    /// ECDSA recovery id
+    #[serde(rename = "yParity", alias = "v")]
+    pub y_parity: Uint256,
+    /// ECDSA signature r
+    pub r: Uint256,
+    /// ECDSA signature s
+    pub s: Uint256,
+}
+
+/// A single access list entry in the format used by the JSONRPC API
+#[derive(Serialize, Deserialize)]
+struct AccessListEntry {
+    address: Uint256,
+    #[serde(rename = "storageKeys")]
+    storage_keys: Vec<Uint256>,
+}
+
+fn access_list_serialize<S>(list: &[(Uint256, Vec<Uint256>)], s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let entries: Vec<AccessListEntry> = list
+        .iter()
+        .map(|(address, storage_keys)| AccessListEntry {
+            address: *address,
+            storage_keys: storage_keys.clone(),
+        })
+        .collect();
+    entries.serialize(s)
+}
+
+fn access_list_deserialize<'de, D>(d: D) -> Result<Vec<(Uint256, Vec<Uint256>)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries: Vec<AccessListEntry> = Vec::deserialize(d)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.address, entry.storage_keys))
+        .collect())
 }
 
 impl TransactionResponse {
     pub fn get_block_number(&self) -> Option<Uint256> {
         match self {
-            TransactionResponse::Eip1559 { block_number, .. }
+            TransactionResponse::Eip7702 { block_number, .. }
+            | TransactionResponse::Eip4844 { block_number, .. }
+            | TransactionResponse::Eip1559 { block_number, .. }
             | TransactionResponse::Eip2930 { block_number, .. }
             | TransactionResponse::Legacy { block_number, .. } => *block_number,
+            TransactionResponse::Unknown { raw, .. } => unknown_field(raw, "blockNumber"),
         }
     }
     pub fn get_nonce(&self) -> Uint256 {
         match self {
-            TransactionResponse::Eip1559 { nonce, .. }
+            TransactionResponse::Eip7702 { nonce, .. }
+            | TransactionResponse::Eip4844 { nonce, .. }
+            | TransactionResponse::Eip1559 { nonce, .. }
             | TransactionResponse::Eip2930 { nonce, .. }
             | TransactionResponse::Legacy { nonce, .. } => *nonce,
+            TransactionResponse::Unknown { raw, .. } => {
+                unknown_field(raw, "nonce").unwrap_or_default()
+            }
         }
     }
     pub fn get_block_hash(&self) -> Option<Vec<u8>> {
         match self {
-            TransactionResponse::Eip1559 { block_hash, .. }
+            TransactionResponse::Eip7702 { block_hash, .. }
+            | TransactionResponse::Eip4844 { block_hash, .. }
+            | TransactionResponse::Eip1559 { block_hash, .. }
             | TransactionResponse::Eip2930 { block_hash, .. }
             | TransactionResponse::Legacy { block_hash, .. } => {
                 block_hash.as_ref().map(|hash| (**hash).clone())
             }
+            TransactionResponse::Unknown { raw, .. } => {
+                unknown_field::<Data>(raw, "blockHash").map(|hash| hash.0)
+            }
+        }
+    }
+    /// Returns the EIP-2718 transaction type, 0 for legacy transactions
+    pub fn get_type(&self) -> u8 {
+        match self {
+            TransactionResponse::Legacy { .. } => 0,
+            TransactionResponse::Eip2930 { .. } => 1,
+            TransactionResponse::Eip1559 { .. } => 2,
+            TransactionResponse::Eip4844 { .. } => 3,
+            TransactionResponse::Eip7702 { .. } => 4,
+            TransactionResponse::Unknown { tx_type, .. } => *tx_type,
+        }
+    }
+    pub fn get_from(&self) -> Address {
+        match self {
+            TransactionResponse::Eip7702 { from, .. }
+            | TransactionResponse::Eip4844 { from, .. }
+            | TransactionResponse::Eip1559 { from, .. }
+            | TransactionResponse::Eip2930 { from, .. }
+            | TransactionResponse::Legacy { from, .. } => *from,
+            TransactionResponse::Unknown { raw, .. } => {
+                unknown_field(raw, "from").unwrap_or_else(zero_address)
+            }
         }
     }
 }
 
+/// Reads a field of an `Unknown` transaction, None if it is missing or malformed
+fn unknown_field<T: serde::de::DeserializeOwned>(raw: &Value, key: &str) -> Option<T> {
+    raw.get(key)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+}
+
 impl Ord for TransactionResponse {
     /// the goal of this ordering is to sort transactions by their block number,
     /// in the case of transactions in the same block or transactions without a block
@@ -263,12 +546,18 @@ impl PartialOrd for TransactionResponse {
     }
 }
 
+/// Filter for `eth_getLogs` and `eth_newFilter`, build one with `FilterBuilder` or start from
+/// `NewFilter::default()`, new fields may be added
 #[derive(Serialize, Default, Debug, Clone)]
+#[non_exhaustive]
 pub struct NewFilter {
     #[serde(rename = "fromBlock", skip_serializing_if = "Option::is_none")]
     pub from_block: Option<String>,
     #[serde(rename = "toBlock", skip_serializing_if = "Option::is_none")]
     pub to_block: Option<String>,
+    /// Restricts the filter to a single block, can't be combined with `from_block` or `to_block`
+    #[serde(rename = "blockHash", skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
     pub address: Vec<Address>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub topics: Option<Vec<Option<Vec<Option<String>>>>>,
@@ -284,8 +573,9 @@ pub enum TransactionRequest {
         chain_id: Option<UnpaddedHex>,
         //The address the transaction is send from.
         from: Address,
-        // The address the transaction is directed to.
-        to: Address,
+        // The address the transaction is directed to, none for contract creation.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<Address>,
         // Integer of the gas provided for the transaction execution. It will return unused gas.
         #[serde(skip_serializing_if = "Option::is_none")]
         gas: Option<UnpaddedHex>,
@@ -316,8 +606,9 @@ pub enum TransactionRequest {
         chain_id: Option<UnpaddedHex>,
         //The address the transaction is send from.
         from: Address,
-        // The address the transaction is directed to.
-        to: Address,
+        // The address the transaction is directed to, none for contract creation.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<Address>,
         // Integer of the gas provided for the transaction execution. It will return unused gas.
         #[serde(skip_serializing_if = "Option::is_none")]
         gas: Option<UnpaddedHex>,
@@ -341,8 +632,9 @@ pub enum TransactionRequest {
     Legacy {
         //The address the transaction is send from.
         from: Address,
-        // The address the transaction is directed to.
-        to: Address,
+        // The address the transaction is directed to, none for contract creation.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to: Option<Address>,
         // Integer of the gas provided for the transaction execution. It will return unused gas.
         #[serde(skip_serializing_if = "Option::is_none")]
         gas: Option<UnpaddedHex>,
@@ -402,6 +694,14 @@ impl TransactionRequest {
             | TransactionRequest::Legacy { gas, .. } => *gas = Some(gas_limit.into()),
         }
     }
+    /// Sets the destination, `None` for contract creation
+    pub fn set_to(&mut self, new_to: Option<Address>) {
+        match self {
+            TransactionRequest::Eip1559 { to, .. }
+            | TransactionRequest::Eip2930 { to, .. }
+            | TransactionRequest::Legacy { to, .. } => *to = new_to,
+        }
+    }
     /// A specialized gas price setter for simulations, EIP1559 gas is treated very differently on actual execution but
     /// for hte purpose of simulation it makes sense to set the value super high and see what results we get.
     pub fn set_gas_price(&mut self, new_gas_price: Uint256) {
@@ -415,6 +715,13 @@ impl TransactionRequest {
             }
         }
     }
+    pub fn get_to(&self) -> Option<Address> {
+        match self {
+            TransactionRequest::Eip1559 { to, .. }
+            | TransactionRequest::Eip2930 { to, .. }
+            | TransactionRequest::Legacy { to, .. } => *to,
+        }
+    }
     pub fn is_eip1559(&self) -> bool {
         matches!(*self, TransactionRequest::Eip1559 { .. })
     }
@@ -423,7 +730,7 @@ impl TransactionRequest {
         TransactionRequest::Eip1559 {
             chain_id: None,
             from,
-            to,
+            to: Some(to),
             gas: None,
             max_priority_fee_per_gas: None,
             max_fee_per_gas: None,
@@ -437,7 +744,7 @@ impl TransactionRequest {
     pub fn quick_legacy_tx(from: Address, to: Address, payload: Vec<u8>) -> TransactionRequest {
         TransactionRequest::Legacy {
             from,
-            to,
+            to: Some(to),
             gas: None,
             gas_price: None,
             value: None,
@@ -457,7 +764,7 @@ impl TransactionRequest {
                 signature: _,
             } => TransactionRequest::Legacy {
                 from,
-                to: *to,
+                to: Some(*to),
                 gas: Some((*gas_limit).into()),
                 gas_price: Some((*gas_price).into()),
                 value: Some((*value).into()),
@@ -477,7 +784,7 @@ impl TransactionRequest {
             } => TransactionRequest::Eip2930 {
                 from,
                 chain_id: Some((*chain_id).into()),
-                to: *to,
+                to: Some(*to),
                 gas: Some((*gas_limit).into()),
                 gas_price: Some((*gas_price).into()),
                 value: Some((*value).into()),
@@ -503,7 +810,7 @@ impl TransactionRequest {
             } => TransactionRequest::Eip1559 {
                 from,
                 chain_id: Some((*chain_id).into()),
-                to: *to,
+                to: Some(*to),
                 gas: Some((*gas_limit).into()),
                 max_fee_per_gas: Some((*max_fee_per_gas).into()),
                 max_priority_fee_per_gas: Some((*max_priority_fee_per_gas).into()),
@@ -520,6 +827,22 @@ impl TransactionRequest {
     }
 }
 
+/// Converts the destination of a transaction into the `to` of a clarity transaction, `None`
+/// creating a contract. clarity encodes the zero address as an empty destination so that is how
+/// creation is signed, which also means a transfer to the zero address can not be signed and is
+/// refused rather than silently turned into a contract creation
+pub(crate) fn clarity_destination(to: Option<Address>) -> Result<Address, Web3Error> {
+    match to {
+        Some(to) if to == zero_address() => Err(Web3Error::BadInput(
+            "Can not send a transaction to the zero address, it would be signed as a contract \
+             creation"
+                .to_string(),
+        )),
+        Some(to) => Ok(to),
+        None => Ok(zero_address()),
+    }
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct UnpaddedHex(pub Uint256);
 
@@ -544,17 +867,58 @@ impl From<u64> for UnpaddedHex {
     }
 }
 
+/// The receipt of a mined transaction as returned by eth_getTransactionReceipt
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReceipt {
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: Uint256,
+    #[serde(rename = "transactionIndex")]
+    pub transaction_index: Uint256,
+    #[serde(rename = "blockHash")]
+    pub block_hash: Uint256,
+    #[serde(rename = "blockNumber")]
+    pub block_number: Uint256,
+    pub from: Address,
+    /// None for contract creation
+    pub to: Option<Address>,
+    #[serde(rename = "cumulativeGasUsed")]
+    pub cumulative_gas_used: Uint256,
+    #[serde(rename = "gasUsed")]
+    pub gas_used: Uint256,
+    #[serde(rename = "effectiveGasPrice", default)]
+    pub effective_gas_price: Option<Uint256>,
+    /// The address of the created contract, only set for contract creation
+    #[serde(rename = "contractAddress", default)]
+    pub contract_address: Option<Address>,
+    pub logs: Vec<Log>,
+    #[serde(rename = "logsBloom")]
+    pub logs_bloom: Data,
+    /// 1 for success and 0 for failure, only present after Byzantium
+    #[serde(default)]
+    pub status: Option<Uint256>,
+    /// The post transaction state root, only present before Byzantium
+    #[serde(default)]
+    pub root: Option<Data>,
+    #[serde(rename = "type", default)]
+    pub transaction_type: Option<Uint256>,
+}
+
+impl TransactionReceipt {
+    /// Returns false if the transaction reverted, pre Byzantium receipts carry no
+    /// status and are assumed to have succeeded
+    pub fn is_success(&self) -> bool {
+        self.status != Some(0u8.into())
+    }
+}
+
 /// Ethereum block
 #[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct Block {
     // geth does not include the author in it's RPC response.
     pub author: Option<Address>,
     pub difficulty: Uint256,
-    #[serde(
-        rename = "extraData",
-        deserialize_with = "parse_possibly_empty_hex_val"
-    )]
-    pub extra_data: Uint256,
+    #[serde(rename = "extraData")]
+    pub extra_data: Data,
     #[serde(rename = "gasLimit")]
     pub gas_limit: Uint256,
     #[serde(rename = "gasUsed")]
@@ -563,10 +927,35 @@ pub struct Block {
     /// the london hardfork
     #[serde(rename = "baseFeePerGas")]
     pub base_fee_per_gas: Option<Uint256>,
+    /// this field will not exist until after
+    /// the shanghai hardfork
+    #[serde(rename = "withdrawalsRoot")]
+    pub withdrawals_root: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "blobGasUsed")]
+    pub blob_gas_used: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "excessBlobGas")]
+    pub excess_blob_gas: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "parentBeaconBlockRoot")]
+    pub parent_beacon_block_root: Option<Uint256>,
+    /// this field will not exist until after
+    /// the prague hardfork
+    #[serde(rename = "requestsHash")]
+    pub requests_hash: Option<Uint256>,
     pub hash: Uint256,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
+    /// not present on proof of authority chains such as xDai
+    #[serde(rename = "mixHash")]
+    pub mix_hash: Option<Uint256>,
+    /// not present on proof of authority chains such as xDai
+    pub nonce: Option<Uint256>,
     pub number: Uint256,
     #[serde(rename = "parentHash")]
     pub parent_hash: Uint256,
@@ -592,11 +981,8 @@ pub struct ConciseBlock {
     // geth does not include the author in it's RPC response.
     pub author: Option<Address>,
     pub difficulty: Uint256,
-    #[serde(
-        rename = "extraData",
-        deserialize_with = "parse_possibly_empty_hex_val"
-    )]
-    pub extra_data: Uint256,
+    #[serde(rename = "extraData")]
+    pub extra_data: Data,
     #[serde(rename = "gasLimit")]
     pub gas_limit: Uint256,
     #[serde(rename = "gasUsed")]
@@ -605,10 +991,33 @@ pub struct ConciseBlock {
     /// the london hardfork
     #[serde(rename = "baseFeePerGas")]
     pub base_fee_per_gas: Option<Uint256>,
+    /// this field will not exist until after
+    /// the shanghai hardfork
+    #[serde(rename = "withdrawalsRoot")]
+    pub withdrawals_root: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "blobGasUsed")]
+    pub blob_gas_used: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "excessBlobGas")]
+    pub excess_blob_gas: Option<Uint256>,
+    /// this field will not exist until after
+    /// the cancun hardfork
+    #[serde(rename = "parentBeaconBlockRoot")]
+    pub parent_beacon_block_root: Option<Uint256>,
+    /// this field will not exist until after
+    /// the prague hardfork
+    #[serde(rename = "requestsHash")]
+    pub requests_hash: Option<Uint256>,
     pub hash: Uint256,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
+    /// not present on proof of authority chains such as xDai
+    #[serde(rename = "mixHash")]
+    pub mix_hash: Option<Uint256>,
     pub number: Uint256,
     #[serde(rename = "parentHash")]
     pub parent_hash: Uint256,
@@ -633,6 +1042,67 @@ pub struct ConciseBlock {
     pub uncles: Vec<Uint256>,
 }
 
+/// A signed transaction ready for broadcast with `eth_sendRawTransaction`
+#[derive(Debug, Clone)]
+pub struct SignedTransaction {
+    pub transaction: Transaction,
+    /// the transaction hash, keccak256 of `raw`
+    pub hash: Uint256,
+    /// the network encoding of the transaction
+    pub raw: Vec<u8>,
+}
+
+impl SignedTransaction {
+    /// Decodes a raw signed transaction, such as one produced by a `Signer`
+    pub fn from_raw(raw: Vec<u8>) -> Result<SignedTransaction, Web3Error> {
+        let transaction = match clarity_decodable(&raw) {
+            Some(patched) => Transaction::decode_from_rlp(&patched)?,
+            None => Transaction::decode_from_rlp(&raw)?,
+        };
+        Ok(SignedTransaction {
+            transaction,
+            hash: Uint256::from_be_bytes(&keccak256(&raw)),
+            raw,
+        })
+    }
+}
+
+/// clarity fails to decode a y parity of 1, which typed transactions encode as the single byte
+/// 0x01, it only accepts a y parity given as a longer string starting with 1. Returns a copy of
+/// such a transaction with the y parity rewritten to 0x820100 so clarity can decode it, or None
+/// if `raw` needs no rewriting
+fn clarity_decodable(raw: &[u8]) -> Option<Vec<u8>> {
+    let (tx_type, list) = match raw.split_first() {
+        Some((tx_type @ (1 | 2), list)) => (*tx_type, list),
+        _ => return None,
+    };
+    let items = match unpack_rlp(list).ok()?.into_iter().next()? {
+        RlpToken::List(items) => items,
+        _ => return None,
+    };
+    let (r, s) = match items.as_slice() {
+        [.., RlpToken::SingleByte(1), r, s] => {
+            (r.get_byte_content().ok()?, s.get_byte_content().ok()?)
+        }
+        _ => return None,
+    };
+    let signature_len = 1 + encode_bytes(&r).len() + encode_bytes(&s).len();
+    let header_len = match list.first()? {
+        len_of_len @ 0xf8.. => 1 + (len_of_len - 0xf7) as usize,
+        _ => 1,
+    };
+    let payload = list.get(header_len..list.len().checked_sub(signature_len)?)?;
+    let signature = &list[list.len() - signature_len + 1..];
+
+    let mut patched = vec![tx_type];
+    patched.extend(encode_list(&[
+        payload.to_vec(),
+        vec![0x82, 0x01, 0x00],
+        signature.to_vec(),
+    ]));
+    Some(patched)
+}
+
 /// Used to configure send_transaction
 #[derive(Debug, Clone, PartialEq)]
 pub enum SendTxOption {
@@ -646,6 +1116,12 @@ pub enum SendTxOption {
     GasPrice(Uint256),
     GasPriceMultiplier(f32),
     NetworkId(u64),
+    /// Send a legacy (EIP-155) transaction, or an EIP-2930 transaction if an access
+    /// list is provided, even if the chain supports EIP-1559
+    Legacy,
+    /// Simulate the transaction with `eth_call` before broadcasting it, a revert is returned
+    /// as `Web3Error::TransactionReverted` and nothing is sent
+    Preflight,
 }
 
 fn parse_possibly_empty_hex_val<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
@@ -722,6 +1198,147 @@ mod tests {
         println!("{res:#?}");
     }
 
+    #[test]
+    fn decode_typed_transactions() {
+        let eip1559 = r#"{
+            "blockHash": "0x5bd5a5d8e8ec4b1a3c6fc2be6d7e5e2b4b1a6b3e4f5a6b7c8d9e0f1a2b3c4d5e",
+            "blockNumber": "0x12a3b4c",
+            "chainId": "0x1",
+            "from": "0x802275979b020f0ec871c5ec1db6e412b72ff20b",
+            "gas": "0x186a0",
+            "gasPrice": "0x3b9aca00",
+            "maxFeePerGas": "0x77359400",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "hash": "0xceb484eb92fd7ad626bc5aced6d669a693baf3d776b515a08d65fafca633a6a6",
+            "input": "0x",
+            "nonce": "0x5",
+            "to": "0xaf38668f4719ecf9452dc0300be3f6c83cbf3721",
+            "transactionIndex": "0x0",
+            "value": "0x0",
+            "type": "0x02",
+            "accessList": [
+                {
+                    "address": "0x89d24a6b4ccb1b6faa2625fe562bdd9a23260359",
+                    "storageKeys": [
+                        "0x0000000000000000000000000000000000000000000000000000000000000003"
+                    ]
+                }
+            ],
+            "v": "0x1",
+            "r": "0x3f25525c83f8260820b5bbfcb3849d83d3740c35ee23ee0d3edb110c845b4abd",
+            "s": "0x7f9eddf929ae5afc7325ee9377c776ddeebebe0701097336340aca8c32668579"
+        }"#;
+        let decoded: TransactionResponse = serde_json::from_str(eip1559).unwrap();
+        match decoded {
+            TransactionResponse::Eip1559 {
+                ref access_list, ..
+            } => {
+                assert_eq!(access_list.len(), 1);
+                assert_eq!(access_list[0].1, vec![3u8.into()]);
+            }
+            _ => panic!("Expected an EIP1559 transaction, got {decoded:?}"),
+        }
+        let reencoded = serde_json::to_string(&decoded).unwrap();
+        assert_eq!(
+            serde_json::from_str::<TransactionResponse>(&reencoded).unwrap(),
+            decoded
+        );
+
+        let eip4844 = r#"{
+            "blockHash": "0x5bd5a5d8e8ec4b1a3c6fc2be6d7e5e2b4b1a6b3e4f5a6b7c8d9e0f1a2b3c4d5e",
+            "blockNumber": "0x12a3b4c",
+            "chainId": "0x1",
+            "from": "0x802275979b020f0ec871c5ec1db6e412b72ff20b",
+            "gas": "0x5208",
+            "gasPrice": "0x3b9aca00",
+            "maxFeePerGas": "0x77359400",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "maxFeePerBlobGas": "0x3e8",
+            "hash": "0x0ae43e19354415ec2f659ba80f886ea3635b09a8c7e10a31a0a1172122e03565",
+            "input": "0x",
+            "nonce": "0x6",
+            "to": "0xaf38668f4719ecf9452dc0300be3f6c83cbf3721",
+            "transactionIndex": "0x1",
+            "value": "0x0",
+            "type": "0x3",
+            "accessList": [],
+            "blobVersionedHashes": [
+                "0x01a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8"
+            ],
+            "v": "0x0",
+            "yParity": "0x0",
+            "r": "0x508d29fd7556f086d59f891a252757ca3a43fc0f740920d0672cc62856cb8111",
+            "s": "0x1e9c8111bb048edfecff85331b67767b95e662a50a6a9a5911e6d472838b9cfe"
+        }"#;
+        let decoded: TransactionResponse = serde_json::from_str(eip4844).unwrap();
+        assert_eq!(decoded.get_type(), 3);
+        match decoded {
+            TransactionResponse::Eip4844 {
+                max_fee_per_blob_gas,
+                blob_versioned_hashes,
+                ..
+            } => {
+                assert_eq!(max_fee_per_blob_gas, 1000u16.into());
+                assert_eq!(blob_versioned_hashes.len(), 1);
+            }
+            _ => panic!("Expected an EIP4844 transaction"),
+        }
+
+        let eip7702 = r#"{
+            "blockHash": null,
+            "blockNumber": null,
+            "chainId": "0x1",
+            "from": "0x802275979b020f0ec871c5ec1db6e412b72ff20b",
+            "gas": "0x186a0",
+            "gasPrice": "0x77359400",
+            "maxFeePerGas": "0x77359400",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "hash": "0x59fc1481417a2efb26d344348865c4fdc363d42a789f7219824ad6fda5b4efd1",
+            "input": "0x",
+            "nonce": "0x7",
+            "to": "0x802275979b020f0ec871c5ec1db6e412b72ff20b",
+            "transactionIndex": null,
+            "value": "0x0",
+            "type": "0x4",
+            "accessList": [],
+            "authorizationList": [
+                {
+                    "chainId": "0x1",
+                    "address": "0x89d24a6b4ccb1b6faa2625fe562bdd9a23260359",
+                    "nonce": "0x8",
+                    "yParity": "0x1",
+                    "r": "0xdd99bf960fe094f7e9e1a4d54745a63fdd3f516ca9d370e1683b4034dce5c69b",
+                    "s": "0x662925efce6834b7bacefc3aaaab9d3814411b0d693c29c207e8260cbbbb9e43"
+                }
+            ],
+            "v": "0x1",
+            "r": "0x41b4e4350aa4656c3bb7194b22b90a62f8d91c09d043afb6909706baa531b86f",
+            "s": "0x4019af93845515b795cd432a35b730e4a1a62079afc5ecacf8dcfdddbda6da19"
+        }"#;
+        let decoded: TransactionResponse = serde_json::from_str(eip7702).unwrap();
+        assert_eq!(decoded.get_block_number(), None);
+        match decoded {
+            TransactionResponse::Eip7702 {
+                authorization_list, ..
+            } => {
+                assert_eq!(authorization_list.len(), 1);
+                assert_eq!(authorization_list[0].nonce, 8u8.into());
+            }
+            _ => panic!("Expected an EIP7702 transaction"),
+        }
+
+        // unknown transaction types, such as OP stack deposits, are kept as returned
+        let unknown = eip1559.replace("\"0x02\"", "\"0x7e\"");
+        let decoded: TransactionResponse = serde_json::from_str(&unknown).unwrap();
+        assert_eq!(decoded.get_type(), 0x7e);
+        assert_eq!(decoded.get_nonce(), 5u8.into());
+        assert_eq!(decoded.get_block_number(), Some(0x12a3b4cu32.into()));
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::from_str::<Value>(&unknown).unwrap()
+        );
+    }
+
     #[test]
     fn decode_block_concise() {
         let original = r#"
@@ -921,4 +1538,105 @@ mod tests {
 
         let _decoded: Block = serde_json::from_str(&file).unwrap();
     }
+
+    #[test]
+    fn decode_contract_creation_receipt() {
+        let receipt: TransactionReceipt = serde_json::from_str(
+            r#"{
+                "blockHash": "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd",
+                "blockNumber": "0xb443",
+                "contractAddress": "0x5fbdb2315678afecb367f032d93f642f64180aa3",
+                "cumulativeGasUsed": "0x7a0c2",
+                "effectiveGasPrice": "0x3b9aca00",
+                "from": "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+                "gasUsed": "0x7a0c2",
+                "logs": [],
+                "logsBloom": "0x00",
+                "status": "0x1",
+                "to": null,
+                "transactionHash": "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+                "transactionIndex": "0x0",
+                "type": "0x2"
+            }"#,
+        )
+        .unwrap();
+        assert!(receipt.is_success());
+        assert!(receipt.to.is_none());
+        assert_eq!(
+            receipt.contract_address,
+            Some(
+                "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+                    .parse()
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn decode_signed_transaction() {
+        use clarity::PrivateKey;
+
+        let key: PrivateKey = "0xfe1bfd3aa9a6b18ce38cbe07e1a8e9e84e6a0cb98e6e2b56d70b4ea5d3b9b7c4"
+            .parse()
+            .unwrap();
+        // both y parities, clarity can not decode a y parity of 1 by itself
+        let mut parities = Vec::new();
+        for value in 0u8..4 {
+            let transaction = Transaction::Eip1559 {
+                chain_id: 1u8.into(),
+                nonce: 7u8.into(),
+                max_priority_fee_per_gas: 1u8.into(),
+                max_fee_per_gas: 100u8.into(),
+                gas_limit: 21000u16.into(),
+                to: key.to_address(),
+                value: value.into(),
+                data: Vec::new(),
+                signature: None,
+                access_list: Vec::new(),
+            }
+            .sign(&key, None);
+            let raw = transaction.to_bytes();
+            parities.push(
+                transaction
+                    .get_signature()
+                    .unwrap()
+                    .get_signature_v()
+                    .unwrap(),
+            );
+
+            let signed = SignedTransaction::from_raw(raw.clone()).unwrap();
+            assert_eq!(signed.hash, Uint256::from_be_bytes(&keccak256(&raw)));
+            assert_eq!(signed.raw, raw);
+            assert_eq!(signed.transaction.to_bytes(), raw);
+            assert!(signed.transaction.is_valid());
+            assert_eq!(signed.transaction.sender().unwrap(), key.to_address());
+        }
+        assert!(parities.contains(&27) && parities.contains(&28));
+        assert!(SignedTransaction::from_raw(vec![0x02, 0xc0]).is_err());
+    }
+
+    #[test]
+    fn test_clarity_destination() {
+        let to: Address = "0xaf38668f4719ecf9452dc0300be3f6c83cbf3721"
+            .parse()
+            .unwrap();
+        assert_eq!(clarity_destination(Some(to)).unwrap(), to);
+        assert_eq!(clarity_destination(None).unwrap(), zero_address());
+        assert!(clarity_destination(Some(zero_address())).is_err());
+
+        // requests keep the destination they are given, creation has to be set explicitly
+        let transaction = Transaction::Legacy {
+            nonce: 0u8.into(),
+            gas_price: 1u8.into(),
+            gas_limit: 21000u16.into(),
+            to: zero_address(),
+            value: 0u8.into(),
+            data: Vec::new(),
+            signature: None,
+        };
+        let mut request = TransactionRequest::from_transaction(&transaction, to);
+        assert_eq!(request.get_to(), Some(zero_address()));
+        request.set_to(None);
+        assert_eq!(request.get_to(), None);
+    }
 }
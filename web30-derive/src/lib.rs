@@ -0,0 +1,135 @@
+//! Derive macros for web30
+//!
+//! `#[derive(ContractEvent)]` implements `web30::ContractEvent` for a struct with named
+//! fields. The event is given by a human readable signature with `indexed` markers and each
+//! field is filled from the event parameter of the same name, or the one named by
+//! `#[event(rename = "...")]`. Field types must implement `web30::abi::FromToken`. The
+//! signature is parsed when the macro expands, a malformed signature or a field without a
+//! matching parameter is a compile error.
+//!
+//! ```rust,ignore
+//! #[derive(ContractEvent)]
+//! #[event("Transfer(address indexed from, address indexed to, uint256 value)")]
+//! struct Transfer {
+//!     from: Address,
+//!     to: Address,
+//!     #[event(rename = "value")]
+//!     amount: Uint256,
+//! }
+//! ```
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+mod signature;
+
+#[proc_macro_derive(ContractEvent, attributes(event))]
+pub fn derive_contract_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_contract_event(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_contract_event(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let signature: LitStr = match input.attrs.iter().find(|a| a.path().is_ident("event")) {
+        Some(attr) => attr.parse_args()?,
+        None => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "ContractEvent requires #[event(\"Name(type indexed name, ...)\")]",
+            ))
+        }
+    };
+    let event = signature::parse_event(&signature.value())
+        .map_err(|e| Error::new_spanned(&signature, e))?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "ContractEvent can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "ContractEvent can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut assignments = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let mut param = ident.to_string();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("event")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    param = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename = \"...\"`"))
+                }
+            })?;
+        }
+        if !event.params.iter().any(|p| p.name == param) {
+            return Err(Error::new_spanned(
+                field,
+                format!("Event {} has no parameter {param}", event.name),
+            ));
+        }
+        assignments.push(quote! {
+            #ident: ::web30::contract::event_param(&params, #param)?
+        });
+    }
+
+    // the event is built directly from the signature parsed above instead of parsed again
+    let event_name = &event.name;
+    let anonymous = event.anonymous;
+    let params = event.params.iter().map(|p| {
+        let (name, kind, indexed) = (&p.name, &p.kind, p.indexed);
+        quote! {
+            ::web30::contract::AbiParam {
+                name: ::std::string::String::from(#name),
+                kind: ::std::string::String::from(#kind),
+                components: ::std::vec::Vec::new(),
+                indexed: #indexed,
+            }
+        }
+    });
+    let abi_event = quote! {
+        ::web30::contract::AbiEvent {
+            name: ::std::string::String::from(#event_name),
+            inputs: ::std::vec![#(#params),*],
+            anonymous: #anonymous,
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::web30::ContractEvent for #name #ty_generics #where_clause {
+            fn from_event(
+                input: &::web30::EventData,
+            ) -> ::std::result::Result<Self, ::web30::jsonrpc::error::Web3Error> {
+                let event = #abi_event;
+                let params = event.decode_tron_params(input)?;
+                ::std::result::Result::Ok(Self { #(#assignments,)* })
+            }
+
+            fn from_log(
+                input: &::web30::types::Log,
+            ) -> ::std::result::Result<Self, ::web30::jsonrpc::error::Web3Error> {
+                let event = #abi_event;
+                let params = event.decode_params(input)?;
+                ::std::result::Result::Ok(Self { #(#assignments,)* })
+            }
+        }
+    })
+}
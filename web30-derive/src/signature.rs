@@ -0,0 +1,189 @@
+//! Expansion time parsing of human readable event signatures. This follows the parser of
+//! `web30::contract::AbiEvent` so that a signature accepted here is accepted at runtime, and a
+//! bad signature is a compile error instead of an error on every decode.
+
+/// A parsed event signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub anonymous: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub kind: String,
+    pub indexed: bool,
+}
+
+/// Parses a signature such as `Transfer(address indexed from, address indexed to, uint256 value)`
+/// or `Foo(uint256 a) anonymous`, unnamed parameters are named by their position
+pub fn parse_event(s: &str) -> Result<Event, String> {
+    let invalid = || format!("Invalid event signature {s}");
+    let s = s.trim();
+    let (s, trailing_anonymous) = match s.strip_suffix("anonymous") {
+        Some(rest) if rest.trim_end().ends_with(')') => (rest.trim_end(), true),
+        _ => (s, false),
+    };
+    let (name, params) = s
+        .strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .ok_or_else(invalid)?;
+    let (name, anonymous) = match name.trim().strip_suffix(" anonymous") {
+        Some(name) => (name.trim(), true),
+        None => (name.trim(), trailing_anonymous),
+    };
+    if name.is_empty() || !is_identifier(name) {
+        return Err(invalid());
+    }
+
+    let mut event = Event {
+        name: name.to_string(),
+        params: Vec::new(),
+        anonymous,
+    };
+    for (i, param) in split_list(params)
+        .ok_or_else(invalid)?
+        .into_iter()
+        .enumerate()
+    {
+        let mut words: Vec<&str> = param.split_whitespace().collect();
+        let indexed = words.contains(&"indexed");
+        words.retain(|w| *w != "indexed");
+        // a trailing identifier after a complete type is the parameter name
+        let name = match words.split_last() {
+            Some((last, rest))
+                if !rest.is_empty() && is_identifier(last) && is_type(&rest.join(" ")) =>
+            {
+                words.pop().unwrap().to_string()
+            }
+            _ => i.to_string(),
+        };
+        let kind = words.join(" ");
+        if !is_type(&kind) {
+            return Err(format!("Invalid ABI type {kind} in event signature {s}"));
+        }
+        if event.params.iter().any(|p| p.name == name) {
+            return Err(format!("Duplicate parameter {name} in event signature {s}"));
+        }
+        event.params.push(Param {
+            name,
+            kind,
+            indexed,
+        });
+    }
+
+    let indexed = event.params.iter().filter(|p| p.indexed).count();
+    let max_indexed = if anonymous { 4 } else { 3 };
+    if indexed > max_indexed {
+        return Err(format!(
+            "Event {name} has {indexed} indexed parameters, at most {max_indexed} are allowed"
+        ));
+    }
+    Ok(event)
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Returns true if `s` is a type name as accepted by `web30::abi::ParamType`
+fn is_type(s: &str) -> bool {
+    let s = s.trim();
+    if let Some(inner) = s.strip_suffix(']') {
+        return match inner.rsplit_once('[') {
+            Some((inner, len)) => {
+                (len.is_empty() || len.parse::<usize>().is_ok()) && is_type(inner)
+            }
+            None => false,
+        };
+    }
+    if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        return match split_list(inner) {
+            Some(types) => types.into_iter().all(is_type),
+            None => false,
+        };
+    }
+    let bits = |size: &str| match size {
+        "" => true,
+        size => {
+            matches!(size.parse::<usize>(), Ok(bits) if bits > 0 && bits <= 256 && bits % 8 == 0)
+        }
+    };
+    match s {
+        "address" | "bool" | "bytes" | "string" => true,
+        _ => {
+            if let Some(size) = s.strip_prefix("uint") {
+                bits(size)
+            } else if let Some(size) = s.strip_prefix("int") {
+                bits(size)
+            } else if let Some(len) = s.strip_prefix("bytes") {
+                matches!(len.parse::<usize>(), Ok(1..=32))
+            } else {
+                false
+            }
+        }
+    }
+}
+
+/// Splits a comma separated list at the commas outside of parentheses, None if the
+/// parentheses are unbalanced
+fn split_list(list: &str) -> Option<Vec<&str>> {
+    if list.trim().is_empty() {
+        return Some(Vec::new());
+    }
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                items.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    items.push(list[start..].trim());
+    Some(items)
+}
+
+#[test]
+fn test_parse_event() {
+    let event =
+        parse_event("Transfer(address indexed from, address indexed to, uint256 value)").unwrap();
+    assert_eq!(event.name, "Transfer");
+    assert!(!event.anonymous);
+    let params: Vec<(&str, &str, bool)> = event
+        .params
+        .iter()
+        .map(|p| (p.name.as_str(), p.kind.as_str(), p.indexed))
+        .collect();
+    assert_eq!(
+        params,
+        [
+            ("from", "address", true),
+            ("to", "address", true),
+            ("value", "uint256", false)
+        ]
+    );
+
+    let event = parse_event("Swap(address indexed, (address, uint24)[] route, int256)").unwrap();
+    let names: Vec<&str> = event.params.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["0", "route", "2"]);
+    assert!(parse_event("Foo(uint256 a) anonymous").unwrap().anonymous);
+
+    assert!(parse_event("Transfer(address indexed from").is_err());
+    assert!(parse_event("Transfer(foo bar)").is_err());
+    assert!(parse_event("Transfer(uint7 a)").is_err());
+    assert!(parse_event("Transfer(uint256 a, uint256 a)").is_err());
+    assert!(
+        parse_event("Foo(uint8 indexed, uint8 indexed, uint8 indexed, uint8 indexed)").is_err()
+    );
+}